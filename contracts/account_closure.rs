@@ -0,0 +1,491 @@
+use near_sdk::json_types::{U64, U128};
+use near_sdk::{
+    AccountId, Gas, NearToken, Promise, PromiseError, PromiseOrValue, PromiseResult, env,
+    ext_contract, near, serde_json,
+};
+
+use crate::events::TradingAccountEvent;
+use crate::withdrawals::{FT_TRANSFER_GAS, INTENTS_WITHDRAW_GAS, ext_ft, ext_intents};
+use crate::{
+    NEAR_INTENTS_ADDRESS, TradingAccountContract, TradingAccountContractExt, WRAP_NEAR_ADDRESS,
+};
+
+const BALANCE_QUERY_GAS: Gas = Gas::from_tgas(10); // Gas for ft_balance_of / mt_batch_balance_of
+const CLOSURE_CALLBACK_GAS: Gas = Gas::from_tgas(20); // Gas reserved for each closure callback
+const GAS_FOR_CLOSE_TRADING_ACCOUNT: Gas = Gas::from_tgas(250);
+const MAX_SWEEPS_PER_STAGE: usize = 3; // Maximum number of balances swept by one run of a stage
+// Gas for a callback sweeping up to MAX_SWEEPS_PER_STAGE balances, then awaiting the result.
+// Withdrawals from intents.near are the most expensive sweeps.
+const SWEEP_CALLBACK_GAS: Gas = Gas::from_gas(
+    2 * CLOSURE_CALLBACK_GAS.as_gas() + MAX_SWEEPS_PER_STAGE as u64 * INTENTS_WITHDRAW_GAS.as_gas(),
+);
+// Gas needed to run any stage: its balance queries plus the callback sweeping them
+const CLOSURE_STAGE_GAS: Gas = Gas::from_gas(
+    MAX_SWEEPS_PER_STAGE as u64 * BALANCE_QUERY_GAS.as_gas() + SWEEP_CALLBACK_GAS.as_gas(),
+);
+const CLOSURE_STAGE_TIMEOUT_NS: u64 = 60 * 60 * 1_000_000_000; // 1 hour
+
+/// The steps of closing a trading account, in the order they run
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccountClosureStage {
+    /// Withdraw the known token balances held on intents.near to the owner
+    SweepIntents,
+    /// Unwrap the wNEAR held on wrap.near, so it leaves with the account's NEAR balance
+    UnwrapNear,
+    /// Transfer the balances of the other allowed tokens, held on their own contracts, to the owner
+    SweepTokens,
+    /// Delete the account with the owner as beneficiary
+    DeleteAccount,
+}
+
+/// An account closure which has been started and not yet completed
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug)]
+pub struct AccountClosure {
+    /// The next stage to run. Only advances once a stage has succeeded.
+    pub stage: AccountClosureStage,
+    /// Block timestamp (in nanoseconds) at which the closure was started
+    pub started_at: U64,
+    /// Whether a stage is currently awaiting its callback
+    pub in_progress: bool,
+    /// Block timestamp (in nanoseconds) at which the current stage was last run. A stage still
+    /// in progress an hour later never reported back and may be resumed or cancelled.
+    pub stage_started_at: U64,
+    /// Allowed tokens whose own balances remain to be checked by the SweepTokens stage
+    pub tokens_to_sweep: Vec<AccountId>,
+    /// Tokens named by the owner when starting the closure, swept like the allowed tokens
+    pub extra_tokens: Vec<AccountId>,
+}
+
+#[allow(dead_code)]
+#[ext_contract(ext_wrap)]
+pub trait WrappedNear {
+    fn near_withdraw(&mut self, amount: U128);
+}
+
+#[near]
+impl TradingAccountContract {
+    /// Pause signing, sweep the intents.near, wrap.near and allowed token balances to the owner
+    /// and delete the account with the owner as beneficiary. Balances of any other token the
+    /// account holds are lost with it unless listed in `extra_tokens` when starting the closure.
+    /// Each run sweeps at most MAX_SWEEPS_PER_STAGE balances. Calling it again after a stage
+    /// failed or paused resumes the closure from that stage.
+    #[payable]
+    pub fn close_trading_account(
+        &mut self,
+        extra_tokens: Option<Vec<AccountId>>,
+    ) -> PromiseOrValue<()> {
+        self.assert_owner_with_one_yocto();
+        assert!(
+            env::prepaid_gas() >= GAS_FOR_CLOSE_TRADING_ACCOUNT,
+            "Not enough gas attached. Please attach at least {} TGas.",
+            GAS_FOR_CLOSE_TRADING_ACCOUNT.as_tgas()
+        );
+
+        let closure = match self.account_closure.clone() {
+            Some(closure) => {
+                assert!(
+                    extra_tokens.is_none(),
+                    "Extra tokens can only be given when the closure starts. Cancel it and start again to sweep more tokens"
+                );
+                closure
+            }
+            None => AccountClosure {
+                stage: AccountClosureStage::SweepIntents,
+                started_at: U64(env::block_timestamp()),
+                in_progress: false,
+                stage_started_at: U64(env::block_timestamp()),
+                tokens_to_sweep: vec![],
+                extra_tokens: extra_tokens.unwrap_or_default(),
+            },
+        };
+        assert_stage_not_running(&closure);
+        let stage = closure.stage;
+        self.account_closure = Some(closure);
+
+        self.run_closure_stage(stage)
+    }
+
+    /// Abandon a failed, paused or stalled account closure and resume signing
+    pub fn cancel_account_closure(&mut self) {
+        self.assert_owner();
+        let closure = self
+            .account_closure
+            .take()
+            .unwrap_or_else(|| env::panic_str("The trading account is not being closed"));
+        assert_stage_not_running(&closure);
+        env::log_str("Account closure cancelled, signing resumed");
+    }
+
+    pub fn get_account_closure(&self) -> Option<AccountClosure> {
+        self.account_closure.clone()
+    }
+
+    #[private] // Only callable by the contract itself
+    pub fn on_closure_intents_balances(
+        &mut self,
+        #[callback_result] balances: Result<Vec<U128>, PromiseError>,
+        tokens: Vec<AccountId>,
+    ) -> PromiseOrValue<()> {
+        let stage = AccountClosureStage::SweepIntents;
+        let balances = match balances {
+            Ok(balances) => balances,
+            Err(e) => {
+                return self.fail_closure_stage(
+                    stage,
+                    format!("Failed to fetch intents.near balances: {:?}", e),
+                );
+            }
+        };
+
+        let owner_id = self.owner_id.clone();
+        let mut balances: Vec<_> = tokens
+            .into_iter()
+            .zip(balances)
+            .filter(|(_, balance)| balance.0 > 0)
+            .collect();
+        // The rest is swept by the next run of the stage
+        let remaining = balances.len().saturating_sub(MAX_SWEEPS_PER_STAGE) as u32;
+        balances.truncate(MAX_SWEEPS_PER_STAGE);
+        let sweep = balances
+            .into_iter()
+            .map(|(token, balance)| {
+                ext_intents::ext(NEAR_INTENTS_ADDRESS.clone())
+                    .with_attached_deposit(NearToken::from_yoctonear(1))
+                    .with_static_gas(INTENTS_WITHDRAW_GAS)
+                    .ft_withdraw(token, owner_id.clone(), balance, None)
+            })
+            .reduce(|sweep, withdrawal| sweep.and(withdrawal));
+
+        match sweep {
+            Some(sweep) => {
+                PromiseOrValue::Promise(sweep.then(self.closure_stage_callback(stage, remaining)))
+            }
+            None => self.complete_closure_stage(stage),
+        }
+    }
+
+    #[private] // Only callable by the contract itself
+    pub fn on_closure_wrap_balance(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+    ) -> PromiseOrValue<()> {
+        let stage = AccountClosureStage::UnwrapNear;
+        match balance {
+            Ok(balance) if balance.0 > 0 => PromiseOrValue::Promise(
+                ext_wrap::ext(WRAP_NEAR_ADDRESS.clone())
+                    .with_attached_deposit(NearToken::from_yoctonear(1))
+                    .with_static_gas(FT_TRANSFER_GAS)
+                    .near_withdraw(balance)
+                    .then(self.closure_stage_callback(stage, 0)),
+            ),
+            Ok(_) => self.complete_closure_stage(stage),
+            Err(e) => self.fail_closure_stage(
+                stage,
+                format!("Failed to fetch the wrap.near balance: {:?}", e),
+            ),
+        }
+    }
+
+    #[private] // Only callable by the contract itself
+    pub fn on_closure_token_balances(&mut self, tokens: Vec<AccountId>) -> PromiseOrValue<()> {
+        let stage = AccountClosureStage::SweepTokens;
+        let mut balances = Vec::with_capacity(tokens.len());
+        for (i, token) in tokens.iter().enumerate() {
+            let balance = match env::promise_result(i as u64) {
+                PromiseResult::Successful(bytes) => serde_json::from_slice::<U128>(&bytes).ok(),
+                PromiseResult::Failed => None,
+            };
+            match balance {
+                Some(balance) => balances.push(balance),
+                None => {
+                    return self.fail_closure_stage(
+                        stage,
+                        format!("Failed to fetch the {} balance", token),
+                    );
+                }
+            }
+        }
+
+        let owner_id = self.owner_id.clone();
+        let sweep = tokens
+            .iter()
+            .zip(balances)
+            .filter(|(_, balance)| balance.0 > 0)
+            .map(|(token, balance)| {
+                ext_ft::ext(token.clone())
+                    .with_attached_deposit(NearToken::from_yoctonear(1))
+                    .with_static_gas(FT_TRANSFER_GAS)
+                    .ft_transfer(owner_id.clone(), balance, None)
+            })
+            .reduce(|sweep, transfer| sweep.and(transfer));
+
+        match sweep {
+            Some(sweep) => PromiseOrValue::Promise(
+                sweep.then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(CLOSURE_CALLBACK_GAS)
+                        .on_closure_tokens_swept(tokens),
+                ),
+            ),
+            None => self.finish_token_sweep(&tokens),
+        }
+    }
+
+    #[private] // Only callable by the contract itself
+    pub fn on_closure_tokens_swept(&mut self, tokens: Vec<AccountId>) -> PromiseOrValue<()> {
+        match count_failed_promises() {
+            0 => self.finish_token_sweep(&tokens),
+            failed => self.fail_closure_stage(
+                AccountClosureStage::SweepTokens,
+                format!(
+                    "{} transfer(s) failed, the funds remain on the account",
+                    failed
+                ),
+            ),
+        }
+    }
+
+    #[private] // Only callable by the contract itself
+    pub fn on_closure_stage_swept(
+        &mut self,
+        stage: AccountClosureStage,
+        remaining: u32,
+    ) -> PromiseOrValue<()> {
+        let failed = count_failed_promises();
+        if failed > 0 {
+            return self.fail_closure_stage(
+                stage,
+                format!(
+                    "{} withdrawal(s) failed, the funds remain on the account",
+                    failed
+                ),
+            );
+        }
+        if remaining > 0 {
+            return self.continue_closure(stage);
+        }
+        self.complete_closure_stage(stage)
+    }
+
+    /// Runs in the same receipt as the account deletion, so the event is only kept if the
+    /// account is actually deleted
+    #[private] // Only callable by the contract itself
+    pub fn on_account_closing(&mut self) {
+        TradingAccountEvent::AccountClosed {
+            beneficiary_id: self.owner_id.clone(),
+        }
+        .emit();
+    }
+
+    /// Only runs if deleting the account failed, a deleted account receives no callbacks
+    #[private] // Only callable by the contract itself
+    pub fn on_closure_account_deleted(&mut self) -> PromiseOrValue<()> {
+        self.fail_closure_stage(
+            AccountClosureStage::DeleteAccount,
+            "Deleting the account failed".to_string(),
+        )
+    }
+}
+
+impl TradingAccountContract {
+    /// Signing is paused from the moment a closure starts until it is cancelled
+    pub(crate) fn is_closing(&self) -> bool {
+        self.account_closure.is_some()
+    }
+
+    fn run_closure_stage(&mut self, stage: AccountClosureStage) -> PromiseOrValue<()> {
+        if let Some(closure) = self.account_closure.as_mut() {
+            closure.in_progress = true;
+            closure.stage_started_at = U64(env::block_timestamp());
+        }
+        let account_id = env::current_account_id();
+        let promise = match stage {
+            AccountClosureStage::SweepIntents => {
+                let mut tokens = self.tokens_to_close();
+                if !tokens.contains(&WRAP_NEAR_ADDRESS) {
+                    tokens.push(WRAP_NEAR_ADDRESS.clone());
+                }
+                let token_ids = tokens
+                    .iter()
+                    .map(|token| format!("nep141:{}", token))
+                    .collect();
+
+                ext_intents::ext(NEAR_INTENTS_ADDRESS.clone())
+                    .with_static_gas(BALANCE_QUERY_GAS)
+                    .mt_batch_balance_of(account_id, token_ids)
+                    .then(
+                        Self::ext(env::current_account_id())
+                            .with_static_gas(SWEEP_CALLBACK_GAS)
+                            .on_closure_intents_balances(tokens),
+                    )
+            }
+            AccountClosureStage::UnwrapNear => ext_ft::ext(WRAP_NEAR_ADDRESS.clone())
+                .with_static_gas(BALANCE_QUERY_GAS)
+                .ft_balance_of(account_id)
+                .then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(CLOSURE_CALLBACK_GAS)
+                        .on_closure_wrap_balance(),
+                ),
+            AccountClosureStage::SweepTokens => {
+                let tokens: Vec<AccountId> = self
+                    .account_closure
+                    .as_ref()
+                    .map(|closure| closure.tokens_to_sweep.clone())
+                    .unwrap_or_default()
+                    .into_iter()
+                    .take(MAX_SWEEPS_PER_STAGE)
+                    .collect();
+                let queries = tokens
+                    .iter()
+                    .map(|token| {
+                        ext_ft::ext(token.clone())
+                            .with_static_gas(BALANCE_QUERY_GAS)
+                            .ft_balance_of(account_id.clone())
+                    })
+                    .reduce(|queries, query| queries.and(query));
+                let Some(queries) = queries else {
+                    return self.complete_closure_stage(stage);
+                };
+                queries.then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(SWEEP_CALLBACK_GAS)
+                        .on_closure_token_balances(tokens),
+                )
+            }
+            AccountClosureStage::DeleteAccount => Self::ext(account_id)
+                .with_static_gas(CLOSURE_CALLBACK_GAS)
+                .on_account_closing()
+                .delete_account(self.owner_id.clone())
+                .then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(CLOSURE_CALLBACK_GAS)
+                        .on_closure_account_deleted(),
+                ),
+        };
+        PromiseOrValue::Promise(promise)
+    }
+
+    /// The allowed tokens and those named when starting the closure
+    fn tokens_to_close(&self) -> Vec<AccountId> {
+        let mut tokens = self.allowed_tokens.to_vec();
+        let extra_tokens = self
+            .account_closure
+            .as_ref()
+            .map(|closure| closure.extra_tokens.clone())
+            .unwrap_or_default();
+        for token in extra_tokens {
+            if !tokens.contains(&token) {
+                tokens.push(token);
+            }
+        }
+        tokens
+    }
+
+    fn closure_stage_callback(&self, stage: AccountClosureStage, remaining: u32) -> Promise {
+        Self::ext(env::current_account_id())
+            .with_static_gas(CLOSURE_CALLBACK_GAS)
+            .on_closure_stage_swept(stage, remaining)
+    }
+
+    /// Drop a swept batch from the tokens left to sweep, then sweep the next batch or move on
+    fn finish_token_sweep(&mut self, tokens: &[AccountId]) -> PromiseOrValue<()> {
+        let stage = AccountClosureStage::SweepTokens;
+        let remaining = match self.account_closure.as_mut() {
+            Some(closure) => {
+                closure
+                    .tokens_to_sweep
+                    .retain(|token| !tokens.contains(token));
+                closure.tokens_to_sweep.len()
+            }
+            None => 0,
+        };
+        if remaining > 0 {
+            return self.continue_closure(stage);
+        }
+        self.complete_closure_stage(stage)
+    }
+
+    fn complete_closure_stage(&mut self, stage: AccountClosureStage) -> PromiseOrValue<()> {
+        TradingAccountEvent::AccountClosureProgress {
+            stage,
+            completed: true,
+            reason: None,
+        }
+        .emit();
+
+        let next = match stage {
+            AccountClosureStage::SweepIntents => AccountClosureStage::UnwrapNear,
+            AccountClosureStage::UnwrapNear => AccountClosureStage::SweepTokens,
+            AccountClosureStage::SweepTokens | AccountClosureStage::DeleteAccount => {
+                AccountClosureStage::DeleteAccount
+            }
+        };
+        if next == AccountClosureStage::SweepTokens {
+            // wNEAR was unwrapped by the previous stage
+            let tokens = self
+                .tokens_to_close()
+                .into_iter()
+                .filter(|token| *token != *WRAP_NEAR_ADDRESS)
+                .collect();
+            if let Some(closure) = self.account_closure.as_mut() {
+                closure.tokens_to_sweep = tokens;
+            }
+        }
+        self.continue_closure(next)
+    }
+
+    /// Run the given stage if enough gas is left for it, or pause the closure until the owner
+    /// calls `close_trading_account` again
+    fn continue_closure(&mut self, stage: AccountClosureStage) -> PromiseOrValue<()> {
+        if let Some(closure) = self.account_closure.as_mut() {
+            closure.stage = stage;
+        }
+        if env::prepaid_gas().saturating_sub(env::used_gas()) < CLOSURE_STAGE_GAS {
+            return self.fail_closure_stage(
+                stage,
+                "Not enough gas left to run the stage, call close_trading_account again to continue"
+                    .to_string(),
+            );
+        }
+        self.run_closure_stage(stage)
+    }
+
+    fn fail_closure_stage(
+        &mut self,
+        stage: AccountClosureStage,
+        reason: String,
+    ) -> PromiseOrValue<()> {
+        if let Some(closure) = self.account_closure.as_mut() {
+            closure.in_progress = false;
+        }
+        TradingAccountEvent::AccountClosureProgress {
+            stage,
+            completed: false,
+            reason: Some(reason),
+        }
+        .emit();
+        PromiseOrValue::Value(())
+    }
+}
+
+/// A stage still awaiting its callback may not be run again or cancelled, unless it never
+/// reported back within CLOSURE_STAGE_TIMEOUT_NS
+fn assert_stage_not_running(closure: &AccountClosure) {
+    let stalled = env::block_timestamp().saturating_sub(closure.stage_started_at.0)
+        >= CLOSURE_STAGE_TIMEOUT_NS;
+    assert!(
+        !closure.in_progress || stalled,
+        "Stage {:?} of the account closure is still in progress",
+        closure.stage
+    );
+}
+
+/// The number of promises awaited by the current callback which did not succeed
+fn count_failed_promises() -> usize {
+    (0..env::promise_results_count())
+        .filter(|i| !matches!(env::promise_result(*i), PromiseResult::Successful(_)))
+        .count()
+}
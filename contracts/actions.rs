@@ -1,4 +1,9 @@
-use near_sdk::{AccountId, Gas, NearToken, near};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Deserialize;
+use near_sdk::{AccountId, Gas, NearToken, env, near};
+use omni_transaction::near::types::Action as OmniAction;
+
+use crate::{ActionString, NEAR_INTENTS_ADDRESS, intents};
 
 #[near(serializers = [json, borsh])]
 #[derive(Clone)]
@@ -12,6 +17,8 @@ pub struct NearAction {
 pub enum ActionValidationError {
     ContractNotAllowed(String),
     MethodNotAllowed(String),
+    GasLimitExceeded(String),
+    DepositNotAllowed(String),
 }
 
 const ALLOWED_CONTRACTS: &[&str] = &["wrap.near", "intents.near", "wrap.testnet"];
@@ -19,11 +26,63 @@ const ALLOWED_METHODS: &[&str] = &[
     "add_public_key",
     "ft_transfer_call",
     "near_deposit",
+    "near_withdraw",
+    "storage_deposit",
     "mt_transfer_call",
     "mt_transfer",
+    "mt_batch_transfer_call",
+    "mt_batch_transfer",
     "ft_withdraw",
 ];
 
+/// The deposit an allowed method must be called with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DepositLimit {
+    Exact(NearToken),
+    Max(NearToken),
+    /// The deposit is the amount being moved, which the balance limits bound instead
+    Any,
+}
+
+/// The gas and deposit bounds of an allowed (contract, method) pair
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MethodLimits {
+    pub max_gas: Gas,
+    pub deposit: DepositLimit,
+}
+
+const ONE_YOCTO: NearToken = NearToken::from_yoctonear(1);
+
+const fn limits(max_tgas: u64, deposit: DepositLimit) -> MethodLimits {
+    MethodLimits {
+        max_gas: Gas::from_tgas(max_tgas),
+        deposit,
+    }
+}
+
+/// The gas and deposit bounds of `method_name` on `contract_id`, None if the method may not be
+/// called on that contract
+pub fn method_limits(contract_id: &str, method_name: &str) -> Option<MethodLimits> {
+    use DepositLimit::*;
+
+    // Registering storage is bounded the same way on every contract it is allowed on
+    if method_name == "storage_deposit" {
+        return Some(limits(50, Max(MAX_STORAGE_DEPOSIT)));
+    }
+    match (contract_id, method_name) {
+        ("wrap.near" | "wrap.testnet", "near_deposit") => Some(limits(50, Any)),
+        ("wrap.near" | "wrap.testnet", "near_withdraw") => Some(limits(50, Exact(ONE_YOCTO))),
+        ("wrap.near" | "wrap.testnet", "ft_transfer_call") => Some(limits(150, Exact(ONE_YOCTO))),
+        ("intents.near", "add_public_key") => Some(limits(50, Exact(ONE_YOCTO))),
+        ("intents.near", "ft_withdraw") => Some(limits(150, Exact(ONE_YOCTO))),
+        ("intents.near", "mt_transfer" | "mt_batch_transfer") => Some(limits(50, Exact(ONE_YOCTO))),
+        ("intents.near", "mt_transfer_call" | "mt_batch_transfer_call") => {
+            Some(limits(150, Exact(ONE_YOCTO)))
+        }
+        _ => None,
+    }
+}
+
 impl NearAction {
     pub fn is_allowed(&self) -> Result<(), ActionValidationError> {
         // Check if contract address is allowed
@@ -42,7 +101,570 @@ impl NearAction {
                     method, ALLOWED_METHODS
                 )));
             }
+            self.check_limits()?;
         }
         Ok(())
     }
+
+    /// Ensure the call stays within the gas and deposit bounds of its method
+    pub fn check_limits(&self) -> Result<(), ActionValidationError> {
+        let Some(method) = &self.method_name else {
+            return Ok(());
+        };
+        let limits = method_limits(self.contract_id.as_str(), method).ok_or_else(|| {
+            ActionValidationError::MethodNotAllowed(format!(
+                "Method {} cannot be called on {}",
+                method, self.contract_id
+            ))
+        })?;
+
+        if self.gas_attached > limits.max_gas {
+            return Err(ActionValidationError::GasLimitExceeded(format!(
+                "{} attaches {} gas, above the maximum of {}",
+                method, self.gas_attached, limits.max_gas
+            )));
+        }
+        match limits.deposit {
+            DepositLimit::Exact(deposit) if self.deposit_attached != deposit => {
+                Err(ActionValidationError::DepositNotAllowed(format!(
+                    "{} requires a deposit of exactly {}, got {}",
+                    method,
+                    deposit.exact_amount_display(),
+                    self.deposit_attached.exact_amount_display()
+                )))
+            }
+            DepositLimit::Max(max) if self.deposit_attached > max => {
+                Err(ActionValidationError::DepositNotAllowed(format!(
+                    "{} deposit of {} exceeds the maximum of {}",
+                    method,
+                    self.deposit_attached.exact_amount_display(),
+                    max.exact_amount_display()
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// An amount of a NEP-141 token that a signed action would move out of the trading account
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenMovement {
+    pub token: AccountId,
+    pub amount: u128,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct FtTransferArgs {
+    amount: U128,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct NearWithdrawArgs {
+    amount: U128,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct StorageDepositArgs {
+    account_id: Option<AccountId>,
+}
+
+// Storage deposits are refunded above the registration cost, a NEP-141 registration costs 0.00125 NEAR
+pub const MAX_STORAGE_DEPOSIT: NearToken = NearToken::from_millinear(10);
+
+/// Validate the arguments of methods which need more than the contract and method allowlists
+pub fn validate_method_args(method_name: &str, args: &serde_json::Value) -> Result<(), String> {
+    match method_name {
+        "near_withdraw" => {
+            let args: NearWithdrawArgs = serde_json::from_value(args.clone())
+                .map_err(|e| format!("Invalid {} args: {}", method_name, e))?;
+            if args.amount.0 == 0 {
+                return Err("near_withdraw amount must be greater than zero".to_string());
+            }
+        }
+        "storage_deposit" => {
+            let args: StorageDepositArgs = serde_json::from_value(args.clone())
+                .map_err(|e| format!("Invalid {} args: {}", method_name, e))?;
+            if let Some(account_id) = args.account_id {
+                if account_id != env::current_account_id() {
+                    return Err(format!(
+                        "storage_deposit may only register the trading account, not {}",
+                        account_id
+                    ));
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct FtTransferCallArgs {
+    receiver_id: AccountId,
+    #[serde(default)]
+    msg: String,
+}
+
+/// The JSON form of an intents.near deposit message, which credits `receiver_id` on the intents ledger
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct IntentsDepositMsg {
+    receiver_id: AccountId,
+}
+
+/// Swap instructions in the Ref Finance style, `{"actions": [{"token_in": .., "token_out": ..}]}`
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct SwapActionsMsg {
+    actions: Vec<SwapAction>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct SwapAction {
+    token_in: AccountId,
+    token_out: AccountId,
+}
+
+/// An intents.near deposit message which executes signed intents once the tokens arrive
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct ExecuteIntentsMsg {
+    execute_intents: Vec<SignedIntentPayload>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct SignedIntentPayload {
+    payload: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct FtWithdrawArgs {
+    token: AccountId,
+    amount: U128,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct FtWithdrawTokenArgs {
+    token: AccountId,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct MtTransferArgs {
+    receiver_id: AccountId,
+    token_id: String,
+    amount: U128,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct MtBatchTransferArgs {
+    receiver_id: AccountId,
+    token_ids: Vec<String>,
+    amounts: Vec<U128>,
+}
+
+/// The token ids and amounts a NEP-245 transfer moves to `receiver_id`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MtTransfer {
+    pub receiver_id: AccountId,
+    pub transfers: Vec<(String, U128)>,
+}
+
+/// Decode the NEP-245 transfer made by `mt_transfer`, `mt_transfer_call` or their batch variants
+pub fn mt_transfer(
+    method_name: &str,
+    args: &serde_json::Value,
+) -> Result<Option<MtTransfer>, String> {
+    let invalid_args = |e: serde_json::Error| format!("Invalid {} args: {}", method_name, e);
+    match method_name {
+        "mt_transfer" | "mt_transfer_call" => {
+            let args: MtTransferArgs =
+                serde_json::from_value(args.clone()).map_err(invalid_args)?;
+            Ok(Some(MtTransfer {
+                receiver_id: args.receiver_id,
+                transfers: vec![(args.token_id, args.amount)],
+            }))
+        }
+        "mt_batch_transfer" | "mt_batch_transfer_call" => {
+            let args: MtBatchTransferArgs =
+                serde_json::from_value(args.clone()).map_err(invalid_args)?;
+            if args.token_ids.len() != args.amounts.len() {
+                return Err(format!(
+                    "Invalid {} args: {} token ids but {} amounts",
+                    method_name,
+                    args.token_ids.len(),
+                    args.amounts.len()
+                ));
+            }
+            Ok(Some(MtTransfer {
+                receiver_id: args.receiver_id,
+                transfers: args.token_ids.into_iter().zip(args.amounts).collect(),
+            }))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// The NEP-141 token wrapped by a NEP-245 token id of intents.near, e.g. wrap.near for "nep141:wrap.near"
+fn mt_token(token_id: &str) -> Result<AccountId, String> {
+    token_id
+        .strip_prefix("nep141:")
+        .and_then(|token| token.parse().ok())
+        .ok_or(format!(
+            "Token {} is not allowed. Only nep141 token ids can be transferred",
+            token_id
+        ))
+}
+
+/// The NEP-141 tokens an `ft_transfer_call`, `ft_withdraw`, `near_withdraw` or NEP-245 transfer
+/// sent to `contract_id` would move
+pub fn transferred_tokens(
+    contract_id: &AccountId,
+    method_name: &str,
+    args: &serde_json::Value,
+) -> Result<Vec<AccountId>, String> {
+    match method_name {
+        // The receiver of an ft call is the token contract itself
+        "ft_transfer_call" | "near_withdraw" => Ok(vec![contract_id.clone()]),
+        "ft_withdraw" => {
+            let args: FtWithdrawTokenArgs = serde_json::from_value(args.clone())
+                .map_err(|e| format!("Invalid {} args: {}", method_name, e))?;
+            Ok(vec![args.token])
+        }
+        _ => match mt_transfer(method_name, args)? {
+            Some(transfer) => transfer
+                .transfers
+                .iter()
+                .map(|(token_id, _)| mt_token(token_id))
+                .collect(),
+            None => Ok(vec![]),
+        },
+    }
+}
+
+// Memo prefix used by the intents.near bridge tokens to withdraw to an address on a foreign chain
+const FOREIGN_WITHDRAWAL_MEMO_PREFIX: &str = "WITHDRAW_TO:";
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct FtWithdrawDestinationArgs {
+    token: AccountId,
+    receiver_id: AccountId,
+    memo: Option<String>,
+}
+
+/// The destination of funds withdrawn by an `ft_withdraw` call, either a NEAR account id or a
+/// foreign chain address. The memo only names the destination when the tokens go to the bridge
+/// token itself, any other receiver is credited regardless of the memo.
+pub fn withdrawal_destination(
+    method_name: &str,
+    args: &serde_json::Value,
+) -> Result<Option<String>, String> {
+    if method_name != "ft_withdraw" {
+        return Ok(None);
+    }
+
+    let args: FtWithdrawDestinationArgs = serde_json::from_value(args.clone())
+        .map_err(|e| format!("Invalid {} args: {}", method_name, e))?;
+    let foreign_address = args
+        .memo
+        .as_deref()
+        .filter(|_| args.receiver_id == args.token)
+        .and_then(|memo| memo.strip_prefix(FOREIGN_WITHDRAWAL_MEMO_PREFIX));
+    Ok(Some(match foreign_address {
+        Some(address) => address.to_string(),
+        None => args.receiver_id.to_string(),
+    }))
+}
+
+/// Determine which tokens, and how much of each, the given actions would move when sent to `contract_id`
+pub fn token_movements(
+    contract_id: &AccountId,
+    actions: &[ActionString],
+) -> Result<Vec<TokenMovement>, String> {
+    let mut movements = Vec::new();
+    for action in actions {
+        let ActionString::FunctionCall {
+            method_name,
+            args,
+            deposit,
+            ..
+        } = action
+        else {
+            continue;
+        };
+
+        match method_name.as_str() {
+            // The receiver of an ft call is the token contract itself, as is the unwrapped token
+            "ft_transfer_call" | "near_withdraw" => {
+                let args: FtTransferArgs = serde_json::from_value(args.clone())
+                    .map_err(|e| format!("Invalid {} args: {}", method_name, e))?;
+                movements.push(TokenMovement {
+                    token: contract_id.clone(),
+                    amount: args.amount.0,
+                });
+            }
+            "ft_withdraw" => {
+                let args: FtWithdrawArgs = serde_json::from_value(args.clone())
+                    .map_err(|e| format!("Invalid {} args: {}", method_name, e))?;
+                movements.push(TokenMovement {
+                    token: args.token,
+                    amount: args.amount.0,
+                });
+            }
+            // Wrapping NEAR is counted as moving the wrapped token
+            "near_deposit" => {
+                let amount = deposit.parse().map_err(|_| "Invalid deposit format")?;
+                movements.push(TokenMovement {
+                    token: contract_id.clone(),
+                    amount,
+                });
+            }
+            _ => {
+                if let Some(transfer) = mt_transfer(method_name, args)? {
+                    for (token_id, amount) in transfer.transfers {
+                        movements.push(TokenMovement {
+                            token: mt_token(&token_id)?,
+                            amount: amount.0,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(movements)
+}
+
+/// The (from, to) token pairs an `ft_transfer_call` would swap, as encoded in its `msg`. Messages
+/// which are not JSON, or do not describe a swap, yield no pairs.
+pub fn swap_pairs(
+    method_name: &str,
+    args: &serde_json::Value,
+) -> Result<Vec<(AccountId, AccountId)>, String> {
+    if method_name != "ft_transfer_call" {
+        return Ok(vec![]);
+    }
+    let Ok(FtTransferCallArgs { msg, .. }) = serde_json::from_value(args.clone()) else {
+        return Ok(vec![]);
+    };
+    let Ok(msg) = serde_json::from_str::<serde_json::Value>(&msg) else {
+        return Ok(vec![]);
+    };
+
+    if let Ok(SwapActionsMsg { actions }) = serde_json::from_value(msg.clone()) {
+        return Ok(actions
+            .into_iter()
+            .map(|action| (action.token_in, action.token_out))
+            .collect());
+    }
+    if let Ok(ExecuteIntentsMsg { execute_intents }) = serde_json::from_value(msg) {
+        let mut pairs = Vec::new();
+        for signed in execute_intents {
+            // NEP-413 payloads wrap the message, other standards sign it as is
+            let message = match &signed.payload {
+                serde_json::Value::String(message) => Some(message.as_str()),
+                payload => payload.get("message").and_then(|message| message.as_str()),
+            };
+            if let Some(message) = message {
+                pairs.extend(intents::token_diff_pairs(message)?);
+            }
+        }
+        return Ok(pairs);
+    }
+    Ok(vec![])
+}
+
+/// The receiver of the tokens sent by an `ft_transfer_call`
+pub fn ft_transfer_receiver(
+    method_name: &str,
+    args: &serde_json::Value,
+) -> Result<Option<AccountId>, String> {
+    if method_name != "ft_transfer_call" {
+        return Ok(None);
+    }
+    let args: FtTransferCallArgs = serde_json::from_value(args.clone())
+        .map_err(|e| format!("Invalid {} args: {}", method_name, e))?;
+    Ok(Some(args.receiver_id))
+}
+
+/// The account an `ft_transfer_call` to intents.near credits on the intents ledger. intents.near
+/// credits the sender for an empty `msg`, the account id for a plain `msg` and the `receiver_id`
+/// of a JSON `msg`. Returns None for calls which do not deposit into intents.near.
+pub fn intents_deposit_receiver(
+    method_name: &str,
+    args: &serde_json::Value,
+) -> Result<Option<AccountId>, String> {
+    if method_name != "ft_transfer_call" {
+        return Ok(None);
+    }
+    let args: FtTransferCallArgs = serde_json::from_value(args.clone())
+        .map_err(|e| format!("Invalid {} args: {}", method_name, e))?;
+    if args.receiver_id != *NEAR_INTENTS_ADDRESS {
+        return Ok(None);
+    }
+
+    let msg = args.msg.trim();
+    if msg.is_empty() {
+        return Ok(Some(env::current_account_id()));
+    }
+    let receiver_id = if msg.starts_with('{') {
+        serde_json::from_str::<IntentsDepositMsg>(msg)
+            .map_err(|e| format!("Invalid intents.near deposit message: {}", e))?
+            .receiver_id
+    } else {
+        msg.parse()
+            .map_err(|_| format!("Invalid intents.near deposit message: {}", msg))?
+    };
+    Ok(Some(receiver_id))
+}
+
+/// Where the balance a signed action spends from is held
+#[near(serializers = [json, borsh])]
+#[derive(Debug, Clone, PartialEq)]
+pub enum BalanceSource {
+    /// The trading account's own NEAR balance
+    Near,
+    /// The trading account's balance on a NEP-141 token contract
+    Ft { token: AccountId },
+    /// The trading account's NEP-245 balance on intents.near, e.g. "nep141:wrap.near"
+    Intents { token_id: String },
+}
+
+impl BalanceSource {
+    /// A stable identifier of the balance, used to track spending per balance
+    pub fn key(&self) -> String {
+        match self {
+            BalanceSource::Near => "near".to_string(),
+            BalanceSource::Ft { token } => token.to_string(),
+            BalanceSource::Intents { token_id } => format!("intents.near:{}", token_id),
+        }
+    }
+}
+
+/// An amount a signed action would spend from one of the trading account's balances
+#[near(serializers = [json, borsh])]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceSpend {
+    pub source: BalanceSource,
+    pub amount: U128,
+}
+
+/// Determine which of the trading account's balances, and how much of each, the given actions
+/// would spend when sent to `contract_id`. Spends of the same balance are summed up, with the NEAR
+/// attached to the actions as a single `BalanceSource::Near` spend.
+pub fn balance_spends(
+    contract_id: &AccountId,
+    actions: &[ActionString],
+) -> Result<Vec<BalanceSpend>, String> {
+    let mut near: u128 = 0;
+    let mut spends = Vec::new();
+    for action in actions {
+        let (method_name, args, deposit) = match action {
+            ActionString::FunctionCall {
+                method_name,
+                args,
+                deposit,
+                ..
+            } => (Some(method_name), Some(args), deposit),
+            ActionString::Transfer { deposit } => (None, None, deposit),
+        };
+        let deposit: u128 = deposit.parse().map_err(|_| "Invalid deposit format")?;
+        near = near.checked_add(deposit).ok_or("Deposit amount overflow")?;
+
+        let (Some(method_name), Some(args)) = (method_name, args) else {
+            continue;
+        };
+        match method_name.as_str() {
+            "ft_transfer_call" | "near_withdraw" => {
+                let args: FtTransferArgs = serde_json::from_value(args.clone())
+                    .map_err(|e| format!("Invalid {} args: {}", method_name, e))?;
+                add_balance_spend(
+                    &mut spends,
+                    BalanceSource::Ft {
+                        token: contract_id.clone(),
+                    },
+                    args.amount,
+                )?;
+            }
+            "ft_withdraw" if contract_id == &*NEAR_INTENTS_ADDRESS => {
+                let args: FtWithdrawArgs = serde_json::from_value(args.clone())
+                    .map_err(|e| format!("Invalid {} args: {}", method_name, e))?;
+                add_balance_spend(
+                    &mut spends,
+                    BalanceSource::Intents {
+                        token_id: format!("nep141:{}", args.token),
+                    },
+                    args.amount,
+                )?;
+            }
+            _ if contract_id == &*NEAR_INTENTS_ADDRESS => {
+                if let Some(transfer) = mt_transfer(method_name, args)? {
+                    for (token_id, amount) in transfer.transfers {
+                        add_balance_spend(
+                            &mut spends,
+                            BalanceSource::Intents { token_id },
+                            amount,
+                        )?;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if near > 0 {
+        spends.insert(
+            0,
+            BalanceSpend {
+                source: BalanceSource::Near,
+                amount: U128(near),
+            },
+        );
+    }
+    Ok(spends)
+}
+
+/// Add `amount` to the spend of `source`, so each balance is checked once against its total
+fn add_balance_spend(
+    spends: &mut Vec<BalanceSpend>,
+    source: BalanceSource,
+    amount: U128,
+) -> Result<(), String> {
+    let key = source.key();
+    match spends.iter_mut().find(|spend| spend.source.key() == key) {
+        Some(spend) => {
+            spend.amount = U128(
+                spend
+                    .amount
+                    .0
+                    .checked_add(amount.0)
+                    .ok_or("Token amount overflow")?,
+            );
+        }
+        None => spends.push(BalanceSpend { source, amount }),
+    }
+    Ok(())
+}
+
+/// The total NEAR attached to the given actions, which leaves the trading account once signed
+pub fn total_deposit(actions: &[OmniAction]) -> Result<u128, String> {
+    actions.iter().try_fold(0u128, |total, action| {
+        let deposit = match action {
+            OmniAction::FunctionCall(call) => call.deposit.0,
+            OmniAction::Transfer(transfer) => transfer.deposit.0,
+            _ => 0,
+        };
+        total
+            .checked_add(deposit)
+            .ok_or("Deposit amount overflow".to_string())
+    })
 }
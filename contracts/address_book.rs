@@ -0,0 +1,137 @@
+use near_sdk::json_types::U64;
+use near_sdk::{AccountId, env, near};
+
+use crate::policy_changes::PolicyChange;
+use crate::{TradingAccountContract, TradingAccountContractExt};
+
+const MAX_ADDRESS_BOOK_ENTRIES: u64 = 20; // Maximum number of withdrawal addresses per trading account
+pub const DEFAULT_ADDRESS_BOOK_DELAY_NS: u64 = 24 * 60 * 60 * 1_000_000_000; // 24 hours
+
+/// A destination the agent may withdraw funds to, once `usable_from` has passed
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug)]
+pub struct AddressBookEntry {
+    /// A NEAR account id, or an address on the given foreign chain
+    pub address: String,
+    /// The foreign chain of the address, e.g. "eth" or "btc". None for NEAR accounts.
+    pub chain: Option<String>,
+    /// Block timestamp (in nanoseconds) at which the entry was added
+    pub added_at: U64,
+    /// Block timestamp (in nanoseconds) from which agents may withdraw to this address
+    pub usable_from: U64,
+}
+
+#[near]
+impl TradingAccountContract {
+    /// Add a withdrawal destination. It only becomes usable after the address book delay has passed.
+    pub fn add_address_book_entry(&mut self, address: String, chain: Option<String>) {
+        self.assert_owner();
+
+        assert!(
+            self.address_book.len() < MAX_ADDRESS_BOOK_ENTRIES,
+            "Maximum number of address book entries reached:({}). One must be removed before adding another.",
+            MAX_ADDRESS_BOOK_ENTRIES
+        );
+        assert!(
+            self.address_book.get(&address).is_none(),
+            "{} is already in the address book",
+            address
+        );
+        if chain.is_none() {
+            assert!(
+                address.parse::<AccountId>().is_ok(),
+                "{} is not a valid NEAR account id",
+                address
+            );
+        }
+
+        let now = env::block_timestamp();
+        let entry = AddressBookEntry {
+            address: address.clone(),
+            chain,
+            added_at: U64(now),
+            usable_from: U64(now.saturating_add(self.address_book_delay_ns)),
+        };
+        self.address_book.insert(&address, &entry);
+
+        env::log_str(&format!(
+            "Address {} added to the address book, usable from {}",
+            address, entry.usable_from.0
+        ));
+    }
+
+    /// Removing an entry only tightens the policy, so guardians may do so as well
+    pub fn remove_address_book_entry(&mut self, address: String) {
+        self.assert_owner_or_guardian();
+        self.address_book.remove(&address);
+    }
+
+    pub fn get_address_book(&self) -> Vec<AddressBookEntry> {
+        self.address_book.values().collect()
+    }
+
+    /// Set how long (in nanoseconds) new address book entries must wait before they can be used.
+    /// Lowering the delay is subject to the policy change delay.
+    pub fn set_address_book_delay(&mut self, delay_ns: U64) {
+        self.assert_owner();
+        if delay_ns.0 >= self.address_book_delay_ns {
+            self.address_book_delay_ns = delay_ns.0;
+        } else {
+            self.apply_or_schedule(PolicyChange::SetAddressBookDelay { delay_ns });
+        }
+    }
+
+    pub fn get_address_book_delay(&self) -> U64 {
+        U64(self.address_book_delay_ns)
+    }
+}
+
+impl TradingAccountContract {
+    /// Ensure funds withdrawn to `destination` stay on the trading account or reach an active address book entry
+    pub(crate) fn check_withdrawal_destination(&self, destination: &str) -> Result<(), String> {
+        if destination == env::current_account_id().as_str() {
+            return Ok(());
+        }
+        self.active_address_book_entry("Withdrawal destination", destination)
+            .map(|_| ())
+    }
+
+    /// Ensure funds credited on intents.near, by a deposit or a multi-token transfer, or sent
+    /// straight to another account, go to the trading account or to a NEAR account in the
+    /// address book
+    pub(crate) fn check_intents_receiver(
+        &self,
+        role: &str,
+        receiver_id: &AccountId,
+    ) -> Result<(), String> {
+        if receiver_id == &env::current_account_id() {
+            return Ok(());
+        }
+        let entry = self.active_address_book_entry(role, receiver_id.as_str())?;
+        if let Some(chain) = entry.chain {
+            return Err(format!(
+                "{} {} is a {} address, not a NEAR account",
+                role, receiver_id, chain
+            ));
+        }
+        Ok(())
+    }
+
+    fn active_address_book_entry(
+        &self,
+        role: &str,
+        address: &str,
+    ) -> Result<AddressBookEntry, String> {
+        let entry = self
+            .address_book
+            .get(&address.to_string())
+            .ok_or(format!("{} {} is not in the address book", role, address))?;
+        if env::block_timestamp() < entry.usable_from.0 {
+            return Err(format!(
+                "{} {} cannot be used until {}",
+                role, address, entry.usable_from.0
+            ));
+        }
+        Ok(entry)
+    }
+}
@@ -3,13 +3,14 @@
 use actions::NearAction;
 use near_gas::NearGas;
 use near_sdk::base64;
-use near_sdk::collections::UnorderedSet;
+use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet};
 
 use near_sdk::ext_contract;
-use near_sdk::json_types::{Base58CryptoHash, U64};
+use near_sdk::json_types::{Base58CryptoHash, U64, U128};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
-    AccountId, Gas, NearToken, PanicOnDefault, Promise, PromiseError, PublicKey, env, near,
+    AccountId, Gas, NearToken, PanicOnDefault, Promise, PromiseError, PromiseOrValue, PublicKey,
+    env, near,
 };
 
 use omni_transaction::TransactionBuilder;
@@ -26,17 +27,59 @@ use omni_transaction::{
 
 use once_cell::sync::Lazy;
 static NEAR_INTENTS_ADDRESS: Lazy<AccountId> = Lazy::new(|| "intents.near".parse().unwrap());
-
-use crate::actions::ActionValidationError;
+static WRAP_NEAR_ADDRESS: Lazy<AccountId> = Lazy::new(|| "wrap.near".parse().unwrap());
+
+pub use crate::account_closure::{AccountClosure, AccountClosureStage};
+use crate::actions::{ActionValidationError, BalanceSpend};
+pub use crate::address_book::AddressBookEntry;
+pub use crate::balance_limits::{BalanceLimits, CountedSpend, DailySpend};
+pub use crate::circuit_breaker::{CircuitBreaker, RejectionCount};
+pub use crate::dca::DcaSchedule;
+pub use crate::events::TradingAccountEvent;
+pub use crate::goals::Goal;
+pub use crate::guardians::OwnerRecovery;
+pub use crate::intents::SwapQuote;
 pub use crate::models::*;
+pub use crate::policy_changes::{PendingPolicyChange, PolicyChange};
+pub use crate::policy_rules::{
+    ApprovalRequest, PolicyRule, PolicyRuleEntry, RuleCondition, RuleOperator, RuleOutcome,
+};
+pub use crate::price_oracle::{AssetOptionalPrice, Price, PriceData};
+use crate::price_oracle::{PRICE_CHECK_CALLBACK_GAS, PRICE_ORACLE_GAS};
+pub use crate::roles::{Role, RoleAssignment};
 pub use crate::serializer::SafeU128;
+use crate::signature_requests::RequestReservation;
+pub use crate::signature_requests::{
+    PendingSignature, SignatureRequest, SignatureRequestStatus, SignedPayload,
+};
+pub use crate::token_pairs::TokenPair;
+pub use crate::trading_schedule::{TradingWindow, Weekday};
 
+mod account_closure;
 mod actions;
+mod address_book;
+mod balance_limits;
+mod balance_reserve;
+mod circuit_breaker;
+mod dca;
+mod events;
+mod goals;
+mod guardians;
 mod integration_tests;
+mod intents;
 mod models;
+mod ownership;
+mod policy_changes;
+mod policy_rules;
+mod price_oracle;
+mod roles;
 mod serializer;
+mod signature_requests;
+mod token_pairs;
+mod trading_schedule;
 mod unit_tests;
 mod utils;
+mod withdrawals;
 
 // Constants
 const GAS_FOR_REQUEST_SIGNATURE: Gas = Gas::from_tgas(100);
@@ -44,6 +87,7 @@ const BASE_GAS: Gas = Gas::from_tgas(10); // Base gas for contract execution
 const CALLBACK_GAS: Gas = Gas::from_tgas(10); // Gas reserved for callback
 const NEAR_MPC_DOMAIN_ID: u32 = 0;
 const MAX_AUTHORIZED_USERS: u64 = 10; // Maximum number of authorized users per trading account
+const MAX_ALLOWED_TOKENS: u64 = 20; // Maximum number of NEP-141 tokens agents may move
 
 #[near(contract_state)]
 #[derive(PanicOnDefault)]
@@ -51,6 +95,41 @@ pub struct TradingAccountContract {
     owner_id: AccountId,
     authorized_users: UnorderedSet<AccountId>,
     signer_id: AccountId,
+    signature_requests: LookupMap<u64, SignatureRequest>,
+    next_signature_request_id: u64,
+    oldest_signature_request_id: u64,
+    goals: UnorderedMap<u64, Goal>,
+    next_goal_id: u64,
+    price_oracle_id: Option<AccountId>,
+    allowed_tokens: UnorderedSet<AccountId>,
+    address_book: UnorderedMap<String, AddressBookEntry>,
+    address_book_delay_ns: u64,
+    guardians: UnorderedSet<AccountId>,
+    pending_policy_changes: UnorderedMap<u64, PendingPolicyChange>,
+    next_policy_change_id: u64,
+    policy_change_delay_ns: u64,
+    recovery_threshold: u32,
+    recovery_delay_ns: u64,
+    owner_recovery: Option<OwnerRecovery>,
+    proposed_owner_id: Option<AccountId>,
+    min_balance_reserve: NearToken,
+    account_closure: Option<AccountClosure>,
+    pending_deposits: u128,
+    balance_limits: BalanceLimits,
+    daily_spend: LookupMap<String, DailySpend>,
+    balance_preflight_enabled: bool,
+    allowed_pairs: UnorderedSet<TokenPair>,
+    policy_rules: Vec<PolicyRuleEntry>,
+    next_policy_rule_id: u64,
+    approval_requests: LookupMap<u64, ApprovalRequest>,
+    roles: UnorderedMap<AccountId, Role>,
+    pending_authorizations: UnorderedSet<AccountId>,
+    circuit_breaker: CircuitBreaker,
+    agent_rejections: LookupMap<AccountId, RejectionCount>,
+    suspended_agents: UnorderedSet<AccountId>,
+    agent_schedules: LookupMap<AccountId, Vec<TradingWindow>>,
+    dca_schedules: UnorderedMap<u64, DcaSchedule>,
+    next_dca_schedule_id: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -85,14 +164,66 @@ impl TradingAccountContract {
             owner_id,
             authorized_users: UnorderedSet::new(b"a"),
             signer_id,
+            signature_requests: LookupMap::new(b"r"),
+            next_signature_request_id: 0,
+            oldest_signature_request_id: 0,
+            goals: UnorderedMap::new(b"g"),
+            next_goal_id: 0,
+            price_oracle_id: None,
+            allowed_tokens: UnorderedSet::new(b"t"),
+            address_book: UnorderedMap::new(b"w"),
+            address_book_delay_ns: address_book::DEFAULT_ADDRESS_BOOK_DELAY_NS,
+            guardians: UnorderedSet::new(b"d"),
+            pending_policy_changes: UnorderedMap::new(b"p"),
+            next_policy_change_id: 0,
+            policy_change_delay_ns: 0,
+            recovery_threshold: 0,
+            recovery_delay_ns: guardians::DEFAULT_RECOVERY_DELAY_NS,
+            owner_recovery: None,
+            proposed_owner_id: None,
+            min_balance_reserve: NearToken::from_yoctonear(0),
+            account_closure: None,
+            pending_deposits: 0,
+            balance_limits: BalanceLimits::default(),
+            daily_spend: LookupMap::new(b"s"),
+            balance_preflight_enabled: false,
+            allowed_pairs: UnorderedSet::new(b"q"),
+            policy_rules: Vec::new(),
+            next_policy_rule_id: 0,
+            approval_requests: LookupMap::new(b"v"),
+            roles: UnorderedMap::new(b"o"),
+            pending_authorizations: UnorderedSet::new(b"k"),
+            circuit_breaker: CircuitBreaker::default(),
+            agent_rejections: LookupMap::new(b"j"),
+            suspended_agents: UnorderedSet::new(b"u"),
+            agent_schedules: LookupMap::new(b"h"),
+            dca_schedules: UnorderedMap::new(b"c"),
+            next_dca_schedule_id: 0,
         }
     }
 
-    // Owner methods for managing authorized users
+    // Owner and manager methods for managing authorized users. The agent only becomes authorized
+    // once it calls `accept_authorization` from its own account.
     pub fn add_authorized_user(&mut self, account_id: AccountId) {
-        self.assert_owner();
+        self.assert_owner_or_manager();
+        self.apply_or_schedule(PolicyChange::AddAuthorizedUser { account_id });
+    }
 
-        // Check maximum limit before adding
+    pub fn remove_authorized_user(&mut self, account_id: AccountId) {
+        self.assert_owner_or_manager();
+        self.authorized_users.remove(&account_id);
+        self.pending_authorizations.remove(&account_id);
+        self.clear_agent_rejections(&account_id);
+    }
+
+    /// Complete an authorization offered by `add_authorized_user`
+    pub fn accept_authorization(&mut self) {
+        let account_id = env::predecessor_account_id();
+        assert!(
+            self.pending_authorizations.remove(&account_id),
+            "No authorization has been offered to {}",
+            account_id
+        );
         assert!(
             self.authorized_users.len() < MAX_AUTHORIZED_USERS,
             "Maximum number of authorized users reached:({}). One must be removed before adding another.",
@@ -100,11 +231,22 @@ impl TradingAccountContract {
         );
 
         self.authorized_users.insert(&account_id);
+        TradingAccountEvent::AgentAuthorized { account_id }.emit();
     }
 
-    pub fn remove_authorized_user(&mut self, account_id: AccountId) {
-        self.assert_owner();
-        self.authorized_users.remove(&account_id);
+    /// Drop the caller's own authorization, or decline one that was offered
+    pub fn renounce_authorization(&mut self) {
+        let account_id = env::predecessor_account_id();
+        let authorized = self.authorized_users.remove(&account_id);
+        let offered = self.pending_authorizations.remove(&account_id);
+        assert!(authorized || offered, "{} is not authorized", account_id);
+        self.clear_agent_rejections(&account_id);
+
+        TradingAccountEvent::AgentRenounced { account_id }.emit();
+    }
+
+    pub fn get_pending_authorizations(&self) -> Vec<AccountId> {
+        self.pending_authorizations.to_vec()
     }
 
     pub fn is_authorized(&self, account_id: AccountId) -> bool {
@@ -115,11 +257,56 @@ impl TradingAccountContract {
         self.authorized_users.to_vec()
     }
 
+    // Owner methods for managing the NEP-141 tokens agents may move
+    pub fn add_allowed_token(&mut self, token_id: AccountId) {
+        self.assert_owner();
+        self.apply_or_schedule(PolicyChange::AddAllowedToken { token_id });
+    }
+
+    pub fn remove_allowed_token(&mut self, token_id: AccountId) {
+        self.assert_owner_or_manager();
+        self.allowed_tokens.remove(&token_id);
+    }
+
+    pub fn get_allowed_tokens(&self) -> Vec<AccountId> {
+        self.allowed_tokens.to_vec()
+    }
+
     pub fn get_owner_id(&self) -> AccountId {
         self.owner_id.clone()
     }
 
     // Helper methods
+    /// Offer an authorization, which the agent completes with `accept_authorization`
+    pub(crate) fn insert_authorized_user(&mut self, account_id: AccountId) {
+        // Check maximum limit before adding
+        assert!(
+            self.authorized_users.len() < MAX_AUTHORIZED_USERS
+                && self.pending_authorizations.len() < MAX_AUTHORIZED_USERS,
+            "Maximum number of authorized users reached:({}). One must be removed before adding another.",
+            MAX_AUTHORIZED_USERS
+        );
+        if self.authorized_users.contains(&account_id) {
+            return;
+        }
+
+        self.pending_authorizations.insert(&account_id);
+        env::log_str(&format!(
+            "Authorization offered to {}, pending its acceptance",
+            account_id
+        ));
+    }
+
+    pub(crate) fn insert_allowed_token(&mut self, token_id: AccountId) {
+        assert!(
+            self.allowed_tokens.len() < MAX_ALLOWED_TOKENS,
+            "Maximum number of allowed tokens reached:({}). One must be removed before adding another.",
+            MAX_ALLOWED_TOKENS
+        );
+
+        self.allowed_tokens.insert(&token_id);
+    }
+
     fn assert_owner(&self) {
         assert_eq!(
             env::predecessor_account_id(),
@@ -174,10 +361,63 @@ impl TradingAccountContract {
                         gas_attached: NearGas::from_gas(gas_u64.0),
                         deposit_attached: deposit_near,
                     };
-                    near_action.is_allowed().map_err(|e| match e {
+                    // Storage may also be registered on the tokens the owner allowed
+                    let token_registration = method_name == "storage_deposit"
+                        && self.allowed_tokens.contains(contract_id);
+                    let allowed = if token_registration {
+                        near_action.check_limits()
+                    } else {
+                        near_action.is_allowed()
+                    };
+                    allowed.map_err(|e| match e {
                         ActionValidationError::ContractNotAllowed(msg) => msg,
                         ActionValidationError::MethodNotAllowed(msg) => msg,
+                        ActionValidationError::GasLimitExceeded(msg) => msg,
+                        ActionValidationError::DepositNotAllowed(msg) => msg,
                     })?;
+                    actions::validate_method_args(&method_name, &args)?;
+
+                    // Tokens moved out of the account must be on the owner's allowlist
+                    for token in actions::transferred_tokens(contract_id, &method_name, &args)? {
+                        if !self.allowed_tokens.contains(&token) {
+                            return Err(format!(
+                                "Token {} is not allowed. Allowed tokens: {:?}",
+                                token,
+                                self.allowed_tokens.to_vec()
+                            ));
+                        }
+                    }
+
+                    // Tokens may only be sent to intents.near, whose deposits are checked below, or
+                    // to a NEAR account in the address book
+                    if let Some(receiver_id) = actions::ft_transfer_receiver(&method_name, &args)? {
+                        if receiver_id != *NEAR_INTENTS_ADDRESS {
+                            self.check_intents_receiver("Token receiver", &receiver_id)?;
+                        }
+                    }
+
+                    // Deposits into intents.near must be credited to the trading account or the address book
+                    if let Some(receiver_id) =
+                        actions::intents_deposit_receiver(&method_name, &args)?
+                    {
+                        self.check_intents_receiver("Deposit receiver", &receiver_id)?;
+                    }
+
+                    // So must multi-token transfers on intents.near
+                    if let Some(transfer) = actions::mt_transfer(&method_name, &args)? {
+                        self.check_intents_receiver("Transfer receiver", &transfer.receiver_id)?;
+                    }
+
+                    // Swaps encoded in the message must be between allowed token pairs
+                    for (from, to) in actions::swap_pairs(&method_name, &args)? {
+                        self.check_swap_pair(&from, &to)?;
+                    }
+
+                    // Withdrawals must target the address book
+                    if let Some(destination) = actions::withdrawal_destination(&method_name, &args)?
+                    {
+                        self.check_withdrawal_destination(&destination)?;
+                    }
 
                     // Convert args to bytes
                     let args_bytes = serde_json::to_vec(&args)
@@ -213,7 +453,16 @@ impl TradingAccountContract {
         domain_id: Option<u32>,
     ) -> serde_json::Value {
         let hashed_payload = utils::hash_payload(&tx.build_for_signing());
+        self.create_hash_signature_request(&hashed_payload, derivation_path, domain_id)
+    }
 
+    /// Create signature request for an already hashed payload
+    pub(crate) fn create_hash_signature_request(
+        &self,
+        hashed_payload: &[u8; 32],
+        derivation_path: String,
+        domain_id: Option<u32>,
+    ) -> serde_json::Value {
         let sign_request = SignRequest {
             payload_v2: EcdsaPayload {
                 ecdsa: hex::encode(hashed_payload),
@@ -249,25 +498,22 @@ impl TradingAccountContract {
         mpc_signer_pk: String,
         derivation_path: String,
         domain_id: Option<u32>,
-    ) -> Promise {
-        let attached_gas = env::prepaid_gas();
-        assert!(
-            attached_gas >= GAS_FOR_REQUEST_SIGNATURE,
-            "Not enough gas attached. Please attach at least {} TGas. Attached: {} TGas",
-            GAS_FOR_REQUEST_SIGNATURE.as_tgas(),
-            attached_gas.as_tgas()
-        );
+        goal_id: U64,
+    ) -> PromiseOrValue<Option<String>> {
+        self.assert_can_request_signature();
 
-        assert!(
-            self.authorized_users
-                .contains(&env::predecessor_account_id()),
-            "Unauthorized: only authorized users can request signatures"
-        );
+        // Agents with a schedule may only trade during its windows
+        if let Err(e) = self.check_trading_window(&env::predecessor_account_id()) {
+            return self.reject_agent_request(format!("Trading window check failed: {}", e));
+        }
 
         // Parse actions from JSON string
-        let actions: Vec<ActionString> = serde_json::from_str(&actions_json).unwrap_or_else(|e| {
-            near_sdk::env::panic_str(&format!("Failed to parse actions JSON: {:?}", e))
-        });
+        let actions: Vec<ActionString> = match serde_json::from_str(&actions_json) {
+            Ok(actions) => actions,
+            Err(e) => {
+                return self.reject_agent_request(format!("Failed to parse actions JSON: {:?}", e));
+            }
+        };
 
         near_sdk::env::log_str(&format!(
             "Request received - Contract: {}, Actions: {:?}, Nonce: {}, Block Hash: {:?}",
@@ -278,7 +524,19 @@ impl TradingAccountContract {
         let mpc_public_key = match mpc_signer_pk.to_public_key() {
             Ok(pk) => pk,
             Err(e) => {
-                near_sdk::env::panic_str(&format!("Invalid MPC public key format: {}", e));
+                return self.reject_agent_request(format!("Invalid MPC public key format: {}", e));
+            }
+        };
+
+        // Determine the tokens moved by the actions before they are consumed by validation
+        let token_movements = actions::token_movements(&contract_id, &actions);
+        let balance_spends = actions::balance_spends(&contract_id, &actions);
+
+        // The owner's policy rules may deny the request or hold it for approval
+        let approval_rule = match self.check_policy_rules(&contract_id, &actions) {
+            Ok(rule_id) => rule_id,
+            Err(e) => {
+                return self.reject_agent_request(format!("Policy rule check failed: {}", e));
             }
         };
 
@@ -286,13 +544,73 @@ impl TradingAccountContract {
         let omni_actions = match self.validate_and_build_actions(actions, &contract_id) {
             Ok(actions) => actions,
             Err(e) => {
-                near_sdk::env::panic_str(&format!(
+                return self.reject_agent_request(format!(
                     "Failed to validate and build OmniActions: {:?}",
                     e
                 ));
             }
         };
 
+        // Goals with price bounds require the oracle to be consulted before signing
+        let price_guard_token = match self.goal_price_guard(goal_id.0) {
+            Ok(token) => token,
+            Err(e) => {
+                return self.reject_agent_request(format!("Goal validation failed: {}", e));
+            }
+        };
+
+        // NEAR attached to the actions must leave the minimum balance reserve untouched
+        self.release_expired_deposits();
+        let deposit = match actions::total_deposit(&omni_actions)
+            .and_then(|deposit| self.check_balance_reserve(deposit).map(|_| deposit))
+        {
+            Ok(deposit) => deposit,
+            Err(e) => {
+                return self.reject_agent_request(format!("Balance reserve check failed: {}", e));
+            }
+        };
+
+        // Every request must fit within the budget of the goal it cites
+        let previous_goal_execution = self.goal_last_execution(goal_id.0);
+        let goal_spend = match token_movements
+            .and_then(|movements| self.spend_goal_budget(goal_id.0, &movements, None))
+        {
+            Ok(spent) => spent,
+            Err(e) => {
+                return self.reject_agent_request(format!("Goal validation failed: {}", e));
+            }
+        };
+
+        // Spends are limited to a share of the balance they come from. NEAR can be checked right
+        // away, other balances are snapshotted before the signature is requested, which also
+        // happens when the owner enabled balance pre-flight checks.
+        let (balance_checks, counted_spends) = match balance_spends.and_then(|spends| {
+            let (near, others): (Vec<_>, Vec<_>) = spends
+                .into_iter()
+                .partition(|spend| spend.source == actions::BalanceSource::Near);
+            let counted = match near.first() {
+                Some(near) => self.check_near_balance_limits(near.amount.0)?,
+                None => vec![],
+            };
+            if self.has_balance_limits() || self.balance_preflight_enabled {
+                Ok((others, counted))
+            } else {
+                Ok((vec![], counted))
+            }
+        }) {
+            Ok(checks) => checks,
+            Err(e) => {
+                // The goal budget was already spent, the request is not going to use it
+                self.refund_goal_budget(
+                    goal_id.0,
+                    goal_spend,
+                    env::block_timestamp(),
+                    previous_goal_execution,
+                );
+                return self.reject_agent_request(format!("Balance limit check failed: {}", e));
+            }
+        };
+
         // construct the entire transaction to be signed
         let tx = TransactionBuilder::new::<NEAR>()
             .signer_id(env::current_account_id().to_string())
@@ -347,22 +665,73 @@ impl TradingAccountContract {
             block_hash
         ));
 
+        // Persist the request so its outcome can be retrieved by id should the caller lose the receipt
+        let request_id = self.record_signature_request(
+            contract_id.clone(),
+            nonce,
+            U128(deposit),
+            RequestReservation {
+                goal_id: Some(goal_id),
+                goal_spend: U128(goal_spend),
+                previous_goal_execution,
+                daily_spends: counted_spends,
+                ..Default::default()
+            },
+        );
+
         // Create signature request
         let request_payload =
             self.create_signature_request(&tx, derivation_path.clone(), domain_id);
 
-        let request_payload_bytes = match near_sdk::serde_json::to_vec(&request_payload) {
-            Ok(bytes) => bytes,
-            Err(e) => {
-                near_sdk::env::panic_str(&format!("Failed to serialize request payload: {}", e));
-            }
-        };
+        if let Some(rule_id) = approval_rule {
+            self.hold_for_approval(
+                request_id,
+                rule_id,
+                ApprovalRequest {
+                    request_payload: request_payload.to_string(),
+                    tx_json_string,
+                    balance_checks,
+                    oracle_assets: price_guard_token.into_iter().collect(),
+                    mpc_deposit: env::attached_deposit(),
+                },
+            );
+            return PromiseOrValue::Value(None);
+        }
 
+        PromiseOrValue::Promise(self.start_signature_request(
+            request_id,
+            request_payload,
+            SignedPayload::Transaction { tx_json_string },
+            balance_checks,
+            None,
+            price_guard_token.into_iter().collect(),
+            env::attached_deposit(),
+        ))
+    }
+
+    /// Run the pre-flight checks of a recorded signature request, consulting the price oracle
+    /// for `oracle_assets` first if there are any, then request the signature
+    pub(crate) fn start_signature_request(
+        &self,
+        request_id: u64,
+        request_payload: serde_json::Value,
+        payload: SignedPayload,
+        balance_checks: Vec<BalanceSpend>,
+        swap: Option<SwapQuote>,
+        oracle_assets: Vec<AccountId>,
+        mpc_deposit: NearToken,
+    ) -> Promise {
         let used_gas = near_sdk::env::used_gas();
-        let gas_for_signing = attached_gas
+        let mut gas_for_signing = env::prepaid_gas()
             .saturating_sub(BASE_GAS)
             .saturating_sub(used_gas)
-            .saturating_sub(CALLBACK_GAS);
+            .saturating_sub(CALLBACK_GAS)
+            .saturating_sub(self.balance_snapshot_gas(&balance_checks));
+        if !oracle_assets.is_empty() {
+            gas_for_signing = gas_for_signing
+                .saturating_sub(PRICE_ORACLE_GAS)
+                .saturating_sub(PRICE_CHECK_CALLBACK_GAS);
+        }
 
         near_sdk::env::log_str(&format!(
             "Used gas: {}, gas reserved for MPC call: {}",
@@ -370,19 +739,73 @@ impl TradingAccountContract {
             gas_for_signing.as_tgas()
         ));
 
-        // Call MPC requesting a signature for the above txn
+        let pending = PendingSignature {
+            request_payload,
+            deposit: mpc_deposit,
+            gas_for_signing,
+            payload,
+            balance_checks,
+            swap,
+        };
+
+        if oracle_assets.is_empty() {
+            return self.continue_signature_request(request_id, pending);
+        }
+
+        // Check the oracle prices before requesting the signature
+        let callback_gas = PRICE_CHECK_CALLBACK_GAS
+            .saturating_add(self.balance_snapshot_gas(&pending.balance_checks))
+            .saturating_add(gas_for_signing)
+            .saturating_add(CALLBACK_GAS);
+        price_oracle::ext_price_oracle::ext(
+            self.price_oracle_id
+                .clone()
+                .expect("Internal bug: price guard without an oracle"),
+        )
+        .with_static_gas(PRICE_ORACLE_GAS)
+        .get_price_data(Some(oracle_assets))
+        .then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(callback_gas)
+                .on_price_data(request_id, pending),
+        )
+    }
+
+    /// Run the remaining pre-flight checks of a signature request, then request the signature
+    fn continue_signature_request(&self, request_id: u64, pending: PendingSignature) -> Promise {
+        if pending.balance_checks.is_empty() {
+            self.request_mpc_signature(request_id, pending)
+        } else {
+            self.snapshot_balances(request_id, pending)
+        }
+    }
+
+    /// Call MPC requesting a signature for the given payload, then build the signed transaction
+    fn request_mpc_signature(&self, request_id: u64, pending: PendingSignature) -> Promise {
+        let request_payload_bytes = match near_sdk::serde_json::to_vec(&pending.request_payload) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                near_sdk::env::panic_str(&format!("Failed to serialize request payload: {}", e));
+            }
+        };
+
         Promise::new(self.signer_id.clone())
             .function_call(
                 "sign".to_string(),
                 request_payload_bytes,
-                env::attached_deposit(),
-                gas_for_signing,
+                pending.deposit,
+                pending.gas_for_signing,
             )
-            .then(
-                Self::ext(env::current_account_id())
+            .then(match pending.payload {
+                SignedPayload::Transaction { tx_json_string } => {
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(CALLBACK_GAS)
+                        .sign_request_callback(request_id, tx_json_string)
+                }
+                SignedPayload::Intent { payload_hash } => Self::ext(env::current_account_id())
                     .with_static_gas(CALLBACK_GAS)
-                    .sign_request_callback(tx_json_string),
-            )
+                    .sign_intent_callback(request_id, payload_hash),
+            })
     }
 
     pub fn add_full_access_key(&mut self, public_key: PublicKey) -> Promise {
@@ -416,23 +839,42 @@ impl TradingAccountContract {
     pub fn sign_request_callback(
         &mut self,
         #[callback_result] call_result: Result<SignatureResponse, PromiseError>,
+        request_id: u64,
         tx_json_string: String,
-    ) -> String {
-        let response = match call_result {
-            Ok(response) => {
-                near_sdk::env::log_str(&format!(
-                    "Parsed the MPC's Signature response: {:?}",
-                    response
-                ));
-                response
+    ) -> Option<String> {
+        // Failures are recorded against the request rather than panicking, which would revert the update
+        match self.build_signed_transaction(call_result, tx_json_string) {
+            Ok(base64_tx) => {
+                self.update_signature_request_status(
+                    request_id,
+                    SignatureRequestStatus::Signed {
+                        signed_transaction: base64_tx.clone(),
+                    },
+                );
+                Some(base64_tx)
             }
-            Err(e) => {
-                near_sdk::env::panic_str(&format!(
-                    "Failed to parse the MPC's Signature response: {:?}",
-                    e
+            Err(reason) => {
+                near_sdk::env::log_str(&format!(
+                    "Signature request {} failed: {}",
+                    request_id, reason
                 ));
+                self.update_signature_request_status(
+                    request_id,
+                    SignatureRequestStatus::Failed { reason },
+                );
+                self.record_request_rejection(request_id);
+                None
             }
-        };
+        }
+    }
+
+    /// Verify the MPC signature response and attach it to the transaction, returning it base64 encoded
+    fn build_signed_transaction(
+        &self,
+        call_result: Result<SignatureResponse, PromiseError>,
+        tx_json_string: String,
+    ) -> Result<String, String> {
+        let response = parse_mpc_response(call_result)?;
 
         // Deserialize transaction that we serialized in request_signature
         let near_tx = serde_json::from_str::<models::NearTransaction>(&tx_json_string)
@@ -441,43 +883,8 @@ impl TradingAccountContract {
         let message_hash = utils::hash_payload(&near_tx.build_for_signing());
         near_sdk::env::log_str(&format!("Message hash: {}", hex::encode(message_hash)));
 
-        // Handle different signature formats
-        let omni_signature = {
-            near_sdk::env::log_str("Using SECP256K1 signature format");
-            // Convert signature components
-            let r = hex::decode(&response.big_r.affine_point[2..]).expect("Invalid hex in r");
-            let s = hex::decode(&response.s.scalar).expect("Invalid hex in s");
-            let v = response.recovery_id;
-
-            // Combine r and s for verification
-            let mut signature = Vec::with_capacity(64);
-            signature.extend_from_slice(&r);
-            signature.extend_from_slice(&s);
-
-            // Verify signature
-            let recovered = self.test_recover(message_hash.to_vec(), signature, v);
-            match recovered {
-                Some(public_key) => {
-                    near_sdk::env::log_str(&format!(
-                        "Signature verified! Recovered public key: {}",
-                        public_key
-                    ));
-                }
-                None => {
-                    near_sdk::env::log_str("Signature verification failed!");
-                    near_sdk::env::panic_str("Invalid signature: ecrecover failed");
-                }
-            }
-
-            // Add individual bytes together in the correct order
-            let mut signature_bytes = [0u8; 65];
-            signature_bytes[..32].copy_from_slice(&r);
-            signature_bytes[32..64].copy_from_slice(&s);
-            signature_bytes[64] = v;
-
-            // Create signature
-            Signature::SECP256K1(Secp256K1Signature(signature_bytes))
-        };
+        let signature_bytes = self.decode_mpc_signature(response, &message_hash)?;
+        let omni_signature = Signature::SECP256K1(Secp256K1Signature(signature_bytes));
 
         near_sdk::env::log_str(&format!(
             "constructed omni signature: {:?}",
@@ -491,7 +898,57 @@ impl TradingAccountContract {
             base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &near_tx_signed);
         near_sdk::env::log_str(&format!("Signed transaction (base64): {}", base64_tx));
 
-        base64_tx
+        Ok(base64_tx)
+    }
+
+    /// Verify the MPC signature response against the signed hash, returning the 65 byte
+    /// SECP256K1 signature (r, s, v)
+    pub(crate) fn decode_mpc_signature(
+        &self,
+        response: SignatureResponse,
+        message_hash: &[u8; 32],
+    ) -> Result<[u8; 65], String> {
+        near_sdk::env::log_str("Using SECP256K1 signature format");
+        // Convert signature components
+        let r = response
+            .big_r
+            .affine_point
+            .get(2..)
+            .and_then(|point| hex::decode(point).ok())
+            .filter(|r| r.len() == 32)
+            .ok_or("Invalid hex in r")?;
+        let s = hex::decode(&response.s.scalar)
+            .ok()
+            .filter(|s| s.len() == 32)
+            .ok_or("Invalid hex in s")?;
+        let v = response.recovery_id;
+
+        // Combine r and s for verification
+        let mut signature = Vec::with_capacity(64);
+        signature.extend_from_slice(&r);
+        signature.extend_from_slice(&s);
+
+        // Verify signature
+        let recovered = self.test_recover(message_hash.to_vec(), signature, v);
+        match recovered {
+            Some(public_key) => {
+                near_sdk::env::log_str(&format!(
+                    "Signature verified! Recovered public key: {}",
+                    public_key
+                ));
+            }
+            None => {
+                near_sdk::env::log_str("Signature verification failed!");
+                return Err("Invalid signature: ecrecover failed".to_string());
+            }
+        }
+
+        // Add individual bytes together in the correct order
+        let mut signature_bytes = [0u8; 65];
+        signature_bytes[..32].copy_from_slice(&r);
+        signature_bytes[32..64].copy_from_slice(&s);
+        signature_bytes[64] = v;
+        Ok(signature_bytes)
     }
 
     fn test_recover(&self, hash: Vec<u8>, signature: Vec<u8>, v: u8) -> Option<String> {
@@ -513,3 +970,22 @@ impl TradingAccountContract {
         })
     }
 }
+
+/// Unwrap the MPC signer's response to a sign request
+pub(crate) fn parse_mpc_response(
+    call_result: Result<SignatureResponse, PromiseError>,
+) -> Result<SignatureResponse, String> {
+    match call_result {
+        Ok(response) => {
+            near_sdk::env::log_str(&format!(
+                "Parsed the MPC's Signature response: {:?}",
+                response
+            ));
+            Ok(response)
+        }
+        Err(e) => Err(format!(
+            "Failed to parse the MPC's Signature response: {:?}",
+            e
+        )),
+    }
+}
@@ -0,0 +1,333 @@
+use near_sdk::json_types::{U64, U128};
+use near_sdk::{
+    AccountId, Gas, PromiseOrValue, PromiseResult, env, ext_contract, near, serde_json,
+};
+
+use crate::actions::{BalanceSource, BalanceSpend};
+use crate::policy_changes::PolicyChange;
+use crate::signature_requests::PendingSignature;
+use crate::withdrawals::ext_ft;
+use crate::{NEAR_INTENTS_ADDRESS, TradingAccountContract, TradingAccountContractExt};
+
+pub const BALANCE_QUERY_GAS: Gas = Gas::from_tgas(5); // Gas for each ft_balance_of / mt_balance_of view
+pub const BALANCE_CHECK_CALLBACK_GAS: Gas = Gas::from_tgas(10); // Gas for evaluating the balance snapshot
+const MAX_BASIS_POINTS: u32 = 10_000;
+pub(crate) const DAY_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// Limits on how much of a balance agents may spend, in basis points (1/100th of a percent) of
+/// the trading account's balance at the time of the request
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BalanceLimits {
+    /// The share of a balance a single request may spend
+    pub max_trade_bps: Option<u32>,
+    /// The share of a balance, as of its first spend of the UTC day, all requests of that day may spend
+    pub max_daily_bps: Option<u32>,
+}
+
+/// What was spent from a balance during a UTC day
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug)]
+pub struct DailySpend {
+    /// Days since the unix epoch
+    pub day: U64,
+    /// The balance before the first spend of the day
+    pub start_balance: U128,
+    pub spent: U128,
+}
+
+/// A spend counted towards the daily limit of a balance by a signature request, taken back
+/// should the request fail
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CountedSpend {
+    /// The balance spent from, as in `get_daily_spend`
+    pub key: String,
+    /// Days since the unix epoch
+    pub day: U64,
+    pub amount: U128,
+}
+
+#[allow(dead_code)]
+#[ext_contract(ext_mt)]
+pub trait MultiToken {
+    fn mt_balance_of(&self, account_id: AccountId, token_id: String) -> U128;
+}
+
+#[near]
+impl TradingAccountContract {
+    /// Limit spending to a share of the current balances. Raising or removing a limit is subject
+    /// to the policy change delay.
+    pub fn set_balance_limits(&mut self, max_trade_bps: Option<u32>, max_daily_bps: Option<u32>) {
+        self.assert_owner_or_manager();
+        for bps in [max_trade_bps, max_daily_bps].into_iter().flatten() {
+            assert!(
+                bps > 0 && bps <= MAX_BASIS_POINTS,
+                "Balance limits must be between 1 and {} basis points",
+                MAX_BASIS_POINTS
+            );
+        }
+
+        let limits = BalanceLimits {
+            max_trade_bps,
+            max_daily_bps,
+        };
+        let loosened = bps_limit_loosened(self.balance_limits.max_trade_bps, max_trade_bps)
+            || bps_limit_loosened(self.balance_limits.max_daily_bps, max_daily_bps);
+        self.tighten_or_schedule(PolicyChange::SetBalanceLimits { limits }, loosened);
+    }
+
+    pub fn get_balance_limits(&self) -> BalanceLimits {
+        self.balance_limits.clone()
+    }
+
+    /// Check that the trading account holds the NEP-141 and intents.near balances a request
+    /// spends before calling the MPC signer, sparing agents the fee for transactions that would fail
+    pub fn set_balance_preflight(&mut self, enabled: bool) {
+        self.assert_owner();
+        self.balance_preflight_enabled = enabled;
+    }
+
+    pub fn get_balance_preflight(&self) -> bool {
+        self.balance_preflight_enabled
+    }
+
+    /// What has been spent today from the balance identified by `key`, e.g. "near" or "wrap.near"
+    pub fn get_daily_spend(&self, key: String) -> Option<DailySpend> {
+        self.daily_spend
+            .get(&key)
+            .filter(|spend| spend.day.0 == current_day())
+    }
+
+    #[private] // Only callable by the contract itself
+    pub fn on_balance_snapshot(
+        &mut self,
+        request_id: u64,
+        pending: PendingSignature,
+    ) -> PromiseOrValue<Option<String>> {
+        let result = self
+            .read_balance_snapshot(&pending.balance_checks)
+            .and_then(|balances| {
+                check_sufficient_balances(&pending.balance_checks, &balances)?;
+                self.check_balance_limits(&pending.balance_checks, &balances)
+            });
+        match result {
+            Ok(counted) => {
+                self.add_counted_spends(request_id, counted);
+                PromiseOrValue::Promise(self.request_mpc_signature(request_id, pending))
+            }
+            Err(reason) => {
+                env::log_str(&format!(
+                    "Signature request {} rejected by balance check: {}",
+                    request_id, reason
+                ));
+                self.reject_signature_request(request_id, reason, pending.deposit);
+                self.record_request_rejection(request_id);
+                PromiseOrValue::Value(None)
+            }
+        }
+    }
+}
+
+impl TradingAccountContract {
+    pub(crate) fn has_balance_limits(&self) -> bool {
+        self.balance_limits != BalanceLimits::default()
+    }
+
+    /// Gas needed to snapshot the given balances before signing
+    pub(crate) fn balance_snapshot_gas(&self, balance_checks: &[BalanceSpend]) -> Gas {
+        if balance_checks.is_empty() {
+            return Gas::from_gas(0);
+        }
+        BALANCE_QUERY_GAS
+            .saturating_mul(balance_checks.len() as u64)
+            .saturating_add(BALANCE_CHECK_CALLBACK_GAS)
+    }
+
+    /// Query every balance to check, then evaluate them in `on_balance_snapshot` before signing
+    pub(crate) fn snapshot_balances(
+        &self,
+        request_id: u64,
+        pending: PendingSignature,
+    ) -> near_sdk::Promise {
+        let account_id = env::current_account_id();
+        let snapshot = pending
+            .balance_checks
+            .iter()
+            .map(|check| match &check.source {
+                BalanceSource::Ft { token } => ext_ft::ext(token.clone())
+                    .with_static_gas(BALANCE_QUERY_GAS)
+                    .ft_balance_of(account_id.clone()),
+                BalanceSource::Intents { token_id } => ext_mt::ext(NEAR_INTENTS_ADDRESS.clone())
+                    .with_static_gas(BALANCE_QUERY_GAS)
+                    .mt_balance_of(account_id.clone(), token_id.clone()),
+                BalanceSource::Near => {
+                    env::panic_str("Internal bug: NEAR balances are checked synchronously")
+                }
+            })
+            .reduce(|snapshot, query| snapshot.and(query))
+            .expect("Internal bug: balance snapshot without balances to check");
+
+        let callback_gas = BALANCE_CHECK_CALLBACK_GAS
+            .saturating_add(pending.gas_for_signing)
+            .saturating_add(crate::CALLBACK_GAS);
+        snapshot.then(
+            Self::ext(account_id)
+                .with_static_gas(callback_gas)
+                .on_balance_snapshot(request_id, pending),
+        )
+    }
+
+    /// Check the NEAR attached to a request against the balance limits, which needs no snapshot
+    pub(crate) fn check_near_balance_limits(
+        &mut self,
+        amount: u128,
+    ) -> Result<Vec<CountedSpend>, String> {
+        if !self.has_balance_limits() {
+            return Ok(vec![]);
+        }
+        // The deposit attached to this call is forwarded to the MPC signer
+        let balance = env::account_balance()
+            .saturating_sub(env::attached_deposit())
+            .as_yoctonear();
+        let check = BalanceSpend {
+            source: BalanceSource::Near,
+            amount: U128(amount),
+        };
+        self.check_balance_limits(&[check], &[balance])
+    }
+
+    /// Check each spend against the limits given the current balances, and count them towards
+    /// today's spending once all of them pass. Returns what was counted.
+    fn check_balance_limits(
+        &mut self,
+        checks: &[BalanceSpend],
+        balances: &[u128],
+    ) -> Result<Vec<CountedSpend>, String> {
+        if !self.has_balance_limits() {
+            return Ok(vec![]);
+        }
+
+        let today = current_day();
+        let mut daily_spends: Vec<(String, DailySpend)> = Vec::with_capacity(checks.len());
+        for (check, balance) in checks.iter().zip(balances) {
+            let key = check.source.key();
+            let amount = check.amount.0;
+
+            if let Some(bps) = self.balance_limits.max_trade_bps {
+                let allowed = bps_of(*balance, bps);
+                if amount > allowed {
+                    return Err(format!(
+                        "Amount {} of {} exceeds the per trade limit of {} ({} bps of the balance of {})",
+                        amount, key, allowed, bps, balance
+                    ));
+                }
+            }
+
+            // Earlier checks of the same balance count towards the daily total of this one
+            let pending = daily_spends.iter().position(|(pending, _)| *pending == key);
+            let mut daily = pending
+                .map(|i| daily_spends.remove(i).1)
+                .or_else(|| self.daily_spend.get(&key))
+                .filter(|spend| spend.day.0 == today)
+                .unwrap_or(DailySpend {
+                    day: U64(today),
+                    start_balance: U128(*balance),
+                    spent: U128(0),
+                });
+            let spent = daily
+                .spent
+                .0
+                .checked_add(amount)
+                .ok_or("Token amount overflow")?;
+            if let Some(bps) = self.balance_limits.max_daily_bps {
+                let allowed = bps_of(daily.start_balance.0, bps);
+                if spent > allowed {
+                    return Err(format!(
+                        "Amount {} of {} exceeds the daily limit of {} ({} bps of the balance of {}), {} was already spent today",
+                        amount, key, allowed, bps, daily.start_balance.0, daily.spent.0
+                    ));
+                }
+            }
+            daily.spent = U128(spent);
+            daily_spends.push((key, daily));
+        }
+
+        for (key, daily) in &daily_spends {
+            self.daily_spend.insert(key, daily);
+        }
+        let counted = checks
+            .iter()
+            .map(|check| CountedSpend {
+                key: check.source.key(),
+                day: U64(today),
+                amount: check.amount,
+            })
+            .collect();
+        Ok(counted)
+    }
+
+    /// Take the spends of a failed request back from the daily totals, unless their day is over
+    pub(crate) fn release_counted_spends(&mut self, counted: &[CountedSpend]) {
+        for spend in counted {
+            if let Some(mut daily) = self.daily_spend.get(&spend.key) {
+                if daily.day == spend.day {
+                    daily.spent = U128(daily.spent.0.saturating_sub(spend.amount.0));
+                    self.daily_spend.insert(&spend.key, &daily);
+                }
+            }
+        }
+    }
+
+    /// Read the balances returned by the queries of `snapshot_balances`, in order
+    fn read_balance_snapshot(&self, checks: &[BalanceSpend]) -> Result<Vec<u128>, String> {
+        checks
+            .iter()
+            .enumerate()
+            .map(|(i, check)| match env::promise_result(i as u64) {
+                PromiseResult::Successful(bytes) => serde_json::from_slice::<U128>(&bytes)
+                    .map(|balance| balance.0)
+                    .map_err(|e| format!("Invalid balance of {}: {}", check.source.key(), e)),
+                PromiseResult::Failed => Err(format!(
+                    "Failed to fetch the balance of {}",
+                    check.source.key()
+                )),
+            })
+            .collect()
+    }
+}
+
+/// Ensure every balance covers what the request spends from it
+fn check_sufficient_balances(checks: &[BalanceSpend], balances: &[u128]) -> Result<(), String> {
+    for (check, balance) in checks.iter().zip(balances) {
+        if check.amount.0 > *balance {
+            return Err(format!(
+                "Insufficient balance of {}: the request spends {} but the trading account holds {}",
+                check.source.key(),
+                check.amount.0,
+                balance
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// `bps` basis points of `amount`, rounded down, without overflowing
+fn bps_of(amount: u128, bps: u32) -> u128 {
+    let bps = bps as u128;
+    let base = MAX_BASIS_POINTS as u128;
+    amount / base * bps + amount % base * bps / base
+}
+
+/// Whether replacing a limit with `new` lets agents spend more. No limit is the loosest.
+fn bps_limit_loosened(old: Option<u32>, new: Option<u32>) -> bool {
+    match (old, new) {
+        (_, None) => old.is_some(),
+        (None, Some(_)) => false,
+        (Some(old), Some(new)) => new > old,
+    }
+}
+
+pub(crate) fn current_day() -> u64 {
+    env::block_timestamp() / DAY_NS
+}
@@ -0,0 +1,55 @@
+use near_sdk::{NearToken, env, near};
+
+use crate::policy_changes::PolicyChange;
+use crate::{TradingAccountContract, TradingAccountContractExt};
+
+#[near]
+impl TradingAccountContract {
+    /// Set the NEAR balance the trading account must keep on top of its storage cost. Lowering
+    /// the reserve lets agents spend more, so it is subject to the policy change delay.
+    pub fn set_min_balance_reserve(&mut self, reserve: NearToken) {
+        self.assert_owner_or_manager();
+        let loosened = reserve < self.min_balance_reserve;
+        self.tighten_or_schedule(PolicyChange::SetMinBalanceReserve { reserve }, loosened);
+    }
+
+    pub fn get_min_balance_reserve(&self) -> NearToken {
+        self.min_balance_reserve
+    }
+
+    /// NEAR attached to signature requests whose transactions may still execute: requests
+    /// awaiting the MPC signer and signed transactions which have not expired or been settled
+    pub fn get_pending_deposits(&self) -> NearToken {
+        NearToken::from_yoctonear(self.pending_deposits)
+    }
+}
+
+impl TradingAccountContract {
+    /// NEAR which may leave the account without eating into its storage cost or the reserve
+    pub(crate) fn available_near_balance(&self) -> NearToken {
+        let storage_cost = env::storage_byte_cost().saturating_mul(env::storage_usage() as u128);
+        env::account_balance()
+            .saturating_sub(storage_cost)
+            .saturating_sub(self.min_balance_reserve)
+    }
+
+    /// Ensure the NEAR attached to a signature request's actions, on top of what pending requests
+    /// and unexpired signed transactions already committed, leaves the minimum balance reserve
+    /// untouched
+    pub(crate) fn check_balance_reserve(&self, deposit: u128) -> Result<(), String> {
+        // The deposit attached to this call is forwarded to the MPC signer
+        let available = self
+            .available_near_balance()
+            .saturating_sub(env::attached_deposit())
+            .saturating_sub(NearToken::from_yoctonear(self.pending_deposits));
+        if deposit > available.as_yoctonear() {
+            return Err(format!(
+                "Attached deposits of {} exceed the {} available above the minimum balance reserve of {} and the deposits of pending requests",
+                NearToken::from_yoctonear(deposit),
+                available,
+                self.min_balance_reserve
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use near_sdk::json_types::U64;
+use near_sdk::{AccountId, Promise, PromiseOrValue, env, near};
+
+use crate::events::TradingAccountEvent;
+use crate::policy_changes::PolicyChange;
+use crate::{TradingAccountContract, TradingAccountContractExt};
+
+pub const DEFAULT_MAX_REJECTIONS: u32 = 5;
+pub const DEFAULT_REJECTION_WINDOW_NS: u64 = 60 * 60 * 1_000_000_000; // 1 hour
+
+/// How many rejected signature requests an agent may make within a window before it is suspended
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CircuitBreaker {
+    pub max_rejections: u32,
+    pub window_ns: U64,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            max_rejections: DEFAULT_MAX_REJECTIONS,
+            window_ns: U64(DEFAULT_REJECTION_WINDOW_NS),
+        }
+    }
+}
+
+/// The rejected signature requests of an agent in the current window
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug)]
+pub struct RejectionCount {
+    /// Block timestamp (in nanoseconds) of the first rejection of the window
+    pub window_start: U64,
+    pub rejections: u32,
+}
+
+#[near]
+impl TradingAccountContract {
+    /// Suspend agents after `max_rejections` rejected requests within `window_ns`. Allowing more
+    /// rejections or shortening the window is subject to the policy change delay.
+    pub fn set_circuit_breaker(&mut self, max_rejections: u32, window_ns: U64) {
+        self.assert_owner_or_manager();
+        assert!(
+            max_rejections > 0 && window_ns.0 > 0,
+            "The circuit breaker needs at least one rejection and a window longer than zero"
+        );
+
+        let breaker = CircuitBreaker {
+            max_rejections,
+            window_ns,
+        };
+        let loosened = max_rejections > self.circuit_breaker.max_rejections
+            || window_ns.0 < self.circuit_breaker.window_ns.0;
+        self.tighten_or_schedule(PolicyChange::SetCircuitBreaker { breaker }, loosened);
+    }
+
+    pub fn get_circuit_breaker(&self) -> CircuitBreaker {
+        self.circuit_breaker.clone()
+    }
+
+    /// Let a suspended agent request signatures again, clearing its rejections
+    pub fn reinstate_agent(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        assert!(
+            self.suspended_agents.remove(&account_id),
+            "{} is not suspended",
+            account_id
+        );
+        self.agent_rejections.remove(&account_id);
+
+        TradingAccountEvent::AgentReinstated { account_id }.emit();
+    }
+
+    pub fn get_suspended_agents(&self) -> Vec<AccountId> {
+        self.suspended_agents.to_vec()
+    }
+
+    pub fn get_agent_rejections(&self, account_id: AccountId) -> Option<RejectionCount> {
+        self.agent_rejections.get(&account_id)
+    }
+}
+
+impl TradingAccountContract {
+    pub(crate) fn assert_not_suspended(&self, account_id: &AccountId) {
+        assert!(
+            !self.suspended_agents.contains(account_id),
+            "{} is suspended after repeated rejected requests. The owner must reinstate it with reinstate_agent",
+            account_id
+        );
+    }
+
+    /// Reject a signature request of the calling agent without panicking, so the rejection counts
+    /// towards the circuit breaker, and refund the deposit attached for the MPC signer
+    pub(crate) fn reject_agent_request(
+        &mut self,
+        reason: String,
+    ) -> PromiseOrValue<Option<String>> {
+        let account_id = env::predecessor_account_id();
+        env::log_str(&format!("Signature request rejected: {}", reason));
+        self.record_rejection(account_id.clone());
+
+        let deposit = env::attached_deposit();
+        if !deposit.is_zero() {
+            Promise::new(account_id).transfer(deposit).detach();
+        }
+        PromiseOrValue::Value(None)
+    }
+
+    /// Count a request which failed after it was accepted, in a pre-flight check or at the MPC
+    /// signer, towards the circuit breaker of the agent which made it
+    pub(crate) fn record_request_rejection(&mut self, request_id: u64) {
+        if let Some(request) = self.signature_requests.get(&request_id) {
+            self.record_rejection(request.requested_by);
+        }
+    }
+
+    fn record_rejection(&mut self, account_id: AccountId) {
+        let now = env::block_timestamp();
+        let window_ns = self.circuit_breaker.window_ns.0;
+        let mut count = self
+            .agent_rejections
+            .get(&account_id)
+            .filter(|count| now.saturating_sub(count.window_start.0) < window_ns)
+            .unwrap_or(RejectionCount {
+                window_start: U64(now),
+                rejections: 0,
+            });
+        count.rejections = count.rejections.saturating_add(1);
+        self.agent_rejections.insert(&account_id, &count);
+
+        if count.rejections >= self.circuit_breaker.max_rejections
+            && self.suspended_agents.insert(&account_id)
+        {
+            TradingAccountEvent::AgentSuspended {
+                account_id,
+                rejections: count.rejections,
+            }
+            .emit();
+        }
+    }
+
+    /// Forget the rejections of an agent which is no longer authorized. A suspension is kept, so
+    /// removing and re-adding an agent cannot lift it, only `reinstate_agent` can.
+    pub(crate) fn clear_agent_rejections(&mut self, account_id: &AccountId) {
+        self.agent_rejections.remove(account_id);
+    }
+}
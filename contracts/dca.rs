@@ -0,0 +1,275 @@
+use near_sdk::json_types::{Base64VecU8, U64, U128};
+use near_sdk::{AccountId, PromiseOrValue, env, near};
+
+use crate::goals::MAX_SLIPPAGE_BPS;
+use crate::intents::{SwapQuote, intents_spend, nep413_hash, parse_token_diff};
+use crate::policy_changes::PolicyChange;
+use crate::signature_requests::{RequestReservation, SignedPayload};
+use crate::{NEAR_INTENTS_ADDRESS, TradingAccountContract, TradingAccountContractExt};
+
+const MAX_DCA_SCHEDULES: u64 = 10; // Maximum number of DCA schedules per trading account
+
+/// A recurring swap of a fixed amount, which the agent may execute once per interval
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug)]
+pub struct DcaSchedule {
+    pub schedule_id: U64,
+    pub from_token: AccountId,
+    pub to_token: AccountId,
+    /// Amount of `from_token` each execution spends
+    pub amount: U128,
+    pub interval_ns: U64,
+    /// Block timestamp (in nanoseconds) at which the first interval starts
+    pub starts_at: U64,
+    /// Optional bound, in basis points, on how much less an execution may receive than it
+    /// spends, valued at the oracle prices
+    pub max_slippage_bps: Option<u32>,
+    pub executions: u32,
+    /// Block timestamp (in nanoseconds) of the last execution
+    pub last_executed_at: Option<U64>,
+    /// Block timestamp (in nanoseconds) from which the next execution may be requested
+    pub next_execution_at: U64,
+}
+
+impl DcaSchedule {
+    /// The start of the interval holding `timestamp`
+    fn interval_start(&self, timestamp: u64) -> u64 {
+        let elapsed = timestamp.saturating_sub(self.starts_at.0);
+        self.starts_at.0 + elapsed / self.interval_ns.0 * self.interval_ns.0
+    }
+}
+
+#[near]
+impl TradingAccountContract {
+    /// Let the agent swap `amount` of `from_token` for `to_token` once every `interval_ns`,
+    /// starting at `starts_at` (defaults to now). The schedule authorizes the swap without a
+    /// goal, so creating one is subject to the policy change delay, and the returned id is the
+    /// one the schedule gets once the change is executed. Its executions must still spend an
+    /// allowed token for a token of an allowed pair.
+    pub fn create_dca_schedule(
+        &mut self,
+        from_token: AccountId,
+        to_token: AccountId,
+        amount: U128,
+        interval_ns: U64,
+        starts_at: Option<U64>,
+        max_slippage_bps: Option<u32>,
+    ) -> U64 {
+        self.assert_owner();
+        assert_ne!(
+            from_token, to_token,
+            "A DCA schedule must swap two different tokens"
+        );
+        assert!(amount.0 > 0, "amount must be greater than zero");
+        assert!(interval_ns.0 > 0, "interval_ns must be greater than zero");
+        if let Some(bps) = max_slippage_bps {
+            assert!(
+                bps <= MAX_SLIPPAGE_BPS,
+                "max_slippage_bps cannot exceed {}",
+                MAX_SLIPPAGE_BPS
+            );
+        }
+        let starts_at = starts_at.unwrap_or(U64(env::block_timestamp()));
+
+        let schedule_id = self.next_dca_schedule_id;
+        self.next_dca_schedule_id += 1;
+        self.apply_or_schedule(PolicyChange::CreateDcaSchedule {
+            schedule: DcaSchedule {
+                schedule_id: U64(schedule_id),
+                from_token,
+                to_token,
+                amount,
+                interval_ns,
+                starts_at,
+                max_slippage_bps,
+                executions: 0,
+                last_executed_at: None,
+                next_execution_at: starts_at,
+            },
+        });
+        U64(schedule_id)
+    }
+
+    /// Cancelling a schedule only takes power away from the agent, so managers may do so as well
+    pub fn cancel_dca_schedule(&mut self, schedule_id: U64) {
+        self.assert_owner_or_manager();
+        assert!(
+            self.dca_schedules.remove(&schedule_id.0).is_some(),
+            "DCA schedule {} does not exist",
+            schedule_id.0
+        );
+        env::log_str(&format!("DCA schedule {} cancelled", schedule_id.0));
+    }
+
+    pub fn get_dca_schedule(&self, schedule_id: U64) -> Option<DcaSchedule> {
+        self.dca_schedules.get(&schedule_id.0)
+    }
+
+    pub fn get_dca_schedules(&self) -> Vec<DcaSchedule> {
+        self.dca_schedules.values().collect()
+    }
+
+    /// Request an MPC signature over a NEP-413 token_diff intent executing a DCA schedule. The
+    /// intent must spend exactly the schedule's amount of its from_token for its to_token, and
+    /// the schedule must be due. Allowed tokens and pairs, balance limits, pre-flight checks and
+    /// the agent's trading windows apply as for `request_intent_signature`.
+    #[payable]
+    pub fn request_dca_signature(
+        &mut self,
+        schedule_id: U64,
+        message: String,
+        nonce: Base64VecU8,
+        derivation_path: String,
+        domain_id: Option<u32>,
+    ) -> PromiseOrValue<Option<String>> {
+        self.assert_can_request_signature();
+
+        if let Err(e) = self.check_trading_window(&env::predecessor_account_id()) {
+            return self.reject_agent_request(format!("Trading window check failed: {}", e));
+        }
+        let Ok(nonce) = <[u8; 32]>::try_from(nonce.0) else {
+            return self.reject_agent_request("The NEP-413 nonce must be 32 bytes".to_string());
+        };
+
+        let mut swap = match parse_token_diff(&message) {
+            Ok(swap) => swap,
+            Err(e) => {
+                return self.reject_agent_request(format!("Invalid token_diff intent: {}", e));
+            }
+        };
+        if let Err(e) = self.check_intent_swap(&swap) {
+            return self.reject_agent_request(e);
+        }
+        if let Err(e) = self.check_intent_policy_rules(&message, &swap) {
+            return self.reject_agent_request(format!("Policy rule check failed: {}", e));
+        }
+        let max_slippage_bps = self
+            .dca_schedules
+            .get(&schedule_id.0)
+            .and_then(|schedule| schedule.max_slippage_bps);
+        if max_slippage_bps.is_some() && self.price_oracle_id.is_none() {
+            return self.reject_agent_request(format!(
+                "DCA schedule check failed: DCA schedule {} has a maximum slippage but no price oracle is configured",
+                schedule_id.0
+            ));
+        }
+        // The execution is taken last, once nothing else can reject the request
+        if let Err(e) = self.take_dca_execution(schedule_id.0, &swap) {
+            return self.reject_agent_request(format!("DCA schedule check failed: {}", e));
+        }
+
+        let balance_checks = if self.has_balance_limits() || self.balance_preflight_enabled {
+            vec![intents_spend(&swap)]
+        } else {
+            vec![]
+        };
+
+        let (swap, oracle_assets) = match max_slippage_bps {
+            Some(bps) => {
+                swap.max_slippage_bps = bps;
+                let oracle_assets = vec![swap.spent_token.clone(), swap.received_token.clone()];
+                (Some(swap), oracle_assets)
+            }
+            None => (None, vec![]),
+        };
+
+        let payload_hash = nep413_hash(message, nonce);
+        let request_id = self.record_signature_request(
+            NEAR_INTENTS_ADDRESS.clone(),
+            U64(0),
+            U128(0),
+            RequestReservation {
+                dca_schedule_id: Some(schedule_id),
+                ..Default::default()
+            },
+        );
+        let request_payload =
+            self.create_hash_signature_request(&payload_hash, derivation_path, domain_id);
+
+        PromiseOrValue::Promise(self.start_signature_request(
+            request_id,
+            request_payload,
+            SignedPayload::Intent {
+                payload_hash: hex::encode(payload_hash),
+            },
+            balance_checks,
+            swap,
+            oracle_assets,
+            env::attached_deposit(),
+        ))
+    }
+}
+
+impl TradingAccountContract {
+    pub(crate) fn insert_dca_schedule(&mut self, schedule: DcaSchedule) {
+        assert!(
+            self.dca_schedules.len() < MAX_DCA_SCHEDULES,
+            "Maximum number of DCA schedules reached:({}). One must be cancelled before adding another.",
+            MAX_DCA_SCHEDULES
+        );
+
+        let schedule_id = schedule.schedule_id.0;
+        self.dca_schedules.insert(&schedule_id, &schedule);
+        env::log_str(&format!("DCA schedule {} created", schedule_id));
+    }
+
+    /// Check a swap against a DCA schedule which must be due, then record the execution and
+    /// move the next one to the following interval
+    pub(crate) fn take_dca_execution(
+        &mut self,
+        schedule_id: u64,
+        swap: &SwapQuote,
+    ) -> Result<DcaSchedule, String> {
+        let mut schedule = self
+            .dca_schedules
+            .get(&schedule_id)
+            .ok_or(format!("DCA schedule {} does not exist", schedule_id))?;
+
+        if swap.spent_token != schedule.from_token || swap.received_token != schedule.to_token {
+            return Err(format!(
+                "DCA schedule {} swaps {} for {}, not {} for {}",
+                schedule_id,
+                schedule.from_token,
+                schedule.to_token,
+                swap.spent_token,
+                swap.received_token
+            ));
+        }
+        if swap.spent_amount != schedule.amount {
+            return Err(format!(
+                "DCA schedule {} spends exactly {} of {}, not {}",
+                schedule_id, schedule.amount.0, schedule.from_token, swap.spent_amount.0
+            ));
+        }
+        let now = env::block_timestamp();
+        if now < schedule.next_execution_at.0 {
+            return Err(format!(
+                "DCA schedule {} is not due until {}",
+                schedule_id, schedule.next_execution_at.0
+            ));
+        }
+
+        schedule.executions += 1;
+        schedule.last_executed_at = Some(U64(now));
+        schedule.next_execution_at = U64(schedule
+            .interval_start(now)
+            .saturating_add(schedule.interval_ns.0));
+        self.dca_schedules.insert(&schedule_id, &schedule);
+        Ok(schedule)
+    }
+
+    /// Give back the interval of an execution which never produced a signature, so the agent
+    /// may retry it
+    pub(crate) fn release_dca_execution(&mut self, schedule_id: u64, requested_at: u64) {
+        let Some(mut schedule) = self.dca_schedules.get(&schedule_id) else {
+            return;
+        };
+        let interval_start = schedule.interval_start(requested_at);
+        if schedule.next_execution_at.0 != interval_start.saturating_add(schedule.interval_ns.0) {
+            return;
+        }
+        schedule.next_execution_at = U64(interval_start);
+        schedule.executions = schedule.executions.saturating_sub(1);
+        self.dca_schedules.insert(&schedule_id, &schedule);
+    }
+}
@@ -0,0 +1,57 @@
+use near_sdk::json_types::U128;
+use near_sdk::{AccountId, near};
+
+use crate::account_closure::AccountClosureStage;
+
+/// NEP-297 events emitted by the trading account
+#[near(event_json(standard = "trading_account"))]
+pub enum TradingAccountEvent {
+    #[event_version("1.0.0")]
+    OwnerProposed {
+        owner_id: AccountId,
+        proposed_owner_id: AccountId,
+    },
+    #[event_version("1.0.0")]
+    OwnerProposalCancelled {
+        owner_id: AccountId,
+        proposed_owner_id: AccountId,
+    },
+    #[event_version("1.0.0")]
+    OwnershipTransferred {
+        old_owner_id: AccountId,
+        new_owner_id: AccountId,
+    },
+    /// Funds sent straight to the owner. `asset` is "near", a NEP-141 token id, or an intents.near
+    /// balance prefixed with "intents.near:"
+    #[event_version("1.0.0")]
+    OwnerWithdrawal {
+        receiver_id: AccountId,
+        asset: String,
+        amount: U128,
+    },
+    /// A stage of `close_trading_account` finished. A failed stage can be resumed by calling
+    /// `close_trading_account` again.
+    #[event_version("1.0.0")]
+    AccountClosureProgress {
+        stage: AccountClosureStage,
+        completed: bool,
+        reason: Option<String>,
+    },
+    /// Emitted by the receipt deleting the account, which only succeeds along with the deletion
+    #[event_version("1.0.0")]
+    AccountClosed { beneficiary_id: AccountId },
+    /// An agent accepted the authorization offered by `add_authorized_user`
+    #[event_version("1.0.0")]
+    AgentAuthorized { account_id: AccountId },
+    /// An agent dropped its own authorization
+    #[event_version("1.0.0")]
+    AgentRenounced { account_id: AccountId },
+    /// An agent hit the circuit breaker's limit of rejected requests and may no longer sign
+    #[event_version("1.0.0")]
+    AgentSuspended {
+        account_id: AccountId,
+        rejections: u32,
+    },
+    #[event_version("1.0.0")]
+    AgentReinstated { account_id: AccountId },
+}
@@ -0,0 +1,336 @@
+use near_sdk::json_types::{U64, U128};
+use near_sdk::{AccountId, env, near};
+
+use crate::actions::TokenMovement;
+use crate::policy_changes::{PolicyChange, price_bound_loosened};
+use crate::price_oracle::Price;
+use crate::{TradingAccountContract, TradingAccountContractExt};
+
+const MAX_GOALS: u64 = 20; // Maximum number of goals per trading account
+pub(crate) const MAX_SLIPPAGE_BPS: u32 = 10_000;
+
+/// An owner-defined allowance goal which bounds what an agent may trade on the owner's behalf
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug)]
+pub struct Goal {
+    pub goal_id: U64,
+    /// The token the agent may spend to realize this goal
+    pub target_token: AccountId,
+    pub max_amount: U128,
+    /// What remains of `max_amount` after previous executions
+    pub remaining_amount: U128,
+    /// Tokens which the agent may move in addition to the target token, i.e. the swap outputs
+    pub allowed_destination_tokens: Vec<AccountId>,
+    /// Block timestamp (in nanoseconds) after which the goal can no longer be executed
+    pub expires_at: U64,
+    pub max_executions: u32,
+    pub executions: u32,
+    /// Optional bounds on the oracle price of the target token, checked before each signature
+    pub min_price: Option<Price>,
+    pub max_price: Option<Price>,
+    /// Optional bound, in basis points, on how much less a token_diff intent may receive than it
+    /// spends, valued at the oracle prices
+    pub max_slippage_bps: Option<u32>,
+    /// Optional minimum time (in nanoseconds) between two executions of the goal
+    pub cooldown_ns: Option<U64>,
+    /// Block timestamp (in nanoseconds) of the last signature request which spent from the goal
+    pub last_executed_at: Option<U64>,
+}
+
+#[near]
+impl TradingAccountContract {
+    pub fn create_goal(
+        &mut self,
+        target_token: AccountId,
+        max_amount: U128,
+        allowed_destination_tokens: Vec<AccountId>,
+        expires_at: U64,
+        max_executions: u32,
+    ) -> U64 {
+        self.assert_owner();
+
+        assert!(max_amount.0 > 0, "max_amount must be greater than zero");
+        assert!(
+            max_executions > 0,
+            "max_executions must be greater than zero"
+        );
+        assert!(
+            expires_at.0 > env::block_timestamp(),
+            "expires_at must be in the future"
+        );
+        assert!(
+            !allowed_destination_tokens.is_empty(),
+            "At least one allowed destination token is required"
+        );
+
+        // The id is reserved up front so it can be returned even if creation is scheduled
+        let goal_id = self.next_goal_id;
+        self.next_goal_id += 1;
+        self.apply_or_schedule(PolicyChange::CreateGoal {
+            goal: Goal {
+                goal_id: U64(goal_id),
+                target_token,
+                max_amount,
+                remaining_amount: max_amount,
+                allowed_destination_tokens,
+                expires_at,
+                max_executions,
+                executions: 0,
+                min_price: None,
+                max_price: None,
+                max_slippage_bps: None,
+                cooldown_ns: None,
+                last_executed_at: None,
+            },
+        });
+        U64(goal_id)
+    }
+
+    /// Cancelling only takes the goal's budget away, so it applies immediately
+    pub fn cancel_goal(&mut self, goal_id: U64) {
+        self.assert_owner_or_manager();
+        assert!(
+            self.goals.get(&goal_id.0).is_some(),
+            "Goal {} does not exist",
+            goal_id.0
+        );
+        self.tighten_or_schedule(PolicyChange::CancelGoal { goal_id }, false);
+    }
+
+    /// Only allow the goal to be executed while the oracle price of its target token is within these bounds
+    pub fn set_goal_price_bounds(
+        &mut self,
+        goal_id: U64,
+        min_price: Option<Price>,
+        max_price: Option<Price>,
+    ) {
+        self.assert_owner_or_manager();
+        let goal = self
+            .goals
+            .get(&goal_id.0)
+            .unwrap_or_else(|| env::panic_str(&format!("Goal {} does not exist", goal_id.0)));
+
+        // Widening or removing a bound lets the goal execute at more prices
+        let loosened = price_bound_loosened(goal.min_price.as_ref(), min_price.as_ref(), true)
+            || price_bound_loosened(goal.max_price.as_ref(), max_price.as_ref(), false);
+        self.tighten_or_schedule(
+            PolicyChange::SetGoalPriceBounds {
+                goal_id,
+                min_price,
+                max_price,
+            },
+            loosened,
+        );
+    }
+
+    /// Only sign token_diff intents for the goal whose received side is worth at least the spent
+    /// side minus `max_slippage_bps` at the oracle prices. Raising or removing the bound is
+    /// subject to the policy change delay.
+    pub fn set_goal_max_slippage(&mut self, goal_id: U64, max_slippage_bps: Option<u32>) {
+        self.assert_owner_or_manager();
+        if let Some(bps) = max_slippage_bps {
+            assert!(
+                bps <= MAX_SLIPPAGE_BPS,
+                "max_slippage_bps cannot exceed {}",
+                MAX_SLIPPAGE_BPS
+            );
+        }
+        let goal = self
+            .goals
+            .get(&goal_id.0)
+            .unwrap_or_else(|| env::panic_str(&format!("Goal {} does not exist", goal_id.0)));
+
+        let loosened = match (goal.max_slippage_bps, max_slippage_bps) {
+            (Some(_), None) => true,
+            (Some(old), Some(new)) => new > old,
+            (None, _) => false,
+        };
+        self.tighten_or_schedule(
+            PolicyChange::SetGoalMaxSlippage {
+                goal_id,
+                max_slippage_bps,
+            },
+            loosened,
+        );
+    }
+
+    /// Leave at least `cooldown_ns` between two executions of the goal. Shortening or removing the
+    /// cooldown is subject to the policy change delay.
+    pub fn set_goal_cooldown(&mut self, goal_id: U64, cooldown_ns: Option<U64>) {
+        self.assert_owner_or_manager();
+        let goal = self
+            .goals
+            .get(&goal_id.0)
+            .unwrap_or_else(|| env::panic_str(&format!("Goal {} does not exist", goal_id.0)));
+
+        let loosened = match (goal.cooldown_ns, cooldown_ns) {
+            (Some(_), None) => true,
+            (Some(old), Some(new)) => new.0 < old.0,
+            (None, _) => false,
+        };
+        self.tighten_or_schedule(
+            PolicyChange::SetGoalCooldown {
+                goal_id,
+                cooldown_ns,
+            },
+            loosened,
+        );
+    }
+
+    pub fn get_goal(&self, goal_id: U64) -> Option<Goal> {
+        self.goals.get(&goal_id.0)
+    }
+
+    pub fn get_goals(&self) -> Vec<Goal> {
+        self.goals.values().collect()
+    }
+}
+
+impl TradingAccountContract {
+    pub(crate) fn insert_goal(&mut self, goal: Goal) {
+        assert!(
+            self.goals.len() < MAX_GOALS,
+            "Maximum number of goals reached:({}). One must be cancelled before adding another.",
+            MAX_GOALS
+        );
+
+        let goal_id = goal.goal_id.0;
+        self.goals.insert(&goal_id, &goal);
+        env::log_str(&format!("Goal {} created", goal_id));
+    }
+
+    pub(crate) fn update_goal_price_bounds(
+        &mut self,
+        goal_id: u64,
+        min_price: Option<Price>,
+        max_price: Option<Price>,
+    ) {
+        let mut goal = self
+            .goals
+            .get(&goal_id)
+            .unwrap_or_else(|| env::panic_str(&format!("Goal {} does not exist", goal_id)));
+        goal.min_price = min_price;
+        goal.max_price = max_price;
+        self.goals.insert(&goal_id, &goal);
+    }
+
+    pub(crate) fn update_goal_max_slippage(&mut self, goal_id: u64, max_slippage_bps: Option<u32>) {
+        let mut goal = self
+            .goals
+            .get(&goal_id)
+            .unwrap_or_else(|| env::panic_str(&format!("Goal {} does not exist", goal_id)));
+        goal.max_slippage_bps = max_slippage_bps;
+        self.goals.insert(&goal_id, &goal);
+    }
+
+    pub(crate) fn update_goal_cooldown(&mut self, goal_id: u64, cooldown_ns: Option<U64>) {
+        let mut goal = self
+            .goals
+            .get(&goal_id)
+            .unwrap_or_else(|| env::panic_str(&format!("Goal {} does not exist", goal_id)));
+        goal.cooldown_ns = cooldown_ns;
+        self.goals.insert(&goal_id, &goal);
+    }
+
+    /// Check the token movements of a signature request against the cited goal, then consume one
+    /// execution and the target token amount from the goal's budget. Returns the amount consumed.
+    /// Only the target token may leave the account; the goal's destination tokens may only be
+    /// received, as the `received_token` of a swap.
+    pub(crate) fn spend_goal_budget(
+        &mut self,
+        goal_id: u64,
+        movements: &[TokenMovement],
+        received_token: Option<&AccountId>,
+    ) -> Result<u128, String> {
+        let mut goal = self
+            .goals
+            .get(&goal_id)
+            .ok_or(format!("Goal {} does not exist", goal_id))?;
+
+        if env::block_timestamp() >= goal.expires_at.0 {
+            return Err(format!("Goal {} has expired", goal_id));
+        }
+        if goal.executions >= goal.max_executions {
+            return Err(format!(
+                "Goal {} has reached its maximum of {} executions",
+                goal_id, goal.max_executions
+            ));
+        }
+        if let (Some(cooldown), Some(last)) = (goal.cooldown_ns, goal.last_executed_at) {
+            let ready_at = last.0.saturating_add(cooldown.0);
+            if env::block_timestamp() < ready_at {
+                return Err(format!(
+                    "Goal {} is cooling down until {}",
+                    goal_id, ready_at
+                ));
+            }
+        }
+
+        let mut spent: u128 = 0;
+        for movement in movements {
+            if movement.token != goal.target_token {
+                return Err(format!(
+                    "Goal {} only permits spending {}, not {}",
+                    goal_id, goal.target_token, movement.token
+                ));
+            }
+            spent = spent
+                .checked_add(movement.amount)
+                .ok_or("Token amount overflow")?;
+        }
+        if let Some(token) = received_token {
+            if !goal.allowed_destination_tokens.contains(token) {
+                return Err(format!(
+                    "Token {} is not permitted by goal {}",
+                    token, goal_id
+                ));
+            }
+        }
+
+        if spent > goal.remaining_amount.0 {
+            return Err(format!(
+                "Amount {} of {} exceeds the remaining budget of {} for goal {}",
+                spent, goal.target_token, goal.remaining_amount.0, goal_id
+            ));
+        }
+
+        goal.remaining_amount = U128(goal.remaining_amount.0 - spent);
+        goal.executions += 1;
+        goal.last_executed_at = Some(U64(env::block_timestamp()));
+        self.goals.insert(&goal_id, &goal);
+        Ok(spent)
+    }
+
+    /// The goal's last execution, to be handed back to `refund_goal_budget` should the request
+    /// spending its budget fail
+    pub(crate) fn goal_last_execution(&self, goal_id: u64) -> Option<U64> {
+        self.goals
+            .get(&goal_id)
+            .and_then(|goal| goal.last_executed_at)
+    }
+
+    /// Return budget consumed by a signature request, made at `requested_at`, which never
+    /// produced a signed transaction. Its execution no longer counts towards the cooldown unless
+    /// a later request has executed the goal since.
+    pub(crate) fn refund_goal_budget(
+        &mut self,
+        goal_id: u64,
+        amount: u128,
+        requested_at: u64,
+        previous_execution: Option<U64>,
+    ) {
+        if let Some(mut goal) = self.goals.get(&goal_id) {
+            goal.remaining_amount = U128(
+                goal.remaining_amount
+                    .0
+                    .saturating_add(amount)
+                    .min(goal.max_amount.0),
+            );
+            goal.executions = goal.executions.saturating_sub(1);
+            if goal.last_executed_at == Some(U64(requested_at)) {
+                goal.last_executed_at = previous_execution;
+            }
+            self.goals.insert(&goal_id, &goal);
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use near_sdk::json_types::U64;
+use near_sdk::{AccountId, env, near};
+
+use crate::policy_changes::PolicyChange;
+use crate::{TradingAccountContract, TradingAccountContractExt};
+
+const MAX_GUARDIANS: u64 = 5; // Maximum number of guardians per trading account
+pub const DEFAULT_RECOVERY_DELAY_NS: u64 = 3 * 24 * 60 * 60 * 1_000_000_000; // 3 days
+
+/// A guardian-initiated rotation of the trading account's owner
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug)]
+pub struct OwnerRecovery {
+    pub new_owner_id: AccountId,
+    pub approvals: Vec<AccountId>,
+    /// Block timestamp (in nanoseconds) at which the first guardian approved the recovery
+    pub started_at: U64,
+    /// Block timestamp (in nanoseconds) from which the recovery may be executed. Set once the
+    /// recovery threshold is reached.
+    pub executable_from: Option<U64>,
+}
+
+#[near]
+impl TradingAccountContract {
+    /// Guardians may cancel pending policy changes and, once a recovery threshold is set, rotate
+    /// the owner. Adding one is therefore subject to the policy change delay.
+    pub fn add_guardian(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        assert_ne!(account_id, self.owner_id, "The owner cannot be a guardian");
+        self.apply_or_schedule(PolicyChange::AddGuardian { account_id });
+    }
+
+    /// Removing a guardian weakens the account's protection, so it is subject to the policy change delay
+    pub fn remove_guardian(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.apply_or_schedule(PolicyChange::RemoveGuardian { account_id });
+    }
+
+    pub fn get_guardians(&self) -> Vec<AccountId> {
+        self.guardians.to_vec()
+    }
+
+    /// Set how many guardian approvals are required to rotate the owner. Zero disables recovery.
+    /// Enabling recovery or lowering the threshold is subject to the policy change delay.
+    pub fn set_recovery_threshold(&mut self, threshold: u32) {
+        self.assert_owner();
+        assert!(
+            threshold as u64 <= self.guardians.len(),
+            "Recovery threshold cannot exceed the number of guardians ({})",
+            self.guardians.len()
+        );
+
+        let loosening =
+            threshold != 0 && (self.recovery_threshold == 0 || threshold < self.recovery_threshold);
+        if loosening {
+            self.apply_or_schedule(PolicyChange::SetRecoveryThreshold { threshold });
+        } else {
+            self.recovery_threshold = threshold;
+            if threshold == 0 {
+                self.owner_recovery = None;
+            }
+        }
+    }
+
+    /// Set how long (in nanoseconds) the owner has to veto a recovery once enough guardians approved it.
+    /// Lowering the delay is subject to the policy change delay.
+    pub fn set_recovery_delay(&mut self, delay_ns: U64) {
+        self.assert_owner();
+        if delay_ns.0 >= self.recovery_delay_ns {
+            self.recovery_delay_ns = delay_ns.0;
+        } else {
+            self.apply_or_schedule(PolicyChange::SetRecoveryDelay { delay_ns });
+        }
+    }
+
+    pub fn get_recovery_threshold(&self) -> u32 {
+        self.recovery_threshold
+    }
+
+    pub fn get_recovery_delay(&self) -> U64 {
+        U64(self.recovery_delay_ns)
+    }
+
+    pub fn get_owner_recovery(&self) -> Option<OwnerRecovery> {
+        self.owner_recovery.clone()
+    }
+
+    /// Approve rotating the owner to `new_owner_id`. The first approval starts the recovery.
+    pub fn approve_owner_recovery(&mut self, new_owner_id: AccountId) {
+        let guardian = env::predecessor_account_id();
+        assert!(
+            self.is_guardian(&guardian),
+            "Only a guardian can approve an owner recovery."
+        );
+        assert!(
+            self.recovery_threshold > 0,
+            "Owner recovery is not enabled for this account"
+        );
+        assert_ne!(
+            new_owner_id, self.owner_id,
+            "{} is already the owner",
+            new_owner_id
+        );
+
+        let mut recovery = match self.owner_recovery.take() {
+            Some(recovery) if recovery.new_owner_id == new_owner_id => recovery,
+            Some(recovery) => {
+                env::panic_str(&format!(
+                    "A recovery to {} is already in progress",
+                    recovery.new_owner_id
+                ));
+            }
+            None => OwnerRecovery {
+                new_owner_id,
+                approvals: vec![],
+                started_at: U64(env::block_timestamp()),
+                executable_from: None,
+            },
+        };
+
+        if !recovery.approvals.contains(&guardian) {
+            recovery.approvals.push(guardian);
+        }
+        if recovery.executable_from.is_none()
+            && self.count_recovery_approvals(&recovery) >= self.recovery_threshold
+        {
+            let executable_from = env::block_timestamp().saturating_add(self.recovery_delay_ns);
+            recovery.executable_from = Some(U64(executable_from));
+            env::log_str(&format!(
+                "Owner recovery to {} approved, executable from {}",
+                recovery.new_owner_id, executable_from
+            ));
+        }
+        self.owner_recovery = Some(recovery);
+    }
+
+    /// Rotate the owner once enough guardians approved the recovery and the veto window has passed.
+    /// Callable by anyone.
+    pub fn execute_owner_recovery(&mut self) {
+        let recovery = self
+            .owner_recovery
+            .clone()
+            .unwrap_or_else(|| env::panic_str("No owner recovery is in progress"));
+        // Guardians removed since approving no longer count towards the threshold
+        assert!(
+            self.recovery_threshold > 0
+                && self.count_recovery_approvals(&recovery) >= self.recovery_threshold,
+            "Owner recovery does not have enough guardian approvals"
+        );
+        let executable_from = recovery
+            .executable_from
+            .expect("Internal bug: approved recovery without an executable_from");
+        assert!(
+            env::block_timestamp() >= executable_from.0,
+            "Owner recovery cannot be executed until {}",
+            executable_from.0
+        );
+
+        env::log_str(&format!(
+            "Owner rotated from {} to {} by guardian recovery",
+            self.owner_id, recovery.new_owner_id
+        ));
+        self.transfer_ownership(recovery.new_owner_id);
+    }
+
+    /// Veto an in-progress owner recovery
+    pub fn cancel_owner_recovery(&mut self) {
+        self.assert_owner();
+        assert!(
+            self.owner_recovery.take().is_some(),
+            "No owner recovery is in progress"
+        );
+        env::log_str("Owner recovery vetoed by the owner");
+    }
+}
+
+impl TradingAccountContract {
+    pub(crate) fn insert_guardian(&mut self, account_id: AccountId) {
+        assert!(
+            self.guardians.len() < MAX_GUARDIANS,
+            "Maximum number of guardians reached:({}). One must be removed before adding another.",
+            MAX_GUARDIANS
+        );
+
+        self.guardians.insert(&account_id);
+    }
+
+    pub(crate) fn is_guardian(&self, account_id: &AccountId) -> bool {
+        self.guardians.contains(account_id)
+    }
+
+    pub(crate) fn assert_owner_or_guardian(&self) {
+        let caller = env::predecessor_account_id();
+        assert!(
+            caller == self.owner_id || self.is_guardian(&caller),
+            "Only the owner or a guardian can perform this action."
+        );
+    }
+
+    fn count_recovery_approvals(&self, recovery: &OwnerRecovery) -> u32 {
+        recovery
+            .approvals
+            .iter()
+            .filter(|guardian| self.is_guardian(guardian))
+            .count() as u32
+    }
+}
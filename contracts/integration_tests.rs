@@ -7,6 +7,8 @@ mod contract_tests {
     use serde_json::json;
 
     const WASM_FILEPATH: &[u8] = include_bytes!("target/near/proxy_contract.wasm");
+    const PRICE_ORACLE_MOCK_WASM_FILEPATH: &[u8] =
+        include_bytes!("price_oracle_mock/target/near/price_oracle_mock.wasm");
 
     async fn init(worker: &Worker<impl DevNetwork>) -> Result<(Contract, Account)> {
         let proxy_contract = worker.dev_deploy(WASM_FILEPATH).await?;
@@ -74,6 +76,12 @@ mod contract_tests {
             .transact()
             .await?;
 
+        // The user completes the authorization from its own account
+        let _ = new_user
+            .call(contract.id(), "accept_authorization")
+            .transact()
+            .await?;
+
         // Verify the user is authorized
         let is_authorized = contract
             .call("is_authorized")
@@ -102,6 +110,10 @@ mod contract_tests {
             }))
             .transact()
             .await?;
+        let _ = user
+            .call(contract.id(), "accept_authorization")
+            .transact()
+            .await?;
 
         // Remove authorization
         let _ = contract
@@ -144,6 +156,7 @@ mod contract_tests {
                 "block_hash": "11111111111111111111111111111111",
                 "mpc_signer_pk":"ed25519:asdf".to_string(),
                 "derivation_path": "agent.auth-factory.appaccount.testnet".to_string(),
+                "goal_id": "0",
 
             }))
             .gas(near_workspaces::types::Gas::from_tgas(200))
@@ -185,6 +198,12 @@ mod contract_tests {
             )
             .transact()
             .await?;
+        for user in [&user1, &user2] {
+            let _ = user
+                .call(contract.id(), "accept_authorization")
+                .transact()
+                .await?;
+        }
 
         // Get all authorized users
         let authorized_users = contract
@@ -197,4 +216,91 @@ mod contract_tests {
         assert!(authorized_users.contains(&user2.id().to_string()));
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_request_signature_rejected_by_price_guard() -> Result<()> {
+        let worker = near_workspaces::sandbox().await?;
+        let (contract, _owner) = init(&worker).await?;
+        let agent = worker.dev_create_account().await?;
+
+        // Stand-in for priceoracle.near reporting 1 NEAR = $2.50
+        let oracle = worker.dev_deploy(PRICE_ORACLE_MOCK_WASM_FILEPATH).await?;
+        let _ = oracle.call("new").transact().await?;
+        let _ = oracle
+            .call("set_price")
+            .args_json(json!({
+                "asset_id": "wrap.testnet",
+                "price": { "multiplier": "25000", "decimals": 28 }
+            }))
+            .transact()
+            .await?;
+
+        // Only allow the goal to execute once NEAR reaches $3.00
+        let _ = contract
+            .batch()
+            .call(Function::new("set_price_oracle").args_json(json!({ "oracle_id": oracle.id() })))
+            .call(
+                Function::new("add_authorized_user").args_json(json!({ "account_id": agent.id() })),
+            )
+            .call(Function::new("create_goal").args_json(json!({
+                "target_token": "wrap.testnet",
+                "max_amount": "1000000000000000000000000",
+                "allowed_destination_tokens": ["usdc.fakes.testnet"],
+                "expires_at": u64::MAX.to_string(),
+                "max_executions": 1
+            })))
+            .call(Function::new("set_goal_price_bounds").args_json(json!({
+                "goal_id": "0",
+                "min_price": { "multiplier": "30000", "decimals": 28 },
+                "max_price": null
+            })))
+            .transact()
+            .await?;
+        let _ = agent
+            .call(contract.id(), "accept_authorization")
+            .transact()
+            .await?;
+
+        let result = agent
+            .call(contract.id(), "request_signature")
+            .args_json(json!({
+                "contract_id": "wrap.testnet",
+                "actions_json": "[{\"type\":\"FunctionCall\", \"deposit\": \"50000000000000000000000\", \"gas\": \"30000000000000\", \"method_name\": \"near_deposit\", \"args\": {}}]",
+                "nonce": "1",
+                "block_hash": "11111111111111111111111111111111",
+                "mpc_signer_pk": "ed25519:11111111111111111111111111111111",
+                "derivation_path": "agent.auth-factory.appaccount.testnet",
+                "goal_id": "0",
+            }))
+            .gas(near_workspaces::types::Gas::from_tgas(300))
+            .transact()
+            .await?;
+
+        assert!(result.is_success(), "{:?}", result.failures());
+        assert_eq!(result.json::<Option<String>>()?, None);
+
+        let request = contract
+            .call("get_signature_request")
+            .args_json(json!({ "request_id": "0" }))
+            .view()
+            .await?
+            .json::<serde_json::Value>()?;
+        let status = request["status"].to_string();
+        assert!(
+            status.contains("is below the minimum price"),
+            "Expected price guard rejection, got: {}",
+            status
+        );
+
+        // The rejected request must not consume the goal's budget
+        let goal = contract
+            .call("get_goal")
+            .args_json(json!({ "goal_id": "0" }))
+            .view()
+            .await?
+            .json::<serde_json::Value>()?;
+        assert_eq!(goal["remaining_amount"], "1000000000000000000000000");
+        assert_eq!(goal["executions"], 0);
+        Ok(())
+    }
 }
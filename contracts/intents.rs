@@ -0,0 +1,404 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use near_sdk::borsh::{self, BorshSerialize};
+use near_sdk::json_types::{Base64VecU8, U64, U128};
+use near_sdk::serde::Deserialize;
+use near_sdk::{AccountId, PromiseError, PromiseOrValue, env, near, serde_json};
+
+use crate::actions::{BalanceSource, BalanceSpend, TokenMovement};
+use crate::price_oracle::{Price, PriceData};
+use crate::signature_requests::{RequestReservation, SignatureRequestStatus, SignedPayload};
+use crate::{
+    NEAR_INTENTS_ADDRESS, SignatureResponse, TradingAccountContract, TradingAccountContractExt,
+    parse_mpc_response, utils,
+};
+
+// NEP-413 messages are prefixed with 2^31 + 413 so they can never be a valid transaction
+const NEP413_TAG: u32 = (1 << 31) + 413;
+const MAX_BASIS_POINTS: u128 = 10_000;
+
+/// The swap described by a token_diff intent, together with the slippage its goal tolerates
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwapQuote {
+    pub spent_token: AccountId,
+    pub spent_amount: U128,
+    pub received_token: AccountId,
+    pub received_amount: U128,
+    pub max_slippage_bps: u32,
+}
+
+#[derive(BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+struct Nep413Payload {
+    message: String,
+    nonce: [u8; 32],
+    recipient: String,
+    callback_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct IntentMessage {
+    signer_id: AccountId,
+    intents: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct TokenDiffIntent {
+    intent: String,
+    diff: BTreeMap<String, String>,
+}
+
+#[near]
+impl TradingAccountContract {
+    /// Request an MPC signature over a NEP-413 message for intents.near. Only messages holding a
+    /// single token_diff intent which spends one allowed NEP-141 token for another are signed.
+    /// The goal must set a maximum slippage: the received side is valued against the spent side
+    /// at the oracle prices, so the output floor never comes from the agent.
+    #[payable]
+    pub fn request_intent_signature(
+        &mut self,
+        message: String,
+        nonce: Base64VecU8,
+        derivation_path: String,
+        domain_id: Option<u32>,
+        goal_id: U64,
+    ) -> PromiseOrValue<Option<String>> {
+        self.assert_can_request_signature();
+
+        if let Err(e) = self.check_trading_window(&env::predecessor_account_id()) {
+            return self.reject_agent_request(format!("Trading window check failed: {}", e));
+        }
+        let Ok(nonce) = <[u8; 32]>::try_from(nonce.0) else {
+            return self.reject_agent_request("The NEP-413 nonce must be 32 bytes".to_string());
+        };
+
+        let mut swap = match parse_token_diff(&message) {
+            Ok(swap) => swap,
+            Err(e) => {
+                return self.reject_agent_request(format!("Invalid token_diff intent: {}", e));
+            }
+        };
+        if let Err(e) = self.check_intent_swap(&swap) {
+            return self.reject_agent_request(e);
+        }
+        if let Err(e) = self.check_intent_policy_rules(&message, &swap) {
+            return self.reject_agent_request(format!("Policy rule check failed: {}", e));
+        }
+
+        let mut oracle_assets: Vec<AccountId> = match self.goal_price_guard(goal_id.0) {
+            Ok(token) => token.into_iter().collect(),
+            Err(e) => return self.reject_agent_request(format!("Goal validation failed: {}", e)),
+        };
+        let Some(goal) = self.goals.get(&goal_id.0) else {
+            return self.reject_agent_request(format!(
+                "Goal validation failed: Goal {} does not exist",
+                goal_id.0
+            ));
+        };
+        let Some(max_slippage_bps) = goal.max_slippage_bps else {
+            return self.reject_agent_request(format!(
+                "Goal validation failed: Goal {} has no maximum slippage to price intents against",
+                goal_id.0
+            ));
+        };
+        if self.price_oracle_id.is_none() {
+            return self.reject_agent_request(format!(
+                "Goal validation failed: Goal {} has a maximum slippage but no price oracle is configured",
+                goal_id.0
+            ));
+        }
+
+        // The received token only has to be permitted by the goal, it does not consume budget.
+        // The budget is spent last, once nothing else can reject the request.
+        let movements = [TokenMovement {
+            token: swap.spent_token.clone(),
+            amount: swap.spent_amount.0,
+        }];
+        let previous_goal_execution = self.goal_last_execution(goal_id.0);
+        let goal_spend =
+            match self.spend_goal_budget(goal_id.0, &movements, Some(&swap.received_token)) {
+                Ok(spent) => spent,
+                Err(e) => {
+                    return self.reject_agent_request(format!("Goal validation failed: {}", e));
+                }
+            };
+
+        let balance_checks = if self.has_balance_limits() || self.balance_preflight_enabled {
+            vec![intents_spend(&swap)]
+        } else {
+            vec![]
+        };
+
+        swap.max_slippage_bps = max_slippage_bps;
+        for token in [&swap.spent_token, &swap.received_token] {
+            if !oracle_assets.contains(token) {
+                oracle_assets.push(token.clone());
+            }
+        }
+
+        let payload_hash = nep413_hash(message, nonce);
+        let request_id = self.record_signature_request(
+            NEAR_INTENTS_ADDRESS.clone(),
+            U64(0),
+            U128(0),
+            RequestReservation {
+                goal_id: Some(goal_id),
+                goal_spend: U128(goal_spend),
+                previous_goal_execution,
+                ..Default::default()
+            },
+        );
+        let request_payload =
+            self.create_hash_signature_request(&payload_hash, derivation_path, domain_id);
+
+        PromiseOrValue::Promise(self.start_signature_request(
+            request_id,
+            request_payload,
+            SignedPayload::Intent {
+                payload_hash: hex::encode(payload_hash),
+            },
+            balance_checks,
+            Some(swap),
+            oracle_assets,
+            env::attached_deposit(),
+        ))
+    }
+
+    #[private] // Only callable by the contract itself
+    pub fn sign_intent_callback(
+        &mut self,
+        #[callback_result] call_result: Result<SignatureResponse, PromiseError>,
+        request_id: u64,
+        payload_hash: String,
+    ) -> Option<String> {
+        let hash = hex::decode(&payload_hash)
+            .ok()
+            .and_then(|hash| <[u8; 32]>::try_from(hash).ok())
+            .expect("Internal bug: invalid payload hash");
+        let signature = parse_mpc_response(call_result)
+            .and_then(|response| self.decode_mpc_signature(response, &hash))
+            .map(|signature| format!("secp256k1:{}", bs58::encode(signature).into_string()));
+
+        match signature {
+            Ok(signature) => {
+                self.update_signature_request_status(
+                    request_id,
+                    SignatureRequestStatus::SignedIntent {
+                        signature: signature.clone(),
+                    },
+                );
+                Some(signature)
+            }
+            Err(reason) => {
+                env::log_str(&format!(
+                    "Signature request {} failed: {}",
+                    request_id, reason
+                ));
+                self.update_signature_request_status(
+                    request_id,
+                    SignatureRequestStatus::Failed { reason },
+                );
+                self.record_request_rejection(request_id);
+                None
+            }
+        }
+    }
+}
+
+impl TradingAccountContract {
+    /// Only allowed tokens may be spent by an intent, and only for tokens they may be swapped for
+    pub(crate) fn check_intent_swap(&self, swap: &SwapQuote) -> Result<(), String> {
+        if !self.allowed_tokens.contains(&swap.spent_token) {
+            return Err(format!(
+                "Token {} is not allowed. Allowed tokens: {:?}",
+                swap.spent_token,
+                self.allowed_tokens.to_vec()
+            ));
+        }
+        self.check_swap_pair(&swap.spent_token, &swap.received_token)
+    }
+}
+
+impl SwapQuote {
+    /// Ensure the received side is worth at least the spent side minus the maximum slippage
+    pub fn check_slippage(&self, price_data: &PriceData) -> Result<(), String> {
+        let price_of = |token: &AccountId| {
+            price_data
+                .price_of(token)
+                .ok_or(format!("Oracle has no price for {}", token))
+        };
+        let spent_price = price_of(&self.spent_token)?;
+        let received_price = price_of(&self.received_token)?;
+
+        let tolerated = MAX_BASIS_POINTS.saturating_sub(self.max_slippage_bps as u128);
+        let ordering = compare_values(
+            (self.received_amount.0, received_price, MAX_BASIS_POINTS),
+            (self.spent_amount.0, spent_price, tolerated),
+        )?;
+        if ordering == Ordering::Less {
+            return Err(format!(
+                "Slippage check failed: {} {} received for {} {} exceeds the maximum slippage of {} bps",
+                self.received_amount.0,
+                self.received_token,
+                self.spent_amount.0,
+                self.spent_token,
+                self.max_slippage_bps
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Parse a NEP-413 message for intents.near which must hold a single token_diff intent, signed
+/// by the trading account, spending one NEP-141 token for another
+pub(crate) fn parse_token_diff(message: &str) -> Result<SwapQuote, String> {
+    let message: IntentMessage =
+        serde_json::from_str(message).map_err(|e| format!("invalid message: {}", e))?;
+    if message.signer_id != env::current_account_id() {
+        return Err(format!(
+            "signer_id {} is not the trading account",
+            message.signer_id
+        ));
+    }
+    let [intent] = message.intents.as_slice() else {
+        return Err("the message must hold exactly one intent".to_string());
+    };
+    let intent: TokenDiffIntent =
+        serde_json::from_value(intent.clone()).map_err(|e| format!("invalid intent: {}", e))?;
+    if intent.intent != "token_diff" {
+        return Err(format!("{} intents are not allowed", intent.intent));
+    }
+
+    let (spent, received) = split_token_diff(intent.diff)?;
+    let ([(spent_token, spent_amount)], [(received_token, received_amount)]) =
+        (spent.as_slice(), received.as_slice())
+    else {
+        return Err("the diff must spend exactly one token and receive exactly one".to_string());
+    };
+
+    Ok(SwapQuote {
+        spent_token: spent_token.clone(),
+        spent_amount: *spent_amount,
+        received_token: received_token.clone(),
+        received_amount: *received_amount,
+        max_slippage_bps: 0,
+    })
+}
+
+/// The (from, to) token pairs swapped by the token_diff intents of an intents.near message, which
+/// spend every negative side of a diff for every positive side
+pub(crate) fn token_diff_pairs(message: &str) -> Result<Vec<(AccountId, AccountId)>, String> {
+    let message: IntentMessage =
+        serde_json::from_str(message).map_err(|e| format!("invalid message: {}", e))?;
+
+    let mut pairs = Vec::new();
+    for intent in message.intents {
+        let Ok(intent) = serde_json::from_value::<TokenDiffIntent>(intent) else {
+            continue;
+        };
+        if intent.intent != "token_diff" {
+            continue;
+        }
+        let (spent, received) = split_token_diff(intent.diff)?;
+        for (from, _) in &spent {
+            for (to, _) in &received {
+                pairs.push((from.clone(), to.clone()));
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+type TokenAmounts = Vec<(AccountId, U128)>;
+
+/// Split a token diff into the NEP-141 tokens it spends and those it receives
+fn split_token_diff(
+    diff: BTreeMap<String, String>,
+) -> Result<(TokenAmounts, TokenAmounts), String> {
+    let mut spent = Vec::new();
+    let mut received = Vec::new();
+    for (token_id, amount) in diff {
+        let token = token_id
+            .strip_prefix("nep141:")
+            .and_then(|token| token.parse::<AccountId>().ok())
+            .ok_or(format!("{} is not a NEP-141 token id", token_id))?;
+        let amount: i128 = amount
+            .parse()
+            .map_err(|_| format!("invalid amount {} for {}", amount, token_id))?;
+        match amount.cmp(&0) {
+            Ordering::Less => spent.push((token, U128(amount.unsigned_abs()))),
+            Ordering::Greater => received.push((token, U128(amount as u128))),
+            Ordering::Equal => {}
+        }
+    }
+    Ok((spent, received))
+}
+
+pub(crate) fn intents_spend(swap: &SwapQuote) -> BalanceSpend {
+    BalanceSpend {
+        source: BalanceSource::Intents {
+            token_id: format!("nep141:{}", swap.spent_token),
+        },
+        amount: swap.spent_amount,
+    }
+}
+
+/// The sha256 hash of a NEP-413 payload addressed to intents.near
+pub(crate) fn nep413_hash(message: String, nonce: [u8; 32]) -> [u8; 32] {
+    let payload = Nep413Payload {
+        message,
+        nonce,
+        recipient: NEAR_INTENTS_ADDRESS.to_string(),
+        callback_url: None,
+    };
+    let mut bytes = borsh::to_vec(&NEP413_TAG).expect("Internal bug: failed to serialize tag");
+    bytes.extend(borsh::to_vec(&payload).expect("Internal bug: failed to serialize payload"));
+    utils::hash_payload(&bytes)
+}
+
+/// Compare `amount * price * factor` of two sides without overflowing
+fn compare_values(
+    (a_amount, a_price, a_factor): (u128, &Price, u128),
+    (b_amount, b_price, b_factor): (u128, &Price, u128),
+) -> Result<Ordering, String> {
+    let overflow = || "Value overflow while comparing prices".to_string();
+    // Bring both prices to the same decimals
+    let scale = |decimals: u8, other: u8| {
+        10u128
+            .checked_pow(other.saturating_sub(decimals) as u32)
+            .ok_or_else(overflow)
+    };
+    let a_scale = scale(a_price.decimals, b_price.decimals)?;
+    let b_scale = scale(b_price.decimals, a_price.decimals)?;
+
+    let a_value = a_amount
+        .checked_mul(a_price.multiplier.0)
+        .ok_or_else(overflow)?;
+    let b_value = b_amount
+        .checked_mul(b_price.multiplier.0)
+        .ok_or_else(overflow)?;
+    let a_factor = a_factor.checked_mul(a_scale).ok_or_else(overflow)?;
+    let b_factor = b_factor.checked_mul(b_scale).ok_or_else(overflow)?;
+    Ok(widening_mul(a_value, a_factor).cmp(&widening_mul(b_value, b_factor)))
+}
+
+/// The 256 bit product of two u128 values, as (high, low) halves
+fn widening_mul(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+    let (a_hi, a_lo) = (a >> 64, a & MASK);
+    let (b_hi, b_lo) = (b >> 64, b & MASK);
+
+    let lo_lo = a_lo * b_lo;
+    let hi_lo = a_hi * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let hi_hi = a_hi * b_hi;
+
+    let cross = (lo_lo >> 64) + (hi_lo & MASK) + (lo_hi & MASK);
+    let low = (cross << 64) | (lo_lo & MASK);
+    let high = hi_hi + (hi_lo >> 64) + (lo_hi >> 64) + (cross >> 64);
+    (high, low)
+}
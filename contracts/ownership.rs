@@ -0,0 +1,72 @@
+use near_sdk::{AccountId, env, near};
+
+use crate::events::TradingAccountEvent;
+use crate::{TradingAccountContract, TradingAccountContractExt};
+
+#[near]
+impl TradingAccountContract {
+    /// Propose handing the trading account over to `new_owner_id`, who must accept it from their
+    /// own account. Proposing again replaces any previous proposal.
+    pub fn propose_owner(&mut self, new_owner_id: AccountId) {
+        self.assert_owner();
+        assert_ne!(
+            new_owner_id, self.owner_id,
+            "{} is already the owner",
+            new_owner_id
+        );
+
+        self.proposed_owner_id = Some(new_owner_id.clone());
+        TradingAccountEvent::OwnerProposed {
+            owner_id: self.owner_id.clone(),
+            proposed_owner_id: new_owner_id,
+        }
+        .emit();
+    }
+
+    /// Complete an ownership transfer. Only callable by the proposed owner.
+    pub fn accept_ownership(&mut self) {
+        let caller = env::predecessor_account_id();
+        assert!(
+            self.proposed_owner_id.as_ref() == Some(&caller),
+            "Only the proposed owner can accept ownership"
+        );
+
+        self.transfer_ownership(caller);
+    }
+
+    pub fn cancel_owner_proposal(&mut self) {
+        self.assert_owner();
+        let proposed_owner_id = self
+            .proposed_owner_id
+            .take()
+            .unwrap_or_else(|| env::panic_str("No ownership transfer has been proposed"));
+
+        TradingAccountEvent::OwnerProposalCancelled {
+            owner_id: self.owner_id.clone(),
+            proposed_owner_id,
+        }
+        .emit();
+    }
+
+    pub fn get_proposed_owner(&self) -> Option<AccountId> {
+        self.proposed_owner_id.clone()
+    }
+}
+
+impl TradingAccountContract {
+    /// Hand the account over to `new_owner_id`, dropping any handover still in flight
+    pub(crate) fn transfer_ownership(&mut self, new_owner_id: AccountId) {
+        self.proposed_owner_id = None;
+        self.owner_recovery = None;
+        // The owner cannot also be one of its own guardians
+        self.guardians.remove(&new_owner_id);
+        self.roles.remove(&new_owner_id);
+
+        let old_owner_id = std::mem::replace(&mut self.owner_id, new_owner_id.clone());
+        TradingAccountEvent::OwnershipTransferred {
+            old_owner_id,
+            new_owner_id,
+        }
+        .emit();
+    }
+}
@@ -0,0 +1,279 @@
+use near_sdk::json_types::U64;
+use near_sdk::{AccountId, NearToken, env, near};
+
+use crate::{
+    BalanceLimits, CircuitBreaker, DcaSchedule, Goal, PolicyRule, Price, Role, TokenPair,
+    TradingAccountContract, TradingAccountContractExt, TradingWindow,
+};
+
+/// A change to the account's policy. When a policy change delay is configured, changes which loosen
+/// the policy are scheduled rather than applied immediately, leaving the owner or a guardian time to
+/// cancel them.
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug)]
+pub enum PolicyChange {
+    AddAuthorizedUser {
+        account_id: AccountId,
+    },
+    AddAllowedToken {
+        token_id: AccountId,
+    },
+    CreateGoal {
+        goal: Goal,
+    },
+    SetGoalPriceBounds {
+        goal_id: U64,
+        min_price: Option<Price>,
+        max_price: Option<Price>,
+    },
+    SetPriceOracle {
+        oracle_id: AccountId,
+    },
+    SetAddressBookDelay {
+        delay_ns: U64,
+    },
+    SetPolicyChangeDelay {
+        delay_ns: U64,
+    },
+    AddGuardian {
+        account_id: AccountId,
+    },
+    RemoveGuardian {
+        account_id: AccountId,
+    },
+    SetRecoveryThreshold {
+        threshold: u32,
+    },
+    SetRecoveryDelay {
+        delay_ns: U64,
+    },
+    SetMinBalanceReserve {
+        reserve: NearToken,
+    },
+    SetBalanceLimits {
+        limits: BalanceLimits,
+    },
+    SetGoalMaxSlippage {
+        goal_id: U64,
+        max_slippage_bps: Option<u32>,
+    },
+    AddAllowedPair {
+        pair: TokenPair,
+    },
+    AddPolicyRule {
+        rule: PolicyRule,
+        before_rule_id: Option<U64>,
+    },
+    RemovePolicyRule {
+        rule_id: U64,
+    },
+    GrantManagerRole {
+        account_id: AccountId,
+    },
+    SetCircuitBreaker {
+        breaker: CircuitBreaker,
+    },
+    SetAgentSchedule {
+        account_id: AccountId,
+        windows: Option<Vec<TradingWindow>>,
+    },
+    SetGoalCooldown {
+        goal_id: U64,
+        cooldown_ns: Option<U64>,
+    },
+    CreateDcaSchedule {
+        schedule: DcaSchedule,
+    },
+    CancelGoal {
+        goal_id: U64,
+    },
+    ClearPriceOracle,
+}
+
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug)]
+pub struct PendingPolicyChange {
+    pub change_id: U64,
+    pub change: PolicyChange,
+    /// Block timestamp (in nanoseconds) at which the change was proposed
+    pub proposed_at: U64,
+    /// Block timestamp (in nanoseconds) from which the change may be executed
+    pub executable_from: U64,
+}
+
+#[near]
+impl TradingAccountContract {
+    /// Apply a scheduled policy change once its delay has passed. Callable by anyone, as the owner has
+    /// already approved the change.
+    pub fn execute_policy_change(&mut self, change_id: U64) {
+        let pending = self
+            .pending_policy_changes
+            .get(&change_id.0)
+            .unwrap_or_else(|| {
+                env::panic_str(&format!("Policy change {} does not exist", change_id.0))
+            });
+        assert!(
+            env::block_timestamp() >= pending.executable_from.0,
+            "Policy change {} cannot be executed until {}",
+            change_id.0,
+            pending.executable_from.0
+        );
+
+        self.pending_policy_changes.remove(&change_id.0);
+        env::log_str(&format!("Executing policy change {}", change_id.0));
+        self.apply_policy_change(pending.change);
+    }
+
+    pub fn cancel_policy_change(&mut self, change_id: U64) {
+        let caller = env::predecessor_account_id();
+        assert!(
+            caller == self.owner_id || self.is_guardian(&caller) || self.is_manager(&caller),
+            "Only the owner, a guardian or a manager can cancel a policy change."
+        );
+        assert!(
+            self.pending_policy_changes.remove(&change_id.0).is_some(),
+            "Policy change {} does not exist",
+            change_id.0
+        );
+        env::log_str(&format!(
+            "Policy change {} cancelled by {}",
+            change_id.0,
+            env::predecessor_account_id()
+        ));
+    }
+
+    /// Set how long (in nanoseconds) loosening policy changes must wait before they can be executed.
+    /// Raising the delay applies immediately, lowering it is itself subject to the current delay.
+    pub fn set_policy_change_delay(&mut self, delay_ns: U64) {
+        self.assert_owner();
+        if delay_ns.0 >= self.policy_change_delay_ns {
+            self.policy_change_delay_ns = delay_ns.0;
+        } else {
+            self.apply_or_schedule(PolicyChange::SetPolicyChangeDelay { delay_ns });
+        }
+    }
+
+    pub fn get_policy_change_delay(&self) -> U64 {
+        U64(self.policy_change_delay_ns)
+    }
+
+    pub fn get_pending_policy_changes(&self) -> Vec<PendingPolicyChange> {
+        self.pending_policy_changes.values().collect()
+    }
+}
+
+impl TradingAccountContract {
+    /// Apply a loosening change right away if no delay is configured, otherwise schedule it
+    pub(crate) fn apply_or_schedule(&mut self, change: PolicyChange) {
+        if self.policy_change_delay_ns == 0 {
+            self.apply_policy_change(change);
+            return;
+        }
+
+        let change_id = self.next_policy_change_id;
+        self.next_policy_change_id += 1;
+
+        let now = env::block_timestamp();
+        let pending = PendingPolicyChange {
+            change_id: U64(change_id),
+            change,
+            proposed_at: U64(now),
+            executable_from: U64(now.saturating_add(self.policy_change_delay_ns)),
+        };
+        env::log_str(&format!(
+            "Policy change {} scheduled, executable from {}: {:?}",
+            change_id, pending.executable_from.0, pending.change
+        ));
+        self.pending_policy_changes.insert(&change_id, &pending);
+    }
+
+    /// Apply a change which tightens the policy, which the owner or a manager may do, or schedule
+    /// one which loosens it, which only the owner may do
+    pub(crate) fn tighten_or_schedule(&mut self, change: PolicyChange, loosened: bool) {
+        self.assert_owner_or_manager();
+        if loosened {
+            // Managers may only tighten the policy
+            self.assert_owner();
+            self.apply_or_schedule(change);
+        } else {
+            self.apply_policy_change(change);
+        }
+    }
+
+    fn apply_policy_change(&mut self, change: PolicyChange) {
+        match change {
+            PolicyChange::AddAuthorizedUser { account_id } => {
+                self.insert_authorized_user(account_id)
+            }
+            PolicyChange::AddAllowedToken { token_id } => self.insert_allowed_token(token_id),
+            PolicyChange::CreateGoal { goal } => self.insert_goal(goal),
+            PolicyChange::SetGoalPriceBounds {
+                goal_id,
+                min_price,
+                max_price,
+            } => self.update_goal_price_bounds(goal_id.0, min_price, max_price),
+            PolicyChange::SetPriceOracle { oracle_id } => self.price_oracle_id = Some(oracle_id),
+            PolicyChange::SetAddressBookDelay { delay_ns } => {
+                self.address_book_delay_ns = delay_ns.0
+            }
+            PolicyChange::SetPolicyChangeDelay { delay_ns } => {
+                self.policy_change_delay_ns = delay_ns.0
+            }
+            PolicyChange::AddGuardian { account_id } => self.insert_guardian(account_id),
+            PolicyChange::RemoveGuardian { account_id } => {
+                self.guardians.remove(&account_id);
+            }
+            PolicyChange::SetRecoveryThreshold { threshold } => self.recovery_threshold = threshold,
+            PolicyChange::SetRecoveryDelay { delay_ns } => self.recovery_delay_ns = delay_ns.0,
+            PolicyChange::SetMinBalanceReserve { reserve } => self.min_balance_reserve = reserve,
+            PolicyChange::SetBalanceLimits { limits } => self.balance_limits = limits,
+            PolicyChange::SetGoalMaxSlippage {
+                goal_id,
+                max_slippage_bps,
+            } => self.update_goal_max_slippage(goal_id.0, max_slippage_bps),
+            PolicyChange::AddAllowedPair { pair } => self.insert_allowed_pair(pair),
+            PolicyChange::AddPolicyRule {
+                rule,
+                before_rule_id,
+            } => self.insert_policy_rule(rule, before_rule_id),
+            PolicyChange::RemovePolicyRule { rule_id } => self.delete_policy_rule(rule_id),
+            PolicyChange::GrantManagerRole { account_id } => {
+                self.insert_role(account_id, Role::Manager)
+            }
+            PolicyChange::SetCircuitBreaker { breaker } => self.circuit_breaker = breaker,
+            PolicyChange::SetAgentSchedule {
+                account_id,
+                windows,
+            } => self.update_agent_schedule(account_id, windows),
+            PolicyChange::SetGoalCooldown {
+                goal_id,
+                cooldown_ns,
+            } => self.update_goal_cooldown(goal_id.0, cooldown_ns),
+            PolicyChange::CreateDcaSchedule { schedule } => self.insert_dca_schedule(schedule),
+            PolicyChange::CancelGoal { goal_id } => {
+                self.goals.remove(&goal_id.0);
+                env::log_str(&format!("Goal {} cancelled", goal_id.0));
+            }
+            PolicyChange::ClearPriceOracle => self.price_oracle_id = None,
+        }
+    }
+}
+
+/// Whether replacing a minimum (`is_min`) or maximum price bound with `new` would admit more prices
+pub(crate) fn price_bound_loosened(old: Option<&Price>, new: Option<&Price>, is_min: bool) -> bool {
+    match (old, new) {
+        (None, _) => false,
+        (Some(_), None) => true,
+        (Some(old), Some(new)) => match new.compare(old) {
+            Ok(ordering) => {
+                if is_min {
+                    ordering.is_lt()
+                } else {
+                    ordering.is_gt()
+                }
+            }
+            // Treat prices which cannot be compared as loosening to err on the side of caution
+            Err(_) => true,
+        },
+    }
+}
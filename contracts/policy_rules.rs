@@ -0,0 +1,403 @@
+use near_sdk::json_types::U64;
+use near_sdk::{AccountId, NearToken, Promise, env, near, serde_json};
+
+use crate::actions::BalanceSpend;
+use crate::intents::SwapQuote;
+use crate::policy_changes::PolicyChange;
+use crate::signature_requests::{SignatureRequestStatus, SignedPayload};
+use crate::{
+    ActionString, NEAR_INTENTS_ADDRESS, TradingAccountContract, TradingAccountContractExt,
+};
+
+const MAX_POLICY_RULES: usize = 20; // Maximum number of policy rules per trading account
+
+/// What happens to a request with an action matched by a rule
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RuleOutcome {
+    /// Stop evaluating later rules for the action. The built-in checks still apply.
+    Allow,
+    /// Reject the request
+    Deny,
+    /// Hold the request until the owner approves it with `approve_signature_request`
+    RequireApproval,
+}
+
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RuleOperator {
+    /// The value at the pointer equals the rule's value. JSON strings are compared without quotes.
+    Eq,
+    Ne,
+    /// Amount comparisons, the value at the pointer and the rule's value are read as u128
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+}
+
+/// A comparison of the value found at a JSON pointer into the call's args, e.g. "/amount"
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuleCondition {
+    pub pointer: String,
+    pub operator: RuleOperator,
+    pub value: String,
+}
+
+/// A rule matching the function call actions of signature requests. Unset fields match anything
+/// and a rule matches when all of its conditions hold. Transfer actions are not matched by rules.
+/// Intent and DCA requests are matched as an `execute_intents` call on intents.near whose args
+/// are the NEP-413 message plus the swap's `spent_token`, `spent_amount`, `received_token` and
+/// `received_amount`, e.g. "/spent_amount". The diff amounts in the message are signed, so
+/// amount comparisons should use these unsigned fields.
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolicyRule {
+    pub contract_id: Option<AccountId>,
+    pub method_name: Option<String>,
+    pub conditions: Vec<RuleCondition>,
+    pub outcome: RuleOutcome,
+}
+
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolicyRuleEntry {
+    pub rule_id: U64,
+    pub rule: PolicyRule,
+}
+
+/// A validated transaction signature request held until the owner approves it
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug)]
+pub struct ApprovalRequest {
+    /// The MPC sign request, serialized to JSON
+    pub request_payload: String,
+    pub tx_json_string: String,
+    pub balance_checks: Vec<BalanceSpend>,
+    pub oracle_assets: Vec<AccountId>,
+    /// The deposit attached by the agent for the MPC signer
+    pub mpc_deposit: NearToken,
+}
+
+#[near]
+impl TradingAccountContract {
+    /// Insert a rule before `before_rule_id`, or at the end. Rules are evaluated in order, so
+    /// anything but a deny rule may let requests through which a later rule would have caught
+    /// and is subject to the policy change delay.
+    pub fn add_policy_rule(&mut self, rule: PolicyRule, before_rule_id: Option<U64>) {
+        self.assert_owner_or_manager();
+        validate_rule(&rule).unwrap_or_else(|e| env::panic_str(&e));
+        let loosened = rule.outcome != RuleOutcome::Deny;
+        self.tighten_or_schedule(
+            PolicyChange::AddPolicyRule {
+                rule,
+                before_rule_id,
+            },
+            loosened,
+        );
+    }
+
+    /// Removing an allow rule only lets later rules apply, other removals are subject to the
+    /// policy change delay
+    pub fn remove_policy_rule(&mut self, rule_id: U64) {
+        self.assert_owner_or_manager();
+        let entry = self
+            .policy_rules
+            .iter()
+            .find(|entry| entry.rule_id == rule_id)
+            .unwrap_or_else(|| {
+                env::panic_str(&format!("Policy rule {} does not exist", rule_id.0))
+            });
+        let loosened = entry.rule.outcome != RuleOutcome::Allow;
+        self.tighten_or_schedule(PolicyChange::RemovePolicyRule { rule_id }, loosened);
+    }
+
+    pub fn get_policy_rules(&self) -> Vec<PolicyRuleEntry> {
+        self.policy_rules.clone()
+    }
+
+    pub fn get_approval_request(&self, request_id: U64) -> Option<ApprovalRequest> {
+        self.approval_requests.get(&request_id.0)
+    }
+
+    /// Request the signature of a request held by a require-approval rule
+    pub fn approve_signature_request(&mut self, request_id: U64) -> Promise {
+        self.assert_owner();
+        assert!(
+            !self.is_closing(),
+            "Signing is paused while the trading account is being closed"
+        );
+        assert!(
+            env::prepaid_gas() >= crate::GAS_FOR_REQUEST_SIGNATURE,
+            "Not enough gas attached. Please attach at least {} TGas",
+            crate::GAS_FOR_REQUEST_SIGNATURE.as_tgas()
+        );
+        let approval = self.take_approval_request(request_id.0);
+        let request_payload = serde_json::from_str(&approval.request_payload)
+            .expect("Internal bug: stored request payload is not valid JSON");
+
+        if let Some(mut request) = self.signature_requests.get(&request_id.0) {
+            request.status = SignatureRequestStatus::Pending;
+            self.signature_requests.insert(&request_id.0, &request);
+        }
+        env::log_str(&format!("Signature request {} approved", request_id.0));
+
+        self.start_signature_request(
+            request_id.0,
+            request_payload,
+            SignedPayload::Transaction {
+                tx_json_string: approval.tx_json_string,
+            },
+            approval.balance_checks,
+            None,
+            approval.oracle_assets,
+            approval.mpc_deposit,
+        )
+    }
+
+    /// Reject a request held by a require-approval rule, refunding the agent's deposit
+    pub fn deny_signature_request(&mut self, request_id: U64) {
+        self.assert_owner();
+        let approval = self.take_approval_request(request_id.0);
+        self.reject_signature_request(
+            request_id.0,
+            "Denied by the owner".to_string(),
+            approval.mpc_deposit,
+        );
+    }
+}
+
+impl TradingAccountContract {
+    pub(crate) fn insert_policy_rule(&mut self, rule: PolicyRule, before_rule_id: Option<U64>) {
+        assert!(
+            self.policy_rules.len() < MAX_POLICY_RULES,
+            "Maximum number of policy rules reached:({}). One must be removed before adding another.",
+            MAX_POLICY_RULES
+        );
+
+        let index = match before_rule_id {
+            Some(before) => self
+                .policy_rules
+                .iter()
+                .position(|entry| entry.rule_id == before)
+                .unwrap_or_else(|| {
+                    env::panic_str(&format!("Policy rule {} does not exist", before.0))
+                }),
+            None => self.policy_rules.len(),
+        };
+        let rule_id = U64(self.next_policy_rule_id);
+        self.next_policy_rule_id += 1;
+        self.policy_rules
+            .insert(index, PolicyRuleEntry { rule_id, rule });
+        env::log_str(&format!("Policy rule {} added", rule_id.0));
+    }
+
+    pub(crate) fn delete_policy_rule(&mut self, rule_id: U64) {
+        self.policy_rules.retain(|entry| entry.rule_id != rule_id);
+    }
+
+    /// Evaluate the policy rules against the function calls of a request. Returns the id of the
+    /// rule requiring owner approval, if any, and an error if a rule denies the request.
+    pub(crate) fn check_policy_rules(
+        &self,
+        contract_id: &AccountId,
+        actions: &[ActionString],
+    ) -> Result<Option<U64>, String> {
+        let mut approval_rule = None;
+        for action in actions {
+            let ActionString::FunctionCall {
+                method_name, args, ..
+            } = action
+            else {
+                continue;
+            };
+
+            for entry in &self.policy_rules {
+                let matched =
+                    rule_matches(&entry.rule, contract_id, method_name, args).map_err(|e| {
+                        format!(
+                            "Policy rule {} could not be evaluated: {}",
+                            entry.rule_id.0, e
+                        )
+                    })?;
+                if !matched {
+                    continue;
+                }
+                match entry.rule.outcome {
+                    RuleOutcome::Allow => {}
+                    RuleOutcome::Deny => {
+                        return Err(format!(
+                            "Policy rule {} denies {} on {}",
+                            entry.rule_id.0, method_name, contract_id
+                        ));
+                    }
+                    RuleOutcome::RequireApproval => {
+                        approval_rule = approval_rule.or(Some(entry.rule_id));
+                    }
+                }
+                break;
+            }
+        }
+        Ok(approval_rule)
+    }
+
+    /// Evaluate the policy rules against a NEP-413 intent message, as the `execute_intents` call
+    /// which will carry it, with the swap's tokens and unsigned amounts added to the args. Intent
+    /// requests cannot be held for approval, so a rule requiring approval rejects them.
+    pub(crate) fn check_intent_policy_rules(
+        &self,
+        message: &str,
+        swap: &SwapQuote,
+    ) -> Result<(), String> {
+        let mut args: serde_json::Value =
+            serde_json::from_str(message).map_err(|e| format!("Invalid intent message: {}", e))?;
+        let Some(fields) = args.as_object_mut() else {
+            return Err("Invalid intent message: expected a JSON object".to_string());
+        };
+        fields.insert(
+            "spent_token".to_string(),
+            swap.spent_token.to_string().into(),
+        );
+        fields.insert(
+            "spent_amount".to_string(),
+            swap.spent_amount.0.to_string().into(),
+        );
+        fields.insert(
+            "received_token".to_string(),
+            swap.received_token.to_string().into(),
+        );
+        fields.insert(
+            "received_amount".to_string(),
+            swap.received_amount.0.to_string().into(),
+        );
+        let execute_intents = ActionString::FunctionCall {
+            method_name: "execute_intents".to_string(),
+            args,
+            gas: "0".to_string(),
+            deposit: "0".to_string(),
+        };
+        match self.check_policy_rules(&NEAR_INTENTS_ADDRESS, &[execute_intents])? {
+            Some(rule_id) => Err(format!(
+                "Policy rule {} requires owner approval, which intent requests cannot be held for",
+                rule_id.0
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Hold a recorded request until the owner approves or denies it
+    pub(crate) fn hold_for_approval(
+        &mut self,
+        request_id: u64,
+        rule_id: U64,
+        approval: ApprovalRequest,
+    ) {
+        if let Some(mut request) = self.signature_requests.get(&request_id) {
+            request.status = SignatureRequestStatus::AwaitingApproval { rule_id };
+            self.signature_requests.insert(&request_id, &request);
+        }
+        self.approval_requests.insert(&request_id, &approval);
+        env::log_str(&format!(
+            "Signature request {} awaits owner approval under policy rule {}",
+            request_id, rule_id.0
+        ));
+    }
+
+    fn take_approval_request(&mut self, request_id: u64) -> ApprovalRequest {
+        self.approval_requests
+            .remove(&request_id)
+            .unwrap_or_else(|| {
+                env::panic_str(&format!(
+                    "Signature request {} is not awaiting approval",
+                    request_id
+                ))
+            })
+    }
+}
+
+fn validate_rule(rule: &PolicyRule) -> Result<(), String> {
+    for condition in &rule.conditions {
+        if !condition.pointer.is_empty() && !condition.pointer.starts_with('/') {
+            return Err(format!(
+                "Invalid JSON pointer {}: it must be empty or start with /",
+                condition.pointer
+            ));
+        }
+        if is_amount_comparison(condition.operator) {
+            condition
+                .value
+                .parse::<u128>()
+                .map_err(|_| format!("Invalid amount {} in policy rule", condition.value))?;
+        }
+    }
+    Ok(())
+}
+
+fn is_amount_comparison(operator: RuleOperator) -> bool {
+    matches!(
+        operator,
+        RuleOperator::Lt | RuleOperator::Lte | RuleOperator::Gt | RuleOperator::Gte
+    )
+}
+
+fn rule_matches(
+    rule: &PolicyRule,
+    contract_id: &AccountId,
+    method_name: &str,
+    args: &serde_json::Value,
+) -> Result<bool, String> {
+    if rule
+        .contract_id
+        .as_ref()
+        .is_some_and(|id| id != contract_id)
+    {
+        return Ok(false);
+    }
+    if rule
+        .method_name
+        .as_deref()
+        .is_some_and(|name| name != method_name)
+    {
+        return Ok(false);
+    }
+    for condition in &rule.conditions {
+        if !condition_holds(condition, args)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Whether the condition holds for the args. A pointer to a missing value never holds, an amount
+/// comparison against a value which is not an amount is an error.
+fn condition_holds(condition: &RuleCondition, args: &serde_json::Value) -> Result<bool, String> {
+    let Some(value) = args.pointer(&condition.pointer) else {
+        return Ok(false);
+    };
+    let text = match value {
+        serde_json::Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+
+    if !is_amount_comparison(condition.operator) {
+        return Ok(match condition.operator {
+            RuleOperator::Eq => text == condition.value,
+            _ => text != condition.value,
+        });
+    }
+
+    let amount: u128 = text
+        .parse()
+        .map_err(|_| format!("{} at {} is not an amount", text, condition.pointer))?;
+    let bound: u128 = condition
+        .value
+        .parse()
+        .map_err(|_| format!("{} is not an amount", condition.value))?;
+    Ok(match condition.operator {
+        RuleOperator::Lt => amount < bound,
+        RuleOperator::Lte => amount <= bound,
+        RuleOperator::Gt => amount > bound,
+        _ => amount >= bound,
+    })
+}
@@ -0,0 +1,179 @@
+use std::cmp::Ordering;
+
+use near_sdk::json_types::{U64, U128};
+use near_sdk::{AccountId, Gas, PromiseError, PromiseOrValue, env, ext_contract, near};
+
+use crate::policy_changes::PolicyChange;
+use crate::signature_requests::PendingSignature;
+use crate::{TradingAccountContract, TradingAccountContractExt};
+
+pub const PRICE_ORACLE_GAS: Gas = Gas::from_tgas(10); // Gas for the oracle's get_price_data view
+pub const PRICE_CHECK_CALLBACK_GAS: Gas = Gas::from_tgas(10); // Gas for evaluating the oracle's response
+
+/// A USD price as reported by priceoracle.near: `multiplier / 10^decimals` USD per smallest token unit
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Price {
+    pub multiplier: U128,
+    pub decimals: u8,
+}
+
+#[near(serializers = [json])]
+#[derive(Clone, Debug)]
+pub struct AssetOptionalPrice {
+    pub asset_id: AccountId,
+    pub price: Option<Price>,
+}
+
+#[near(serializers = [json])]
+#[derive(Clone, Debug)]
+pub struct PriceData {
+    pub timestamp: U64,
+    pub recency_duration_sec: u32,
+    pub prices: Vec<AssetOptionalPrice>,
+}
+
+#[allow(dead_code)]
+#[ext_contract(ext_price_oracle)]
+pub trait PriceOracle {
+    fn get_price_data(&self, asset_ids: Option<Vec<AccountId>>) -> PriceData;
+}
+
+impl Price {
+    /// Compare two prices which may be expressed with different decimals
+    pub fn compare(&self, other: &Price) -> Result<Ordering, String> {
+        let scale = |price: &Price, decimals: u8| {
+            10u128
+                .checked_pow(decimals as u32)
+                .and_then(|factor| price.multiplier.0.checked_mul(factor))
+                .ok_or("Price overflow while comparing prices".to_string())
+        };
+        Ok(scale(self, other.decimals)?.cmp(&scale(other, self.decimals)?))
+    }
+
+    /// Ensure the price falls within the given inclusive bounds
+    pub fn check_bounds(
+        &self,
+        min_price: Option<&Price>,
+        max_price: Option<&Price>,
+    ) -> Result<(), String> {
+        if let Some(min_price) = min_price {
+            if self.compare(min_price)? == Ordering::Less {
+                return Err(format!(
+                    "Price {:?} is below the minimum price {:?}",
+                    self, min_price
+                ));
+            }
+        }
+        if let Some(max_price) = max_price {
+            if self.compare(max_price)? == Ordering::Greater {
+                return Err(format!(
+                    "Price {:?} is above the maximum price {:?}",
+                    self, max_price
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PriceData {
+    pub fn price_of(&self, asset_id: &AccountId) -> Option<&Price> {
+        self.prices
+            .iter()
+            .find(|asset| &asset.asset_id == asset_id)
+            .and_then(|asset| asset.price.as_ref())
+    }
+}
+
+#[near]
+impl TradingAccountContract {
+    /// Set or clear the price oracle consulted for goals with price bounds. Clearing it blocks
+    /// price-bounded goals and applies immediately, pointing at a new oracle is subject to the
+    /// policy change delay.
+    pub fn set_price_oracle(&mut self, oracle_id: Option<AccountId>) {
+        match oracle_id {
+            Some(oracle_id) => {
+                self.tighten_or_schedule(PolicyChange::SetPriceOracle { oracle_id }, true)
+            }
+            None => self.tighten_or_schedule(PolicyChange::ClearPriceOracle, false),
+        }
+    }
+
+    pub fn get_price_oracle(&self) -> Option<AccountId> {
+        self.price_oracle_id.clone()
+    }
+
+    #[private] // Only callable by the contract itself
+    pub fn on_price_data(
+        &mut self,
+        #[callback_result] price_data: Result<PriceData, PromiseError>,
+        request_id: u64,
+        pending: PendingSignature,
+    ) -> PromiseOrValue<Option<String>> {
+        let result = price_data
+            .map_err(|e| format!("Failed to fetch price data from oracle: {:?}", e))
+            .and_then(|price_data| {
+                self.check_goal_price(request_id, &price_data)?;
+                match &pending.swap {
+                    Some(swap) => swap.check_slippage(&price_data),
+                    None => Ok(()),
+                }
+            });
+        match result {
+            Ok(()) => PromiseOrValue::Promise(self.continue_signature_request(request_id, pending)),
+            Err(reason) => {
+                env::log_str(&format!(
+                    "Signature request {} rejected by price guard: {}",
+                    request_id, reason
+                ));
+                self.reject_signature_request(request_id, reason, pending.deposit);
+                self.record_request_rejection(request_id);
+                PromiseOrValue::Value(None)
+            }
+        }
+    }
+}
+
+impl TradingAccountContract {
+    /// The oracle and token to consult before signing on behalf of the given goal, if it has price bounds
+    pub(crate) fn goal_price_guard(&self, goal_id: u64) -> Result<Option<AccountId>, String> {
+        let Some(goal) = self.goals.get(&goal_id) else {
+            return Ok(None);
+        };
+        if goal.min_price.is_none() && goal.max_price.is_none() {
+            return Ok(None);
+        }
+        match &self.price_oracle_id {
+            Some(_) => Ok(Some(goal.target_token)),
+            None => Err(format!(
+                "Goal {} has price bounds but no price oracle is configured",
+                goal_id
+            )),
+        }
+    }
+
+    fn check_goal_price(&self, request_id: u64, price_data: &PriceData) -> Result<(), String> {
+        let request = self.signature_requests.get(&request_id).ok_or(format!(
+            "Signature request {} is no longer stored",
+            request_id
+        ))?;
+        let Some(goal_id) = request.goal_id else {
+            // DCA executions are only bounded by the slippage of their swap
+            return Ok(());
+        };
+        let goal = self
+            .goals
+            .get(&goal_id.0)
+            .ok_or(format!("Goal {} does not exist", goal_id.0))?;
+        if goal.min_price.is_none() && goal.max_price.is_none() {
+            return Ok(());
+        }
+
+        let price = price_data
+            .price_of(&goal.target_token)
+            .ok_or(format!("Oracle has no price for {}", goal.target_token))?;
+
+        price.check_bounds(goal.min_price.as_ref(), goal.max_price.as_ref())
+    }
+}
@@ -0,0 +1,60 @@
+use near_sdk::json_types::{U64, U128};
+use near_sdk::store::IterableMap;
+use near_sdk::{AccountId, PanicOnDefault, env, near};
+
+/// Mirrors the priceoracle.near `Price` type
+#[near(serializers = [json, borsh])]
+#[derive(Clone)]
+pub struct Price {
+    pub multiplier: U128,
+    pub decimals: u8,
+}
+
+#[near(serializers = [json])]
+pub struct AssetOptionalPrice {
+    pub asset_id: AccountId,
+    pub price: Option<Price>,
+}
+
+#[near(serializers = [json])]
+pub struct PriceData {
+    pub timestamp: U64,
+    pub recency_duration_sec: u32,
+    pub prices: Vec<AssetOptionalPrice>,
+}
+
+#[near(contract_state)]
+#[derive(PanicOnDefault)]
+pub struct PriceOracleMock {
+    prices: IterableMap<AccountId, Price>,
+}
+
+#[near]
+impl PriceOracleMock {
+    #[init]
+    pub fn new() -> Self {
+        Self {
+            prices: IterableMap::new(b"p"),
+        }
+    }
+
+    pub fn set_price(&mut self, asset_id: AccountId, price: Price) {
+        self.prices.insert(asset_id, price);
+    }
+
+    pub fn get_price_data(&self, asset_ids: Option<Vec<AccountId>>) -> PriceData {
+        let asset_ids =
+            asset_ids.unwrap_or_else(|| self.prices.keys().cloned().collect::<Vec<_>>());
+        PriceData {
+            timestamp: U64(env::block_timestamp()),
+            recency_duration_sec: 90,
+            prices: asset_ids
+                .into_iter()
+                .map(|asset_id| AssetOptionalPrice {
+                    price: self.prices.get(&asset_id).cloned(),
+                    asset_id,
+                })
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use near_sdk::{AccountId, env, near};
+
+use crate::policy_changes::PolicyChange;
+use crate::{TradingAccountContract, TradingAccountContractExt};
+
+const MAX_ROLE_ASSIGNMENTS: u64 = 10; // Maximum number of managers and viewers per trading account
+
+/// What an account may do on the trading account
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    /// Full control, including withdrawals. Held by `owner_id` only.
+    Owner,
+    /// May add and remove agents and apply any tightening of the policy immediately, but not
+    /// withdraw or loosen it
+    Manager,
+    /// May request signatures. Agents are the authorized users.
+    Agent,
+    /// Read-only access, recorded for off-chain tooling
+    Viewer,
+}
+
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoleAssignment {
+    pub account_id: AccountId,
+    pub role: Role,
+}
+
+#[near]
+impl TradingAccountContract {
+    /// Grant a manager or viewer role. Managers may authorize agents, so granting the role is
+    /// subject to the policy change delay. Agents are added with `add_authorized_user` and the
+    /// owner role is handed over with `propose_owner`.
+    pub fn grant_role(&mut self, account_id: AccountId, role: Role) {
+        self.assert_owner();
+        assert_ne!(
+            account_id, self.owner_id,
+            "The owner already holds every role"
+        );
+        match role {
+            Role::Manager => self.apply_or_schedule(PolicyChange::GrantManagerRole { account_id }),
+            Role::Viewer => self.insert_role(account_id, role),
+            Role::Agent => env::panic_str("Agents are added with add_authorized_user"),
+            Role::Owner => env::panic_str("Ownership is handed over with propose_owner"),
+        }
+    }
+
+    /// Revoke a manager or viewer role. Revoking only takes power away, so it applies immediately.
+    pub fn revoke_role(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        assert!(
+            self.roles.remove(&account_id).is_some(),
+            "{} holds no manager or viewer role",
+            account_id
+        );
+    }
+
+    /// The roles held by an account
+    pub fn get_roles(&self, account_id: AccountId) -> Vec<Role> {
+        let mut roles = Vec::new();
+        if account_id == self.owner_id {
+            roles.push(Role::Owner);
+        }
+        if let Some(role) = self.roles.get(&account_id) {
+            roles.push(role);
+        }
+        if self.authorized_users.contains(&account_id) {
+            roles.push(Role::Agent);
+        }
+        roles
+    }
+
+    /// Every role assignment, the owner first
+    pub fn get_role_assignments(&self) -> Vec<RoleAssignment> {
+        let mut assignments = vec![RoleAssignment {
+            account_id: self.owner_id.clone(),
+            role: Role::Owner,
+        }];
+        assignments.extend(
+            self.roles
+                .iter()
+                .map(|(account_id, role)| RoleAssignment { account_id, role }),
+        );
+        assignments.extend(
+            self.authorized_users
+                .iter()
+                .map(|account_id| RoleAssignment {
+                    account_id,
+                    role: Role::Agent,
+                }),
+        );
+        assignments
+    }
+}
+
+impl TradingAccountContract {
+    pub(crate) fn insert_role(&mut self, account_id: AccountId, role: Role) {
+        assert!(
+            self.roles.get(&account_id).is_some() || self.roles.len() < MAX_ROLE_ASSIGNMENTS,
+            "Maximum number of role assignments reached:({}). One must be revoked before granting another.",
+            MAX_ROLE_ASSIGNMENTS
+        );
+
+        self.roles.insert(&account_id, &role);
+    }
+
+    pub(crate) fn is_manager(&self, account_id: &AccountId) -> bool {
+        self.roles.get(account_id) == Some(Role::Manager)
+    }
+
+    pub(crate) fn assert_owner_or_manager(&self) {
+        let caller = env::predecessor_account_id();
+        assert!(
+            caller == self.owner_id || self.is_manager(&caller),
+            "Only the owner or a manager can perform this action."
+        );
+    }
+}
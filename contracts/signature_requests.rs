@@ -0,0 +1,371 @@
+use near_sdk::json_types::{U64, U128};
+use near_sdk::{AccountId, Gas, NearToken, Promise, env, near, serde_json};
+
+use crate::actions::BalanceSpend;
+use crate::balance_limits::CountedSpend;
+use crate::intents::SwapQuote;
+
+use crate::{GAS_FOR_REQUEST_SIGNATURE, TradingAccountContract, TradingAccountContractExt};
+
+// Maximum number of signature requests kept in state. Once reached, the oldest entry is evicted
+// to make room for a new one, which keeps the storage staked by this history bounded. Requests
+// which may still be signed or executed are never evicted; new requests are refused instead.
+pub const MAX_STORED_SIGNATURE_REQUESTS: u64 = 50;
+const DEFAULT_SIGNATURE_REQUESTS_PAGE_SIZE: u64 = 10;
+// Blocks after which a transaction's block_hash is too old for it to be included (mainnet
+// transaction_validity_period). A transaction signed for a request can land until then.
+pub const TRANSACTION_VALIDITY_BLOCKS: u64 = 86_400;
+
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug, PartialEq)]
+pub enum SignatureRequestStatus {
+    /// The MPC signer has not yet responded
+    Pending,
+    /// The MPC signer responded and the signed transaction (base64 encoded) is ready to broadcast
+    Signed { signed_transaction: String },
+    /// The MPC signer signed a NEP-413 intent message. The signature is "secp256k1:" followed
+    /// by the base58 encoded r, s and v bytes.
+    SignedIntent { signature: String },
+    /// The MPC signer failed to respond or returned a signature that could not be verified
+    Failed { reason: String },
+    /// A policy rule requires the owner to approve the request before it is signed
+    AwaitingApproval { rule_id: U64 },
+}
+
+impl SignatureRequest {
+    /// Whether the request can neither be signed nor hold a deposit any more, so that forgetting
+    /// it loses no budget, cooldown or DCA interval to refund
+    pub fn is_settled(&self) -> bool {
+        !self.status.is_pending() && !self.deposit_held
+    }
+}
+
+impl SignatureRequestStatus {
+    /// Whether the request has yet to be signed or rejected
+    pub fn is_pending(&self) -> bool {
+        matches!(
+            self,
+            SignatureRequestStatus::Pending | SignatureRequestStatus::AwaitingApproval { .. }
+        )
+    }
+}
+
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug)]
+pub struct SignatureRequest {
+    pub request_id: U64,
+    pub requested_by: AccountId,
+    pub contract_id: AccountId,
+    /// The transaction nonce, 0 for NEP-413 intents which carry their own 32 byte nonce
+    pub nonce: U64,
+    /// The goal the request draws on, none for DCA executions which draw on `dca_schedule_id`
+    pub goal_id: Option<U64>,
+    /// Amount of the goal's target token reserved by this request
+    pub goal_spend: U128,
+    /// The goal's last execution before this request, restored should the request fail
+    pub previous_goal_execution: Option<U64>,
+    /// The DCA schedule executed by the request, if any
+    pub dca_schedule_id: Option<U64>,
+    /// What the request counted towards the daily balance limits, taken back should it fail
+    pub daily_spends: Vec<CountedSpend>,
+    /// Total NEAR attached to the actions of the transaction to sign
+    pub deposit: U128,
+    /// Whether `deposit` still counts against the balance reserve. It does until the request
+    /// fails, or its signed transaction expires or is settled by the owner.
+    pub deposit_held: bool,
+    /// Block timestamp (in nanoseconds) at which the request was made
+    pub created_at: U64,
+    /// Block height at which the request was made. Its transaction references a block no later
+    /// than this one, so it expires within TRANSACTION_VALIDITY_BLOCKS.
+    pub created_at_height: U64,
+    pub status: SignatureRequestStatus,
+}
+
+/// What an accepted signature request took from its goal or DCA schedule and the daily balance
+/// limits, handed back should it fail
+#[derive(Default)]
+pub(crate) struct RequestReservation {
+    pub goal_id: Option<U64>,
+    pub goal_spend: U128,
+    pub previous_goal_execution: Option<U64>,
+    pub dca_schedule_id: Option<U64>,
+    pub daily_spends: Vec<CountedSpend>,
+}
+
+/// A signature request which passed validation and awaits its pre-flight checks before the MPC
+/// signer is called
+#[near(serializers = [json])]
+#[derive(Clone, Debug)]
+pub struct PendingSignature {
+    pub request_payload: serde_json::Value,
+    /// The deposit attached by the agent, forwarded to the MPC signer or refunded on rejection
+    pub deposit: NearToken,
+    pub gas_for_signing: Gas,
+    pub payload: SignedPayload,
+    /// Balances to snapshot before signing
+    pub balance_checks: Vec<BalanceSpend>,
+    /// The swap of a token_diff intent, checked for slippage against the oracle prices
+    pub swap: Option<SwapQuote>,
+}
+
+/// What the MPC signature is for
+#[near(serializers = [json])]
+#[derive(Clone, Debug)]
+pub enum SignedPayload {
+    /// A NEAR transaction, serialized to JSON
+    Transaction { tx_json_string: String },
+    /// A NEP-413 message, hex encoded hash
+    Intent { payload_hash: String },
+}
+
+#[near]
+impl TradingAccountContract {
+    pub fn get_signature_request(&self, request_id: U64) -> Option<SignatureRequest> {
+        self.signature_requests.get(&request_id.0)
+    }
+
+    /// Lists stored signature requests in ascending request id order, starting at `from_request_id`
+    /// (defaults to the oldest stored request).
+    pub fn get_signature_requests(
+        &self,
+        from_request_id: Option<U64>,
+        limit: Option<u64>,
+    ) -> Vec<SignatureRequest> {
+        let start = from_request_id
+            .map(|id| id.0)
+            .unwrap_or(self.oldest_signature_request_id)
+            .max(self.oldest_signature_request_id);
+        let limit = limit.unwrap_or(DEFAULT_SIGNATURE_REQUESTS_PAGE_SIZE);
+
+        (start..self.next_signature_request_id)
+            .filter_map(|id| self.signature_requests.get(&id))
+            .take(limit as usize)
+            .collect()
+    }
+
+    /// Removes every settled signature request with an id lower than `before_request_id`.
+    /// Requests which are pending, awaiting approval or hold the deposit of a signed transaction
+    /// are kept. Returns the number of entries removed.
+    pub fn prune_signature_requests(&mut self, before_request_id: U64) -> u64 {
+        self.assert_owner();
+
+        let start = self.oldest_signature_request_id;
+        let end = before_request_id.0.min(self.next_signature_request_id);
+        let mut removed = 0;
+        let mut kept_any = false;
+        for request_id in start..end {
+            match self.signature_requests.get(&request_id) {
+                Some(request) if !request.is_settled() => kept_any = true,
+                Some(_) => {
+                    self.signature_requests.remove(&request_id);
+                    removed += 1;
+                }
+                None => {}
+            }
+            // The oldest id only moves past entries which are gone
+            if !kept_any {
+                self.oldest_signature_request_id = request_id + 1;
+            }
+        }
+
+        env::log_str(&format!("Pruned {} signature requests", removed));
+        removed
+    }
+
+    /// Stop counting the deposit of a signed transaction against the balance reserve before it
+    /// expires, once the owner has seen it land or made sure it never will
+    pub fn settle_signature_request(&mut self, request_id: U64) {
+        self.assert_owner();
+        let mut request = self
+            .signature_requests
+            .get(&request_id.0)
+            .unwrap_or_else(|| {
+                env::panic_str(&format!("Signature request {} is not stored", request_id.0))
+            });
+        assert!(
+            matches!(request.status, SignatureRequestStatus::Signed { .. }) && request.deposit_held,
+            "Signature request {} has no signed transaction holding a deposit",
+            request_id.0
+        );
+        self.release_pending_deposit(&mut request);
+        self.signature_requests.insert(&request_id.0, &request);
+        env::log_str(&format!("Signature request {} settled", request_id.0));
+    }
+}
+
+impl TradingAccountContract {
+    /// The checks every signature request method starts with, which panic. Rejections past them
+    /// are returned through `reject_agent_request` instead, as a panic would revert the rejection
+    /// count of the circuit breaker.
+    pub(crate) fn assert_can_request_signature(&self) {
+        let attached_gas = env::prepaid_gas();
+        assert!(
+            attached_gas >= GAS_FOR_REQUEST_SIGNATURE,
+            "Not enough gas attached. Please attach at least {} TGas. Attached: {} TGas",
+            GAS_FOR_REQUEST_SIGNATURE.as_tgas(),
+            attached_gas.as_tgas()
+        );
+        let account_id = env::predecessor_account_id();
+        assert!(
+            self.authorized_users.contains(&account_id),
+            "Unauthorized: only authorized users can request signatures"
+        );
+        self.assert_not_suspended(&account_id);
+        assert!(
+            !self.is_closing(),
+            "Signing is paused while the trading account is being closed"
+        );
+    }
+
+    /// Store a new pending signature request, evicting the oldest one if the history is full.
+    /// Panics if the oldest request is still live, so it keeps what it reserved.
+    pub(crate) fn record_signature_request(
+        &mut self,
+        contract_id: AccountId,
+        nonce: U64,
+        deposit: U128,
+        reservation: RequestReservation,
+    ) -> u64 {
+        if self.next_signature_request_id - self.oldest_signature_request_id
+            >= MAX_STORED_SIGNATURE_REQUESTS
+        {
+            let oldest = self.oldest_signature_request_id;
+            self.release_expired_deposit(oldest);
+            if let Some(request) = self.signature_requests.get(&oldest) {
+                if !request.is_settled() {
+                    env::panic_str(&format!(
+                        "Too many signature requests in flight, request {} must settle first",
+                        oldest
+                    ));
+                }
+                self.signature_requests.remove(&oldest);
+            }
+            self.oldest_signature_request_id += 1;
+        }
+
+        let request_id = self.next_signature_request_id;
+        self.next_signature_request_id += 1;
+
+        self.signature_requests.insert(
+            &request_id,
+            &SignatureRequest {
+                request_id: U64(request_id),
+                requested_by: env::predecessor_account_id(),
+                contract_id,
+                nonce,
+                goal_id: reservation.goal_id,
+                goal_spend: reservation.goal_spend,
+                previous_goal_execution: reservation.previous_goal_execution,
+                dca_schedule_id: reservation.dca_schedule_id,
+                daily_spends: reservation.daily_spends,
+                deposit,
+                deposit_held: deposit.0 > 0,
+                created_at: U64(env::block_timestamp()),
+                created_at_height: U64(env::block_height()),
+                status: SignatureRequestStatus::Pending,
+            },
+        );
+
+        self.pending_deposits = self.pending_deposits.saturating_add(deposit.0);
+
+        env::log_str(&format!("Signature request {} created", request_id));
+        request_id
+    }
+
+    /// Add spends counted by the pre-flight balance snapshot to those of a request, so they can
+    /// be taken back should it fail
+    pub(crate) fn add_counted_spends(&mut self, request_id: u64, counted: Vec<CountedSpend>) {
+        if counted.is_empty() {
+            return;
+        }
+        if let Some(mut request) = self.signature_requests.get(&request_id) {
+            request.daily_spends.extend(counted);
+            self.signature_requests.insert(&request_id, &request);
+        }
+    }
+
+    /// Update the status of a stored signature request. Requests that have since been pruned are ignored.
+    /// A failed request returns the budget it reserved to its goal, or its interval to its DCA
+    /// schedule, and what it counted towards the daily balance limits.
+    pub(crate) fn update_signature_request_status(
+        &mut self,
+        request_id: u64,
+        status: SignatureRequestStatus,
+    ) {
+        match self.signature_requests.get(&request_id) {
+            Some(mut request) => {
+                // A signed transaction keeps its deposit held until it can no longer execute
+                if matches!(status, SignatureRequestStatus::Failed { .. }) {
+                    self.release_pending_deposit(&mut request);
+                    self.release_counted_spends(&request.daily_spends);
+                    if let Some(schedule_id) = request.dca_schedule_id {
+                        self.release_dca_execution(schedule_id.0, request.created_at.0);
+                    }
+                    if let Some(goal_id) = request.goal_id {
+                        self.refund_goal_budget(
+                            goal_id.0,
+                            request.goal_spend.0,
+                            request.created_at.0,
+                            request.previous_goal_execution,
+                        );
+                    }
+                }
+                request.status = status;
+                self.signature_requests.insert(&request_id, &request);
+            }
+            None => env::log_str(&format!(
+                "Signature request {} is no longer stored, skipping status update",
+                request_id
+            )),
+        }
+    }
+
+    /// Fail a request before it reaches the MPC signer, refunding the deposit meant for the signer
+    pub(crate) fn reject_signature_request(
+        &mut self,
+        request_id: u64,
+        reason: String,
+        deposit: NearToken,
+    ) {
+        if let Some(request) = self.signature_requests.get(&request_id) {
+            if !deposit.is_zero() {
+                Promise::new(request.requested_by)
+                    .transfer(deposit)
+                    .detach();
+            }
+        }
+        self.update_signature_request_status(request_id, SignatureRequestStatus::Failed { reason });
+    }
+
+    /// Stop counting the deposit of a request against the balance reserve. The caller stores the
+    /// updated request.
+    fn release_pending_deposit(&mut self, request: &mut SignatureRequest) {
+        if request.deposit_held {
+            self.pending_deposits = self.pending_deposits.saturating_sub(request.deposit.0);
+            request.deposit_held = false;
+        }
+    }
+
+    /// Release the deposits of signed transactions which can no longer be included in a block
+    pub(crate) fn release_expired_deposits(&mut self) {
+        if self.pending_deposits == 0 {
+            return;
+        }
+        for request_id in self.oldest_signature_request_id..self.next_signature_request_id {
+            self.release_expired_deposit(request_id);
+        }
+    }
+
+    fn release_expired_deposit(&mut self, request_id: u64) {
+        let Some(mut request) = self.signature_requests.get(&request_id) else {
+            return;
+        };
+        let expired = request.deposit_held
+            && matches!(request.status, SignatureRequestStatus::Signed { .. })
+            && env::block_height() > request.created_at_height.0 + TRANSACTION_VALIDITY_BLOCKS;
+        if expired {
+            self.release_pending_deposit(&mut request);
+            self.signature_requests.insert(&request_id, &request);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use near_sdk::{AccountId, env, near};
+
+use crate::policy_changes::PolicyChange;
+use crate::{TradingAccountContract, TradingAccountContractExt};
+
+const MAX_ALLOWED_PAIRS: u64 = 20; // Maximum number of token pairs agents may swap
+
+/// A swap agents may make, spending `from` to receive `to`
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenPair {
+    pub from: AccountId,
+    pub to: AccountId,
+}
+
+#[near]
+impl TradingAccountContract {
+    pub fn add_allowed_pair(&mut self, from: AccountId, to: AccountId) {
+        self.assert_owner();
+        assert_ne!(
+            from, to,
+            "A token pair must swap between two different tokens"
+        );
+        self.apply_or_schedule(PolicyChange::AddAllowedPair {
+            pair: TokenPair { from, to },
+        });
+    }
+
+    pub fn remove_allowed_pair(&mut self, from: AccountId, to: AccountId) {
+        self.assert_owner_or_manager();
+        self.allowed_pairs.remove(&TokenPair { from, to });
+    }
+
+    pub fn get_allowed_pairs(&self) -> Vec<TokenPair> {
+        self.allowed_pairs.to_vec()
+    }
+}
+
+impl TradingAccountContract {
+    pub(crate) fn insert_allowed_pair(&mut self, pair: TokenPair) {
+        assert!(
+            self.allowed_pairs.len() < MAX_ALLOWED_PAIRS,
+            "Maximum number of allowed token pairs reached:({}). One must be removed before adding another.",
+            MAX_ALLOWED_PAIRS
+        );
+
+        self.allowed_pairs.insert(&pair);
+    }
+
+    /// Ensure agents only swap between the token pairs the owner allowed
+    pub(crate) fn check_swap_pair(&self, from: &AccountId, to: &AccountId) -> Result<(), String> {
+        let pair = TokenPair {
+            from: from.clone(),
+            to: to.clone(),
+        };
+        if self.allowed_pairs.contains(&pair) {
+            return Ok(());
+        }
+        env::log_str(&format!("Rejected swap from {} to {}", from, to));
+        Err(format!(
+            "Swap from {} to {} is not allowed. Allowed pairs: {:?}",
+            from,
+            to,
+            self.allowed_pairs
+                .iter()
+                .map(|pair| format!("{} -> {}", pair.from, pair.to))
+                .collect::<Vec<_>>()
+        ))
+    }
+}
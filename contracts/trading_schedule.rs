@@ -0,0 +1,140 @@
+use near_sdk::{AccountId, env, near};
+
+use crate::balance_limits::{DAY_NS, current_day};
+use crate::policy_changes::PolicyChange;
+use crate::{TradingAccountContract, TradingAccountContractExt};
+
+const MAX_TRADING_WINDOWS: usize = 21; // Maximum number of trading windows per agent
+const MINUTES_PER_DAY: u16 = 24 * 60;
+const MINUTE_NS: u64 = 60 * 1_000_000_000;
+
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    const ALL: [Weekday; 7] = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+        Weekday::Sunday,
+    ];
+
+    /// The weekday of a day since the unix epoch, which was a Thursday
+    fn of_day(day: u64) -> Self {
+        Self::ALL[((day + 3) % 7) as usize]
+    }
+}
+
+/// A span of a UTC weekday during which an agent may request signatures
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TradingWindow {
+    pub weekday: Weekday,
+    /// Minutes after midnight UTC at which the window opens
+    pub start_minute: u16,
+    /// Minutes after midnight UTC at which the window closes, at most 1440
+    pub end_minute: u16,
+}
+
+impl TradingWindow {
+    fn contains(&self, other: &TradingWindow) -> bool {
+        self.weekday == other.weekday
+            && self.start_minute <= other.start_minute
+            && other.end_minute <= self.end_minute
+    }
+}
+
+#[near]
+impl TradingAccountContract {
+    /// Only let the agent request signatures during the given UTC windows, or at any time with
+    /// None. Widening the schedule is subject to the policy change delay.
+    pub fn set_agent_schedule(
+        &mut self,
+        account_id: AccountId,
+        windows: Option<Vec<TradingWindow>>,
+    ) {
+        self.assert_owner_or_manager();
+        if let Some(windows) = &windows {
+            assert!(
+                windows.len() <= MAX_TRADING_WINDOWS,
+                "An agent can have at most {} trading windows",
+                MAX_TRADING_WINDOWS
+            );
+            for window in windows {
+                assert!(
+                    window.start_minute < window.end_minute && window.end_minute <= MINUTES_PER_DAY,
+                    "Invalid trading window {:?}: it must start before it ends, within {} minutes of midnight",
+                    window,
+                    MINUTES_PER_DAY
+                );
+            }
+        }
+
+        let loosened = match (self.agent_schedules.get(&account_id), &windows) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(old), Some(new)) => new
+                .iter()
+                .any(|window| !old.iter().any(|old| old.contains(window))),
+        };
+        self.tighten_or_schedule(
+            PolicyChange::SetAgentSchedule {
+                account_id,
+                windows,
+            },
+            loosened,
+        );
+    }
+
+    pub fn get_agent_schedule(&self, account_id: AccountId) -> Option<Vec<TradingWindow>> {
+        self.agent_schedules.get(&account_id)
+    }
+}
+
+impl TradingAccountContract {
+    pub(crate) fn update_agent_schedule(
+        &mut self,
+        account_id: AccountId,
+        windows: Option<Vec<TradingWindow>>,
+    ) {
+        match windows {
+            Some(windows) => self.agent_schedules.insert(&account_id, &windows),
+            None => self.agent_schedules.remove(&account_id),
+        };
+    }
+
+    /// Ensure the agent's schedule, if it has one, has a window open at the current block time
+    pub(crate) fn check_trading_window(&self, account_id: &AccountId) -> Result<(), String> {
+        let Some(windows) = self.agent_schedules.get(account_id) else {
+            return Ok(());
+        };
+        let weekday = Weekday::of_day(current_day());
+        let minute = ((env::block_timestamp() % DAY_NS) / MINUTE_NS) as u16;
+        let open = windows.iter().any(|window| {
+            window.weekday == weekday && window.start_minute <= minute && minute < window.end_minute
+        });
+        if open {
+            return Ok(());
+        }
+        Err(format!(
+            "{} may not trade on {:?} at {:02}:{:02} UTC. Trading windows: {:?}",
+            account_id,
+            weekday,
+            minute / 60,
+            minute % 60,
+            windows
+        ))
+    }
+}
@@ -1,16 +1,21 @@
 #[cfg(test)]
 mod tests {
+    use crate::actions::{BalanceSource, BalanceSpend};
+    use crate::intents::parse_token_diff;
+    use crate::signature_requests::{RequestReservation, TRANSACTION_VALIDITY_BLOCKS};
     use crate::{
-        ActionString, BigR, EcdsaSignatureResponse, ScalarValue, SignatureResponse,
-        TradingAccountContract,
+        AccountClosureStage, ActionString, BigR, EcdsaSignatureResponse, PendingSignature,
+        PolicyRule, Role, RoleAssignment, RuleCondition, RuleOperator, RuleOutcome, ScalarValue,
+        SignatureRequestStatus, SignatureResponse, SignedPayload, SwapQuote, TokenPair,
+        TradingAccountContract, TradingWindow, Weekday,
     };
-    use near_sdk::PublicKey;
     use near_sdk::{
         AccountId,
-        json_types::{Base58CryptoHash, U64},
+        json_types::{Base58CryptoHash, U64, U128},
         test_utils::{VMContextBuilder, accounts},
         testing_env,
     };
+    use near_sdk::{NearToken, PromiseError, PromiseOrValue, PromiseResult, PublicKey};
     use omni_transaction::TxBuilder;
     use omni_transaction::near::utils::PublicKeyStrExt;
     use std::str::FromStr;
@@ -23,6 +28,40 @@ mod tests {
         builder
     }
 
+    /// The current context with another predecessor
+    fn context_as(predecessor: AccountId) -> VMContextBuilder {
+        let mut builder = get_context(predecessor);
+        builder
+            .account_balance(near_sdk::env::account_balance())
+            .block_timestamp(near_sdk::env::block_timestamp())
+            .prepaid_gas(near_sdk::env::prepaid_gas())
+            .attached_deposit(near_sdk::env::attached_deposit())
+            .storage_usage(near_sdk::env::storage_usage());
+        builder
+    }
+
+    /// Offer an authorization to `account_id` and accept it from the agent's account
+    fn authorize_agent(contract: &mut TradingAccountContract, account_id: AccountId) {
+        let caller = near_sdk::env::predecessor_account_id();
+        contract.add_authorized_user(account_id.clone());
+        testing_env!(context_as(account_id).build());
+        contract.accept_authorization();
+        testing_env!(context_as(caller).build());
+    }
+
+    /// Assert a signature request was rejected, without panicking, for `reason`
+    fn assert_rejected(result: PromiseOrValue<Option<String>>, reason: &str) {
+        assert!(matches!(result, PromiseOrValue::Value(None)));
+        assert!(
+            near_sdk::test_utils::get_logs()
+                .iter()
+                .any(|log| log.starts_with("Signature request rejected:") && log.contains(reason)),
+            "No rejection for {} in {:?}",
+            reason,
+            near_sdk::test_utils::get_logs()
+        );
+    }
+
     #[test]
     fn test_new() {
         let context = get_context(accounts(1));
@@ -43,7 +82,7 @@ mod tests {
             AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
         );
 
-        contract.add_authorized_user(accounts(2));
+        authorize_agent(&mut contract, accounts(2));
         assert!(contract.is_authorized(accounts(2)));
     }
 
@@ -56,7 +95,7 @@ mod tests {
             AccountId::try_from("v1.signer".to_string()).unwrap(),
         );
 
-        contract.add_authorized_user(accounts(2));
+        authorize_agent(&mut contract, accounts(2));
         assert!(contract.is_authorized(accounts(2)));
 
         contract.remove_authorized_user(accounts(2));
@@ -64,7 +103,7 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "You have no power here. Only the owner can perform this action.")]
+    #[should_panic(expected = "Only the owner or a manager can perform this action.")]
     fn test_unauthorized_add_user() {
         let context = get_context(accounts(2));
         testing_env!(context.build());
@@ -84,8 +123,8 @@ mod tests {
             AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
         );
 
-        contract.add_authorized_user(accounts(2));
-        contract.add_authorized_user(accounts(3));
+        authorize_agent(&mut contract, accounts(2));
+        authorize_agent(&mut contract, accounts(3));
 
         let users = contract.get_authorized_users();
         assert_eq!(users.len(), 2);
@@ -126,7 +165,7 @@ mod tests {
         // Add 10 authorized users (the maximum)
         for i in 0..10 {
             let user = AccountId::try_from(format!("user{}.testnet", i)).unwrap();
-            contract.add_authorized_user(user);
+            authorize_agent(&mut contract, user);
         }
 
         // Verify we have 10 users
@@ -139,7 +178,7 @@ mod tests {
 
         // Now we should be able to add another user
         let user11 = AccountId::try_from("user11.testnet".to_string()).unwrap();
-        contract.add_authorized_user(user11.clone());
+        authorize_agent(&mut contract, user11.clone());
         assert_eq!(contract.get_authorized_users().len(), 10);
         assert!(contract.is_authorized(user11));
     }
@@ -161,13 +200,11 @@ mod tests {
             "secp256k1:abcd".to_string(),                       // public_key: String
             "test_path".to_string(),                            // path: String
             None,                                               // domain_id: Option<u32>
+            U64(0),                                             // goal_id: U64
         );
     }
 
     #[test]
-    #[should_panic(
-        expected = "unknown variant `Sign Message`, expected `FunctionCall` or `Transfer`"
-    )]
     fn test_disallowed_action() {
         let context = get_context(accounts(2));
         testing_env!(context.build());
@@ -177,7 +214,7 @@ mod tests {
         );
 
         testing_env!(get_context(accounts(1)).build());
-        contract.add_authorized_user(accounts(2));
+        authorize_agent(&mut contract, accounts(2));
 
         let actions_json = r#"[
             {
@@ -187,7 +224,7 @@ mod tests {
         ]"#;
 
         testing_env!(get_context(accounts(2)).build());
-        let _ = contract.request_signature(
+        let result = contract.request_signature(
             accounts(3),                       // contract_id
             actions_json.to_string(),          // actions_json
             U64(1),                            // nonce
@@ -195,13 +232,15 @@ mod tests {
             "secp256k1:abcd".to_string(),      // public_key
             "ed25519:wxyz".to_string(),        // path
             None,                              // domain_id: Option<u32>
+            U64(0),                            // goal_id: U64
+        );
+        assert_rejected(
+            result,
+            "unknown variant `Sign Message`, expected `FunctionCall` or `Transfer`",
         );
     }
 
     #[test]
-    #[should_panic(
-        expected = "Transfer actions must be accompanied by at least one FunctionCall action"
-    )]
     fn test_request_signature_single_transfer_action_fails() {
         let context = get_context(accounts(2));
         testing_env!(context.build());
@@ -211,7 +250,7 @@ mod tests {
         );
 
         testing_env!(get_context(accounts(1)).build());
-        contract.add_authorized_user(accounts(2));
+        authorize_agent(&mut contract, accounts(2));
 
         let actions_json = r#"[
             {
@@ -221,14 +260,19 @@ mod tests {
         ]"#;
 
         testing_env!(get_context(accounts(2)).build());
-        let _ = contract.request_signature(
+        let result = contract.request_signature(
             AccountId::try_from("bad-account.near".to_string()).unwrap(),
             actions_json.to_string(),
             U64(1),
             Base58CryptoHash::from([0u8; 32]),
             "ed25519:11111111111111111111111111111111".to_string(),
             "trading-account.near".to_string(),
-            None, // domain_id: Option<u32>
+            None,   // domain_id: Option<u32>
+            U64(0), // goal_id: U64
+        );
+        assert_rejected(
+            result,
+            "Transfer actions must be accompanied by at least one FunctionCall action",
         );
     }
 
@@ -243,15 +287,23 @@ mod tests {
         );
 
         testing_env!(get_context(accounts(1)).build());
-        contract.add_authorized_user(accounts(2));
+        authorize_agent(&mut contract, accounts(2));
+        contract.add_allowed_token(AccountId::try_from("wrap.near".to_string()).unwrap());
+        let goal_id = contract.create_goal(
+            AccountId::try_from("wrap.near".to_string()).unwrap(),
+            U128(1_000_000_000_000_000_000_000_000),
+            vec![AccountId::try_from("usdt.tether-token.near".to_string()).unwrap()],
+            U64(u64::MAX),
+            1,
+        );
 
         let actions_json = r#"[
             {
                 "type": "FunctionCall",
                 "method_name": "ft_transfer_call",
-                "args": {"receiver_id": "alice.near", "amount": "1000000000000000000000000"},
+                "args": {"receiver_id": "intents.near", "amount": "1000000000000000000000000"},
                 "gas": "100000000000000",
-                "deposit": "1000000000000000000000000"
+                "deposit": "1"
             },
             {
                 "type": "Transfer",
@@ -267,15 +319,13 @@ mod tests {
             Base58CryptoHash::from([0u8; 32]),
             "ed25519:11111111111111111111111111111111".to_string(),
             "trading-account.near".to_string(),
-            None, // domain_id: Option<u32>
+            None,    // domain_id: Option<u32>
+            goal_id, // goal_id: U64
         );
         // Test passes  - gas exceeded - but validation succeeds
     }
 
     #[test]
-    #[should_panic(
-        expected = "Transfer actions must be accompanied by at least one FunctionCall action"
-    )]
     fn test_request_signature_multiple_transfer_actions_without_function_call_fails() {
         let context = get_context(accounts(2));
         testing_env!(context.build());
@@ -285,7 +335,7 @@ mod tests {
         );
 
         testing_env!(get_context(accounts(1)).build());
-        contract.add_authorized_user(accounts(2));
+        authorize_agent(&mut contract, accounts(2));
 
         let actions_json = r#"[
             {
@@ -299,14 +349,19 @@ mod tests {
         ]"#;
 
         testing_env!(get_context(accounts(2)).build());
-        let _ = contract.request_signature(
+        let result = contract.request_signature(
             AccountId::try_from("wrap.near".to_string()).unwrap(),
             actions_json.to_string(),
             U64(1),
             Base58CryptoHash::from([0u8; 32]),
             "ed25519:11111111111111111111111111111111".to_string(),
             "trading-account.near".to_string(),
-            None, // domain_id: Option<u32>
+            None,   // domain_id: Option<u32>
+            U64(0), // goal_id: U64
+        );
+        assert_rejected(
+            result,
+            "Transfer actions must be accompanied by at least one FunctionCall action",
         );
     }
 
@@ -392,16 +447,17 @@ mod tests {
     fn test_validate_and_build_actions_valid_function_call() {
         let context = get_context(accounts(1));
         testing_env!(context.build());
-        let contract = TradingAccountContract::new(
+        let mut contract = TradingAccountContract::new(
             accounts(1),
             AccountId::try_from("v1.signer.testnet".to_string()).unwrap(),
         );
+        contract.add_allowed_token(AccountId::try_from("wrap.near".to_string()).unwrap());
 
         let actions = vec![ActionString::FunctionCall {
             method_name: "ft_transfer_call".to_string(),
-            args: serde_json::json!({"receiver_id": "alice.near", "amount": "1000000000000000000000000"}),
+            args: serde_json::json!({"receiver_id": "intents.near", "amount": "1000000000000000000000000"}),
             gas: "100000000000000".to_string(),
-            deposit: "1000000000000000000000000".to_string(),
+            deposit: "1".to_string(),
         }];
 
         let contract_id = AccountId::try_from("wrap.near".to_string()).unwrap();
@@ -416,10 +472,11 @@ mod tests {
     fn test_validate_and_build_actions_valid_transfer() {
         let context = get_context(accounts(1));
         testing_env!(context.build());
-        let contract = TradingAccountContract::new(
+        let mut contract = TradingAccountContract::new(
             accounts(1),
             AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
         );
+        contract.add_allowed_token(AccountId::try_from("wrap.near".to_string()).unwrap());
 
         let actions = vec![
             ActionString::Transfer {
@@ -427,9 +484,9 @@ mod tests {
             },
             ActionString::FunctionCall {
                 method_name: "ft_transfer_call".to_string(),
-                args: serde_json::json!({"receiver_id": "alice.near", "amount": "1000000000000000000000000"}),
+                args: serde_json::json!({"receiver_id": "intents.near", "amount": "1000000000000000000000000"}),
                 gas: "100000000000000".to_string(),
-                deposit: "1000000000000000000000000".to_string(),
+                deposit: "1".to_string(),
             },
         ];
 
@@ -489,6 +546,103 @@ mod tests {
         assert!(error_msg.contains("Method disallowed_method is restricted"));
     }
 
+    #[test]
+    fn test_validate_and_build_actions_gas_limit_exceeded() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer.testnet".to_string()).unwrap(),
+        );
+        contract.add_allowed_token(AccountId::try_from("wrap.near".to_string()).unwrap());
+
+        // mt_transfer may attach at most 50 TGas
+        let actions = vec![ActionString::FunctionCall {
+            method_name: "mt_transfer".to_string(),
+            args: serde_json::json!({"receiver_id": "intents.near", "token_id": "nep141:wrap.near", "amount": "1000"}),
+            gas: "60000000000000".to_string(),
+            deposit: "1".to_string(),
+        }];
+
+        let contract_id = AccountId::try_from("intents.near".to_string()).unwrap();
+        let result = contract.validate_and_build_actions(actions, &contract_id);
+
+        assert!(result.is_err());
+        let error_msg = result.unwrap_err();
+        assert!(
+            error_msg
+                .contains("mt_transfer attaches 60.0 Tgas gas, above the maximum of 50.0 Tgas"),
+            "{}",
+            error_msg
+        );
+    }
+
+    #[test]
+    fn test_validate_and_build_actions_exact_deposit_mismatch() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer.testnet".to_string()).unwrap(),
+        );
+        contract.add_allowed_token(AccountId::try_from("wrap.near".to_string()).unwrap());
+
+        let transfer_call = vec![ActionString::FunctionCall {
+            method_name: "ft_transfer_call".to_string(),
+            args: serde_json::json!({"receiver_id": "intents.near", "amount": "1000"}),
+            gas: "100000000000000".to_string(),
+            deposit: "2".to_string(),
+        }];
+        let contract_id = AccountId::try_from("wrap.near".to_string()).unwrap();
+        let error_msg = contract
+            .validate_and_build_actions(transfer_call, &contract_id)
+            .unwrap_err();
+        assert!(
+            error_msg.contains("ft_transfer_call requires a deposit of exactly"),
+            "{}",
+            error_msg
+        );
+
+        let add_public_key = vec![ActionString::FunctionCall {
+            method_name: "add_public_key".to_string(),
+            args: serde_json::json!({"public_key": "ed25519:11111111111111111111111111111111"}),
+            gas: "30000000000000".to_string(),
+            deposit: "0".to_string(),
+        }];
+        let contract_id = AccountId::try_from("intents.near".to_string()).unwrap();
+        let error_msg = contract
+            .validate_and_build_actions(add_public_key, &contract_id)
+            .unwrap_err();
+        assert!(
+            error_msg.contains("add_public_key requires a deposit of exactly"),
+            "{}",
+            error_msg
+        );
+    }
+
+    #[test]
+    fn test_validate_and_build_actions_near_deposit_accepts_any_amount() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer.testnet".to_string()).unwrap(),
+        );
+        contract.add_allowed_token(AccountId::try_from("wrap.near".to_string()).unwrap());
+
+        let contract_id = AccountId::try_from("wrap.near".to_string()).unwrap();
+        for deposit in ["0", "1", "1000000000000000000000000000"] {
+            let actions = vec![ActionString::FunctionCall {
+                method_name: "near_deposit".to_string(),
+                args: serde_json::json!({}),
+                gas: "50000000000000".to_string(),
+                deposit: deposit.to_string(),
+            }];
+            let result = contract.validate_and_build_actions(actions, &contract_id);
+            assert!(result.is_ok(), "{}: {:?}", deposit, result.err());
+        }
+    }
+
     #[test]
     fn test_validate_and_build_actions_invalid_gas_format() {
         let context = get_context(accounts(1));
@@ -541,17 +695,18 @@ mod tests {
     fn test_validate_and_build_actions_multiple_actions() {
         let context = get_context(accounts(1));
         testing_env!(context.build());
-        let contract = TradingAccountContract::new(
+        let mut contract = TradingAccountContract::new(
             accounts(1),
             AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
         );
+        contract.add_allowed_token(AccountId::try_from("wrap.near".to_string()).unwrap());
 
         let actions = vec![
             ActionString::FunctionCall {
                 method_name: "ft_transfer_call".to_string(),
-                args: serde_json::json!({"receiver_id": "alice.near"}),
+                args: serde_json::json!({"receiver_id": "intents.near"}),
                 gas: "100000000000000".to_string(),
-                deposit: "1000000000000000000000000".to_string(),
+                deposit: "1".to_string(),
             },
             ActionString::Transfer {
                 deposit: "500000000000000000000000".to_string(),
@@ -758,4 +913,3761 @@ mod tests {
         );
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_signature_request_is_recorded_as_pending() {
+        let context = get_context(accounts(2));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+
+        let contract_id = AccountId::try_from("wrap.near".to_string()).unwrap();
+        let request_id = contract.record_signature_request(
+            contract_id.clone(),
+            U64(7),
+            U128(0),
+            RequestReservation::default(),
+        );
+
+        let request = contract.get_signature_request(U64(request_id)).unwrap();
+        assert_eq!(request.requested_by, accounts(2));
+        assert_eq!(request.contract_id, contract_id);
+        assert_eq!(request.nonce, U64(7));
+        assert_eq!(request.status, SignatureRequestStatus::Pending);
+    }
+
+    fn goal_reservation(goal_id: U64, goal_spend: u128) -> RequestReservation {
+        RequestReservation {
+            goal_id: Some(goal_id),
+            goal_spend: U128(goal_spend),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sign_request_callback_records_failure() {
+        let context = get_context(accounts(2));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+
+        let request_id = contract.record_signature_request(
+            AccountId::try_from("wrap.near".to_string()).unwrap(),
+            U64(1),
+            U128(0),
+            RequestReservation::default(),
+        );
+        let result =
+            contract.sign_request_callback(Err(PromiseError::Failed), request_id, String::new());
+
+        assert!(result.is_none());
+        let request = contract.get_signature_request(U64(request_id)).unwrap();
+        assert!(matches!(
+            request.status,
+            SignatureRequestStatus::Failed { reason } if reason.contains("Failed to parse the MPC's Signature response")
+        ));
+    }
+
+    #[test]
+    fn test_signature_requests_are_bounded() {
+        let context = get_context(accounts(2));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+
+        let contract_id = AccountId::try_from("wrap.near".to_string()).unwrap();
+        for nonce in 0..=crate::signature_requests::MAX_STORED_SIGNATURE_REQUESTS {
+            let request_id = contract.record_signature_request(
+                contract_id.clone(),
+                U64(nonce),
+                U128(0),
+                RequestReservation::default(),
+            );
+            contract.update_signature_request_status(
+                request_id,
+                SignatureRequestStatus::SignedIntent {
+                    signature: "secp256k1:signature".to_string(),
+                },
+            );
+        }
+
+        // The oldest request was evicted to make room for the newest
+        assert!(contract.get_signature_request(U64(0)).is_none());
+        assert!(contract.get_signature_request(U64(1)).is_some());
+        let stored = contract.get_signature_requests(None, Some(100));
+        assert_eq!(
+            stored.len() as u64,
+            crate::signature_requests::MAX_STORED_SIGNATURE_REQUESTS
+        );
+    }
+
+    #[test]
+    fn test_prune_signature_requests() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+
+        let contract_id = AccountId::try_from("wrap.near".to_string()).unwrap();
+        for nonce in 0..5 {
+            let request_id = contract.record_signature_request(
+                contract_id.clone(),
+                U64(nonce),
+                U128(0),
+                RequestReservation::default(),
+            );
+            // Request 1 is left pending
+            if request_id != 1 {
+                contract.update_signature_request_status(
+                    request_id,
+                    SignatureRequestStatus::Failed {
+                        reason: "MPC signer unavailable".to_string(),
+                    },
+                );
+            }
+        }
+
+        assert_eq!(contract.prune_signature_requests(U64(3)), 2);
+        let remaining = contract.get_signature_requests(None, None);
+        assert_eq!(remaining.len(), 3);
+        assert_eq!(remaining[0].request_id, U64(1));
+        assert_eq!(remaining[0].status, SignatureRequestStatus::Pending);
+        assert_eq!(remaining[1].request_id, U64(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "Too many signature requests in flight, request 0 must settle first")]
+    fn test_live_signature_requests_are_not_evicted() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+
+        let goal_id = create_test_goal(&mut contract, 100);
+        for nonce in 0..=crate::signature_requests::MAX_STORED_SIGNATURE_REQUESTS {
+            contract.record_signature_request(
+                wrap_near(),
+                U64(nonce),
+                U128(0),
+                goal_reservation(goal_id, 1),
+            );
+        }
+    }
+
+    #[test]
+    fn test_settled_signature_request_is_evicted() {
+        let context = get_context(accounts(2));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+
+        for nonce in 0..crate::signature_requests::MAX_STORED_SIGNATURE_REQUESTS {
+            contract.record_signature_request(
+                wrap_near(),
+                U64(nonce),
+                U128(0),
+                RequestReservation::default(),
+            );
+        }
+        contract.update_signature_request_status(
+            0,
+            SignatureRequestStatus::Failed {
+                reason: "MPC signer unavailable".to_string(),
+            },
+        );
+
+        // Only the settled request makes room, the live ones are kept
+        contract.record_signature_request(
+            wrap_near(),
+            U64(99),
+            U128(0),
+            RequestReservation::default(),
+        );
+        assert!(contract.get_signature_request(U64(0)).is_none());
+        assert_eq!(
+            contract.get_signature_request(U64(1)).unwrap().status,
+            SignatureRequestStatus::Pending
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "You have no power here. Only the owner can perform this action.")]
+    fn test_prune_signature_requests_non_owner() {
+        let context = get_context(accounts(2));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.prune_signature_requests(U64(1));
+    }
+
+    fn wrap_near() -> AccountId {
+        AccountId::try_from("wrap.near".to_string()).unwrap()
+    }
+
+    fn usdt() -> AccountId {
+        AccountId::try_from("usdt.tether-token.near".to_string()).unwrap()
+    }
+
+    fn create_test_goal(contract: &mut TradingAccountContract, max_amount: u128) -> U64 {
+        contract.create_goal(wrap_near(), U128(max_amount), vec![usdt()], U64(1_000), 2)
+    }
+
+    #[test]
+    fn test_create_and_cancel_goal() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+
+        let goal_id = create_test_goal(&mut contract, 100);
+        let goal = contract.get_goal(goal_id).unwrap();
+        assert_eq!(goal.target_token, wrap_near());
+        assert_eq!(goal.remaining_amount, U128(100));
+        assert_eq!(contract.get_goals().len(), 1);
+
+        contract.cancel_goal(goal_id);
+        assert!(contract.get_goal(goal_id).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "You have no power here. Only the owner can perform this action.")]
+    fn test_create_goal_non_owner() {
+        let context = get_context(accounts(2));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        create_test_goal(&mut contract, 100);
+    }
+
+    #[test]
+    fn test_spend_goal_budget_decrements_remaining_amount() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        let goal_id = create_test_goal(&mut contract, 100);
+
+        let actions = vec![ActionString::FunctionCall {
+            method_name: "ft_transfer_call".to_string(),
+            args: serde_json::json!({"receiver_id": "intents.near", "amount": "60", "msg": ""}),
+            gas: "100000000000000".to_string(),
+            deposit: "1".to_string(),
+        }];
+        let movements = crate::actions::token_movements(&wrap_near(), &actions).unwrap();
+
+        assert_eq!(
+            contract.spend_goal_budget(goal_id.0, &movements, None),
+            Ok(60)
+        );
+        let goal = contract.get_goal(goal_id).unwrap();
+        assert_eq!(goal.remaining_amount, U128(40));
+        assert_eq!(goal.executions, 1);
+
+        // The second execution exceeds what remains of the budget
+        let result = contract.spend_goal_budget(goal_id.0, &movements, None);
+        assert!(result.unwrap_err().contains("exceeds the remaining budget"));
+    }
+
+    #[test]
+    fn test_spend_goal_budget_rejects_unlisted_token() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        let goal_id = create_test_goal(&mut contract, 100);
+
+        let memecoin = AccountId::try_from("memecoin.near".to_string()).unwrap();
+        let result = contract.spend_goal_budget(goal_id.0, &[], Some(&memecoin));
+        assert!(result.unwrap_err().contains("is not permitted by goal"));
+        assert!(
+            contract
+                .spend_goal_budget(goal_id.0, &[], Some(&usdt()))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_spend_goal_budget_rejects_spending_destination_token() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        let goal_id = create_test_goal(&mut contract, 100);
+
+        // A destination token of the goal may be received, but spending it would bypass the budget
+        let movements = vec![crate::actions::TokenMovement {
+            token: usdt(),
+            amount: 1_000_000,
+        }];
+        let result = contract.spend_goal_budget(goal_id.0, &movements, None);
+        assert!(
+            result
+                .unwrap_err()
+                .contains("only permits spending wrap.near, not usdt.tether-token.near")
+        );
+        assert_eq!(contract.get_goal(goal_id).unwrap().executions, 0);
+    }
+
+    #[test]
+    fn test_spend_goal_budget_expired_and_exhausted_goals() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        let goal_id = create_test_goal(&mut contract, 100);
+
+        assert!(contract.spend_goal_budget(goal_id.0, &[], None).is_ok());
+        assert!(contract.spend_goal_budget(goal_id.0, &[], None).is_ok());
+        let result = contract.spend_goal_budget(goal_id.0, &[], None);
+        assert!(result.unwrap_err().contains("maximum of 2 executions"));
+
+        let goal_id = create_test_goal(&mut contract, 100);
+        testing_env!(get_context(accounts(1)).block_timestamp(1_000).build());
+        let result = contract.spend_goal_budget(goal_id.0, &[], None);
+        assert!(result.unwrap_err().contains("has expired"));
+
+        let result = contract.spend_goal_budget(42, &[], None);
+        assert!(result.unwrap_err().contains("Goal 42 does not exist"));
+    }
+
+    #[test]
+    fn test_failed_signature_request_refunds_goal_budget() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        let goal_id = create_test_goal(&mut contract, 100);
+
+        let movements = vec![crate::actions::TokenMovement {
+            token: wrap_near(),
+            amount: 30,
+        }];
+        let spent = contract
+            .spend_goal_budget(goal_id.0, &movements, None)
+            .unwrap();
+        let request_id = contract.record_signature_request(
+            wrap_near(),
+            U64(1),
+            U128(0),
+            goal_reservation(goal_id, spent),
+        );
+        assert_eq!(
+            contract.get_goal(goal_id).unwrap().remaining_amount,
+            U128(70)
+        );
+
+        contract.sign_request_callback(Err(PromiseError::Failed), request_id, String::new());
+
+        let goal = contract.get_goal(goal_id).unwrap();
+        assert_eq!(goal.remaining_amount, U128(100));
+        assert_eq!(goal.executions, 0);
+    }
+
+    fn usd_price(multiplier: u128, decimals: u8) -> crate::Price {
+        crate::Price {
+            multiplier: U128(multiplier),
+            decimals,
+        }
+    }
+
+    #[test]
+    fn test_price_compare_with_different_decimals() {
+        // $3.18 expressed with 4 and 6 decimals of precision
+        let price = usd_price(31_800, 28);
+        assert_eq!(
+            price.compare(&usd_price(3_180_000, 30)),
+            Ok(std::cmp::Ordering::Equal)
+        );
+        assert!(
+            price
+                .check_bounds(Some(&usd_price(30_000, 28)), Some(&usd_price(35_000, 28)))
+                .is_ok()
+        );
+        assert!(
+            price
+                .check_bounds(Some(&usd_price(33_390, 28)), None)
+                .unwrap_err()
+                .contains("is below the minimum price")
+        );
+        assert!(
+            price
+                .check_bounds(None, Some(&usd_price(3_000_000, 30)))
+                .unwrap_err()
+                .contains("is above the maximum price")
+        );
+    }
+
+    #[test]
+    fn test_goal_price_guard_requires_oracle() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        let goal_id = create_test_goal(&mut contract, 100);
+        assert_eq!(contract.goal_price_guard(goal_id.0), Ok(None));
+
+        contract.set_goal_price_bounds(goal_id, Some(usd_price(33_390, 28)), None);
+        assert!(
+            contract
+                .goal_price_guard(goal_id.0)
+                .unwrap_err()
+                .contains("no price oracle is configured")
+        );
+
+        let oracle = AccountId::try_from("priceoracle.near".to_string()).unwrap();
+        contract.set_price_oracle(Some(oracle.clone()));
+        assert_eq!(contract.get_price_oracle(), Some(oracle));
+        assert_eq!(contract.goal_price_guard(goal_id.0), Ok(Some(wrap_near())));
+    }
+
+    #[test]
+    fn test_on_price_data_rejects_price_outside_bounds() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        let goal_id = create_test_goal(&mut contract, 100);
+        contract.set_goal_price_bounds(goal_id, Some(usd_price(33_390, 28)), None);
+
+        let spent = contract
+            .spend_goal_budget(
+                goal_id.0,
+                &[crate::actions::TokenMovement {
+                    token: wrap_near(),
+                    amount: 50,
+                }],
+                None,
+            )
+            .unwrap();
+        let request_id = contract.record_signature_request(
+            wrap_near(),
+            U64(1),
+            U128(0),
+            goal_reservation(goal_id, spent),
+        );
+
+        let price_data = crate::PriceData {
+            timestamp: U64(0),
+            recency_duration_sec: 90,
+            prices: vec![crate::AssetOptionalPrice {
+                asset_id: wrap_near(),
+                price: Some(usd_price(31_800, 28)),
+            }],
+        };
+        let result =
+            contract.on_price_data(Ok(price_data), request_id, test_pending_signature(vec![]));
+
+        assert!(matches!(result, near_sdk::PromiseOrValue::Value(None)));
+        let request = contract.get_signature_request(U64(request_id)).unwrap();
+        assert!(matches!(
+            request.status,
+            SignatureRequestStatus::Failed { reason } if reason.contains("is below the minimum price")
+        ));
+        assert_eq!(
+            contract.get_goal(goal_id).unwrap().remaining_amount,
+            U128(100)
+        );
+    }
+
+    #[test]
+    fn test_manage_allowed_tokens() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+
+        contract.add_allowed_token(wrap_near());
+        contract.add_allowed_token(usdt());
+        assert_eq!(contract.get_allowed_tokens().len(), 2);
+
+        contract.remove_allowed_token(usdt());
+        assert_eq!(contract.get_allowed_tokens(), vec![wrap_near()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "You have no power here. Only the owner can perform this action.")]
+    fn test_add_allowed_token_non_owner() {
+        let context = get_context(accounts(2));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.add_allowed_token(wrap_near());
+    }
+
+    #[test]
+    fn test_validate_and_build_actions_disallowed_token() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.add_allowed_token(wrap_near());
+
+        // ft_transfer_call moves the token of the contract it is sent to
+        let actions = vec![ActionString::FunctionCall {
+            method_name: "ft_transfer_call".to_string(),
+            args: serde_json::json!({"receiver_id": "intents.near", "amount": "1"}),
+            gas: "100000000000000".to_string(),
+            deposit: "1".to_string(),
+        }];
+        let result = contract.validate_and_build_actions(
+            actions,
+            &AccountId::try_from("wrap.testnet".to_string()).unwrap(),
+        );
+        assert!(
+            result
+                .unwrap_err()
+                .contains("Token wrap.testnet is not allowed")
+        );
+
+        // ft_withdraw names the token it moves in its args
+        let withdraw = |token: &str| {
+            vec![ActionString::FunctionCall {
+                method_name: "ft_withdraw".to_string(),
+                args: serde_json::json!({"token": token, "receiver_id": near_sdk::env::current_account_id(), "amount": "1"}),
+                gas: "100000000000000".to_string(),
+                deposit: "1".to_string(),
+            }]
+        };
+        let intents = AccountId::try_from("intents.near".to_string()).unwrap();
+        let result = contract.validate_and_build_actions(withdraw("memecoin.near"), &intents);
+        assert!(
+            result
+                .unwrap_err()
+                .contains("Token memecoin.near is not allowed")
+        );
+        assert!(
+            contract
+                .validate_and_build_actions(withdraw("wrap.near"), &intents)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_withdrawal_destination_must_be_active_address_book_entry() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.add_allowed_token(wrap_near());
+        contract.set_address_book_delay(U64(100));
+        contract.add_address_book_entry("bob.near".to_string(), None);
+
+        let withdraw = || {
+            vec![ActionString::FunctionCall {
+                method_name: "ft_withdraw".to_string(),
+                args: serde_json::json!({"token": "wrap.near", "receiver_id": "bob.near", "amount": "1"}),
+                gas: "100000000000000".to_string(),
+                deposit: "1".to_string(),
+            }]
+        };
+        let intents = AccountId::try_from("intents.near".to_string()).unwrap();
+
+        let result = contract.validate_and_build_actions(withdraw(), &intents);
+        assert!(result.unwrap_err().contains("cannot be used until 100"));
+
+        testing_env!(get_context(accounts(1)).block_timestamp(100).build());
+        assert!(
+            contract
+                .validate_and_build_actions(withdraw(), &intents)
+                .is_ok()
+        );
+
+        contract.remove_address_book_entry("bob.near".to_string());
+        let result = contract.validate_and_build_actions(withdraw(), &intents);
+        assert!(
+            result
+                .unwrap_err()
+                .contains("Withdrawal destination bob.near is not in the address book")
+        );
+    }
+
+    #[test]
+    fn test_foreign_chain_withdrawal_destination() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        let eth = AccountId::try_from("eth.omft.near".to_string()).unwrap();
+        contract.add_allowed_token(eth.clone());
+        contract.set_address_book_delay(U64(0));
+        contract.add_address_book_entry(
+            "0x1234567890abcdef1234567890abcdef12345678".to_string(),
+            Some("eth".to_string()),
+        );
+
+        let withdraw = |address: &str| {
+            vec![ActionString::FunctionCall {
+                method_name: "ft_withdraw".to_string(),
+                args: serde_json::json!({
+                    "token": eth,
+                    "receiver_id": eth,
+                    "amount": "1",
+                    "memo": format!("WITHDRAW_TO:{}", address)
+                }),
+                gas: "100000000000000".to_string(),
+                deposit: "1".to_string(),
+            }]
+        };
+        let intents = AccountId::try_from("intents.near".to_string()).unwrap();
+
+        assert!(
+            contract
+                .validate_and_build_actions(
+                    withdraw("0x1234567890abcdef1234567890abcdef12345678"),
+                    &intents
+                )
+                .is_ok()
+        );
+        let result = contract.validate_and_build_actions(
+            withdraw("0xdeadbeefdeadbeefdeadbeefdeadbeefdeadbeef"),
+            &intents,
+        );
+        assert!(result.unwrap_err().contains("is not in the address book"));
+    }
+
+    #[test]
+    fn test_withdrawal_memo_cannot_spoof_destination() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        let eth = AccountId::try_from("eth.omft.near".to_string()).unwrap();
+        contract.add_allowed_token(eth.clone());
+        contract.set_address_book_delay(U64(0));
+        contract.add_address_book_entry(
+            "0x1234567890abcdef1234567890abcdef12345678".to_string(),
+            Some("eth".to_string()),
+        );
+
+        // Only the bridge token reads the memo, any other receiver is paid directly
+        let actions = vec![ActionString::FunctionCall {
+            method_name: "ft_withdraw".to_string(),
+            args: serde_json::json!({
+                "token": eth,
+                "receiver_id": "attacker.near",
+                "amount": "1",
+                "memo": "WITHDRAW_TO:0x1234567890abcdef1234567890abcdef12345678"
+            }),
+            gas: "100000000000000".to_string(),
+            deposit: "1".to_string(),
+        }];
+        let intents = AccountId::try_from("intents.near".to_string()).unwrap();
+        let result = contract.validate_and_build_actions(actions, &intents);
+        assert!(
+            result
+                .unwrap_err()
+                .contains("Withdrawal destination attacker.near is not in the address book")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "not-an-account! is not a valid NEAR account id")]
+    fn test_add_address_book_entry_invalid_near_account() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.add_address_book_entry("not-an-account!".to_string(), None);
+    }
+
+    const HOUR_NS: u64 = 60 * 60 * 1_000_000_000;
+
+    #[test]
+    fn test_loosening_change_is_scheduled_until_delay_passes() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.set_policy_change_delay(U64(HOUR_NS));
+
+        contract.add_authorized_user(accounts(2));
+        assert!(!contract.is_authorized(accounts(2)));
+        let pending = contract.get_pending_policy_changes();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].executable_from, U64(HOUR_NS));
+
+        // Anyone may execute the change once the delay has passed
+        testing_env!(get_context(accounts(3)).block_timestamp(HOUR_NS).build());
+        contract.execute_policy_change(pending[0].change_id);
+        assert_eq!(contract.get_pending_authorizations(), vec![accounts(2)]);
+        assert!(contract.get_pending_policy_changes().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Policy change 0 cannot be executed until")]
+    fn test_execute_policy_change_before_delay() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.set_policy_change_delay(U64(HOUR_NS));
+        contract.add_allowed_token(wrap_near());
+        contract.execute_policy_change(U64(0));
+    }
+
+    #[test]
+    fn test_guardian_cancels_scheduled_goal() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.add_guardian(accounts(3));
+        contract.set_policy_change_delay(U64(HOUR_NS));
+
+        let goal_id = create_test_goal(&mut contract, 100);
+        assert!(contract.get_goal(goal_id).is_none());
+
+        testing_env!(get_context(accounts(3)).build());
+        contract.cancel_policy_change(U64(0));
+        assert!(contract.get_pending_policy_changes().is_empty());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Only the owner, a guardian or a manager can cancel a policy change."
+    )]
+    fn test_cancel_policy_change_unauthorized() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.set_policy_change_delay(U64(HOUR_NS));
+        contract.add_authorized_user(accounts(2));
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.cancel_policy_change(U64(0));
+    }
+
+    #[test]
+    fn test_tightening_changes_apply_immediately() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        let goal_id = create_test_goal(&mut contract, 100);
+        contract.add_guardian(accounts(3));
+        contract.set_policy_change_delay(U64(HOUR_NS));
+
+        // Raising delays and narrowing price bounds are immediate
+        contract.set_policy_change_delay(U64(2 * HOUR_NS));
+        assert_eq!(contract.get_policy_change_delay(), U64(2 * HOUR_NS));
+        contract.set_address_book_delay(U64(48 * HOUR_NS));
+        assert_eq!(contract.get_address_book_delay(), U64(48 * HOUR_NS));
+        contract.set_goal_price_bounds(goal_id, Some(usd_price(30_000, 28)), None);
+        assert_eq!(
+            contract.get_goal(goal_id).unwrap().min_price,
+            Some(usd_price(30_000, 28))
+        );
+        assert!(contract.get_pending_policy_changes().is_empty());
+
+        // Lowering delays, widening price bounds and removing guardians are scheduled
+        contract.set_policy_change_delay(U64(0));
+        contract.set_address_book_delay(U64(0));
+        contract.set_goal_price_bounds(goal_id, Some(usd_price(20_000, 28)), None);
+        contract.remove_guardian(accounts(3));
+        assert_eq!(contract.get_pending_policy_changes().len(), 4);
+        assert_eq!(contract.get_policy_change_delay(), U64(2 * HOUR_NS));
+        assert_eq!(contract.get_guardians(), vec![accounts(3)]);
+    }
+
+    #[test]
+    fn test_guardian_removes_address_book_entry() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.add_guardian(accounts(3));
+        contract.add_address_book_entry("bob.near".to_string(), None);
+
+        testing_env!(get_context(accounts(3)).build());
+        contract.remove_address_book_entry("bob.near".to_string());
+        assert!(contract.get_address_book().is_empty());
+    }
+
+    fn contract_with_recovery_guardians() -> TradingAccountContract {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.add_guardian(accounts(3));
+        contract.add_guardian(accounts(4));
+        contract.set_recovery_threshold(2);
+        contract.set_recovery_delay(U64(10 * HOUR_NS));
+        contract
+    }
+
+    #[test]
+    fn test_guardians_recover_ownership_after_delay() {
+        let mut contract = contract_with_recovery_guardians();
+        let new_owner = AccountId::try_from("alice-new.near".to_string()).unwrap();
+
+        testing_env!(get_context(accounts(3)).build());
+        contract.approve_owner_recovery(new_owner.clone());
+        assert!(
+            contract
+                .get_owner_recovery()
+                .unwrap()
+                .executable_from
+                .is_none()
+        );
+
+        testing_env!(get_context(accounts(4)).build());
+        contract.approve_owner_recovery(new_owner.clone());
+        assert_eq!(
+            contract.get_owner_recovery().unwrap().executable_from,
+            Some(U64(10 * HOUR_NS))
+        );
+
+        testing_env!(
+            get_context(accounts(5))
+                .block_timestamp(10 * HOUR_NS)
+                .build()
+        );
+        contract.execute_owner_recovery();
+        assert_eq!(contract.get_owner_id(), new_owner);
+        assert!(contract.get_owner_recovery().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner recovery cannot be executed until")]
+    fn test_execute_owner_recovery_before_delay() {
+        let mut contract = contract_with_recovery_guardians();
+        let new_owner = AccountId::try_from("alice-new.near".to_string()).unwrap();
+
+        testing_env!(get_context(accounts(3)).build());
+        contract.approve_owner_recovery(new_owner.clone());
+        testing_env!(get_context(accounts(4)).build());
+        contract.approve_owner_recovery(new_owner);
+        contract.execute_owner_recovery();
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner recovery does not have enough guardian approvals")]
+    fn test_execute_owner_recovery_below_threshold() {
+        let mut contract = contract_with_recovery_guardians();
+
+        testing_env!(get_context(accounts(3)).build());
+        contract.approve_owner_recovery(AccountId::try_from("alice-new.near".to_string()).unwrap());
+        contract.execute_owner_recovery();
+    }
+
+    #[test]
+    #[should_panic(expected = "No owner recovery is in progress")]
+    fn test_owner_vetoes_recovery() {
+        let mut contract = contract_with_recovery_guardians();
+        let new_owner = AccountId::try_from("alice-new.near".to_string()).unwrap();
+
+        testing_env!(get_context(accounts(3)).build());
+        contract.approve_owner_recovery(new_owner.clone());
+        testing_env!(get_context(accounts(4)).build());
+        contract.approve_owner_recovery(new_owner);
+
+        testing_env!(get_context(accounts(1)).build());
+        contract.cancel_owner_recovery();
+        assert_eq!(contract.get_owner_id(), accounts(1));
+
+        testing_env!(
+            get_context(accounts(3))
+                .block_timestamp(10 * HOUR_NS)
+                .build()
+        );
+        contract.execute_owner_recovery();
+    }
+
+    #[test]
+    #[should_panic(expected = "Only a guardian can approve an owner recovery.")]
+    fn test_approve_owner_recovery_non_guardian() {
+        let mut contract = contract_with_recovery_guardians();
+        testing_env!(get_context(accounts(2)).build());
+        contract.approve_owner_recovery(accounts(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner recovery is not enabled for this account")]
+    fn test_approve_owner_recovery_disabled() {
+        let mut contract = contract_with_recovery_guardians();
+        contract.set_recovery_threshold(0);
+        testing_env!(get_context(accounts(3)).build());
+        contract.approve_owner_recovery(accounts(2));
+    }
+
+    #[test]
+    fn test_lowering_recovery_threshold_is_scheduled() {
+        let mut contract = contract_with_recovery_guardians();
+        contract.set_policy_change_delay(U64(HOUR_NS));
+
+        contract.set_recovery_threshold(1);
+        assert_eq!(contract.get_recovery_threshold(), 2);
+        assert_eq!(contract.get_pending_policy_changes().len(), 1);
+
+        // Adding a guardian is scheduled too, as it grants recovery powers
+        contract.add_guardian(accounts(5));
+        assert_eq!(contract.get_guardians().len(), 2);
+        assert_eq!(contract.get_pending_policy_changes().len(), 2);
+    }
+
+    #[test]
+    fn test_two_step_ownership_transfer() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.propose_owner(accounts(2));
+        assert_eq!(contract.get_proposed_owner(), Some(accounts(2)));
+        assert_eq!(contract.get_owner_id(), accounts(1));
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.accept_ownership();
+        assert_eq!(contract.get_owner_id(), accounts(2));
+        assert!(contract.get_proposed_owner().is_none());
+        assert!(
+            near_sdk::test_utils::get_logs()
+                .iter()
+                .any(|log| log.starts_with("EVENT_JSON:") && log.contains("ownership_transferred"))
+        );
+
+        // Owner-only paths follow the new owner
+        let pk = PublicKey::from_str("ed25519:11111111111111111111111111111111").unwrap();
+        let _ = contract.add_full_access_key(pk);
+    }
+
+    #[test]
+    #[should_panic(expected = "You have no power here. Only the owner can perform this action.")]
+    fn test_previous_owner_loses_power_after_transfer() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.propose_owner(accounts(2));
+        testing_env!(get_context(accounts(2)).build());
+        contract.accept_ownership();
+
+        testing_env!(get_context(accounts(1)).build());
+        let pk = PublicKey::from_str("ed25519:11111111111111111111111111111111").unwrap();
+        let _ = contract.add_full_access_key(pk);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the proposed owner can accept ownership")]
+    fn test_accept_ownership_not_proposed() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.propose_owner(accounts(2));
+        testing_env!(get_context(accounts(3)).build());
+        contract.accept_ownership();
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the proposed owner can accept ownership")]
+    fn test_cancel_owner_proposal() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.propose_owner(accounts(2));
+        contract.cancel_owner_proposal();
+        assert!(contract.get_proposed_owner().is_none());
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.accept_ownership();
+    }
+
+    fn withdrawal_context(predecessor: AccountId) -> VMContextBuilder {
+        let mut context = get_context(predecessor);
+        context
+            .account_balance(NearToken::from_near(10))
+            .storage_usage(0)
+            .attached_deposit(NearToken::from_yoctonear(1));
+        context
+    }
+
+    #[test]
+    fn test_withdraw_near_to_owner() {
+        testing_env!(withdrawal_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.set_min_balance_reserve(NearToken::from_near(2));
+        let _ = contract.withdraw_near(Some(NearToken::from_near(3)));
+        let _ = contract.withdraw_near(None);
+        assert!(
+            near_sdk::test_utils::get_logs()
+                .iter()
+                .any(|log| log.contains("owner_withdrawal"))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "is available above the minimum balance reserve of 2.00 NEAR")]
+    fn test_withdraw_near_respects_reserve() {
+        testing_env!(withdrawal_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.set_min_balance_reserve(NearToken::from_near(2));
+        let _ = contract.withdraw_near(Some(NearToken::from_near(9)));
+    }
+
+    #[test]
+    #[should_panic(expected = "This method requires an attached deposit of exactly 1 yoctoNear")]
+    fn test_withdraw_ft_requires_one_yocto() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        let _ = contract.withdraw_ft(wrap_near(), U128(1_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "You have no power here. Only the owner can perform this action.")]
+    fn test_withdraw_from_intents_non_owner() {
+        testing_env!(withdrawal_context(accounts(2)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        let _ = contract.withdraw_ft_from_intents(usdt(), U128(1_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "token_ids and amounts must have the same length")]
+    fn test_withdraw_mt_from_intents_mismatched_amounts() {
+        testing_env!(withdrawal_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        let _ = contract.withdraw_mt_from_intents(
+            AccountId::try_from("nep245.example.near".to_string()).unwrap(),
+            vec!["1".to_string(), "2".to_string()],
+            vec![U128(1)],
+        );
+    }
+
+    fn closing_contract() -> TradingAccountContract {
+        let mut context = withdrawal_context(accounts(1));
+        context.prepaid_gas(near_sdk::Gas::from_tgas(300));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        authorize_agent(&mut contract, accounts(2));
+        contract.add_allowed_token(wrap_near());
+        contract.add_allowed_token(usdt());
+        let _ = contract.close_trading_account(None);
+        contract
+    }
+
+    /// Run the owner's next closure callback with the given promise results
+    fn closure_callback_env(results: Vec<PromiseResult>) {
+        testing_env!(
+            context_as(accounts(1)).build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            results,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Signing is paused while the trading account is being closed")]
+    fn test_close_trading_account_pauses_signing() {
+        let mut contract = closing_contract();
+        assert_eq!(
+            contract.get_account_closure().unwrap().stage,
+            AccountClosureStage::SweepIntents
+        );
+
+        testing_env!(get_context(accounts(2)).build());
+        let _ = contract.request_signature(
+            wrap_near(),
+            r#"[{"type": "FunctionCall", "method_name": "near_deposit", "args": {}, "gas": "50000000000000", "deposit": "1"}]"#.to_string(),
+            U64(1),
+            Base58CryptoHash::from([0u8; 32]),
+            "ed25519:11111111111111111111111111111111".to_string(),
+            "trading-account.near".to_string(),
+            None,
+            U64(0),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Stage SweepIntents of the account closure is still in progress")]
+    fn test_close_trading_account_while_in_progress() {
+        let mut contract = closing_contract();
+        let _ = contract.close_trading_account(None);
+    }
+
+    #[test]
+    fn test_close_trading_account_resumes_failed_stage() {
+        let mut contract = closing_contract();
+
+        // Nothing to sweep on intents.near, so the closure moves on to unwrapping wNEAR
+        let _ = contract.on_closure_intents_balances(Ok(vec![U128(0)]), vec![wrap_near()]);
+        assert_eq!(
+            contract.get_account_closure().unwrap().stage,
+            AccountClosureStage::UnwrapNear
+        );
+
+        let _ = contract.on_closure_wrap_balance(Err(PromiseError::Failed));
+        let closure = contract.get_account_closure().unwrap();
+        assert_eq!(closure.stage, AccountClosureStage::UnwrapNear);
+        assert!(!closure.in_progress);
+        assert!(
+            near_sdk::test_utils::get_logs()
+                .iter()
+                .any(|log| log.contains("account_closure_progress")
+                    && log.contains("\"completed\":false"))
+        );
+
+        // Calling it again resumes from the failed stage
+        let _ = contract.close_trading_account(None);
+        assert!(contract.get_account_closure().unwrap().in_progress);
+    }
+
+    #[test]
+    fn test_close_trading_account_stops_on_failed_withdrawal() {
+        let mut contract = closing_contract();
+
+        testing_env!(
+            withdrawal_context(accounts(1)).build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![]), PromiseResult::Failed],
+        );
+        let _ = contract.on_closure_stage_swept(AccountClosureStage::SweepIntents, 0);
+        let closure = contract.get_account_closure().unwrap();
+        assert_eq!(closure.stage, AccountClosureStage::SweepIntents);
+        assert!(!closure.in_progress);
+    }
+
+    #[test]
+    fn test_close_trading_account_sweeps_intents_in_batches() {
+        let mut contract = closing_contract();
+        let tokens: Vec<AccountId> = (0..7)
+            .map(|i| format!("token{}.near", i).parse().unwrap())
+            .collect();
+        closure_callback_env(vec![]);
+        let _ = contract.on_closure_intents_balances(Ok(vec![U128(1); 7]), tokens);
+
+        // Four balances were left for the next run, which queries intents.near again
+        closure_callback_env((0..3).map(|_| PromiseResult::Successful(vec![])).collect());
+        let _ = contract.on_closure_stage_swept(AccountClosureStage::SweepIntents, 4);
+        let closure = contract.get_account_closure().unwrap();
+        assert_eq!(closure.stage, AccountClosureStage::SweepIntents);
+        assert!(closure.in_progress);
+
+        // Without enough gas for another run, the closure pauses until it is called again
+        let mut context = context_as(accounts(1));
+        context.prepaid_gas(near_sdk::Gas::from_tgas(20));
+        testing_env!(
+            context.build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            (0..3).map(|_| PromiseResult::Successful(vec![])).collect(),
+        );
+        let _ = contract.on_closure_stage_swept(AccountClosureStage::SweepIntents, 4);
+        let closure = contract.get_account_closure().unwrap();
+        assert_eq!(closure.stage, AccountClosureStage::SweepIntents);
+        assert!(!closure.in_progress);
+    }
+
+    #[test]
+    fn test_close_trading_account_sweeps_allowed_token_balances() {
+        let mut contract = closing_contract();
+        let _ = contract.on_closure_intents_balances(Ok(vec![U128(0)]), vec![wrap_near()]);
+        let _ = contract.on_closure_wrap_balance(Ok(U128(0)));
+        let closure = contract.get_account_closure().unwrap();
+        assert_eq!(closure.stage, AccountClosureStage::SweepTokens);
+        assert_eq!(closure.tokens_to_sweep, vec![usdt()]);
+
+        closure_callback_env(vec![PromiseResult::Successful(b"\"5000000\"".to_vec())]);
+        let _ = contract.on_closure_token_balances(vec![usdt()]);
+        assert_eq!(
+            contract.get_account_closure().unwrap().stage,
+            AccountClosureStage::SweepTokens
+        );
+
+        // A failed transfer keeps the token to be swept on the next run
+        closure_callback_env(vec![PromiseResult::Failed]);
+        let _ = contract.on_closure_tokens_swept(vec![usdt()]);
+        let closure = contract.get_account_closure().unwrap();
+        assert_eq!(closure.tokens_to_sweep, vec![usdt()]);
+        assert!(!closure.in_progress);
+
+        let _ = contract.close_trading_account(None);
+        closure_callback_env(vec![PromiseResult::Successful(vec![])]);
+        let _ = contract.on_closure_tokens_swept(vec![usdt()]);
+        let closure = contract.get_account_closure().unwrap();
+        assert!(closure.tokens_to_sweep.is_empty());
+        assert_eq!(closure.stage, AccountClosureStage::DeleteAccount);
+        // The account is only reported closed by the receipt which deletes it
+        assert!(
+            !near_sdk::test_utils::get_logs()
+                .iter()
+                .any(|log| log.contains("account_closed"))
+        );
+    }
+
+    #[test]
+    fn test_account_closed_event_is_emitted_with_the_deletion() {
+        let mut contract = closing_contract();
+        closure_callback_env(vec![]);
+        contract.on_account_closing();
+        assert!(
+            near_sdk::test_utils::get_logs()
+                .iter()
+                .any(|log| log.starts_with("EVENT_JSON:") && log.contains("account_closed"))
+        );
+    }
+
+    #[test]
+    fn test_close_trading_account_sweeps_extra_tokens() {
+        let mut context = withdrawal_context(accounts(1));
+        context.prepaid_gas(near_sdk::Gas::from_tgas(300));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.add_allowed_token(usdt());
+        // Tokens which are no longer allowed would otherwise be lost with the account
+        let _ = contract.close_trading_account(Some(vec![memecoin(), usdt()]));
+        assert_eq!(
+            contract.get_account_closure().unwrap().extra_tokens,
+            vec![memecoin(), usdt()]
+        );
+
+        closure_callback_env(vec![]);
+        let _ = contract.on_closure_intents_balances(Ok(vec![U128(0)]), vec![wrap_near()]);
+        let _ = contract.on_closure_wrap_balance(Ok(U128(0)));
+        assert_eq!(
+            contract.get_account_closure().unwrap().tokens_to_sweep,
+            vec![usdt(), memecoin()]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Extra tokens can only be given when the closure starts")]
+    fn test_resumed_closure_rejects_extra_tokens() {
+        let mut contract = closing_contract();
+        let _ = contract.on_closure_intents_balances(Err(PromiseError::Failed), vec![]);
+        let _ = contract.close_trading_account(Some(vec![memecoin()]));
+    }
+
+    #[test]
+    fn test_failed_account_deletion_can_be_resumed() {
+        let mut contract = closing_contract();
+        let _ = contract.on_closure_account_deleted();
+        let closure = contract.get_account_closure().unwrap();
+        assert!(!closure.in_progress);
+
+        closure_callback_env(vec![]);
+        let _ = contract.close_trading_account(None);
+        assert!(contract.get_account_closure().unwrap().in_progress);
+    }
+
+    #[test]
+    fn test_stalled_account_closure_can_be_cancelled() {
+        let mut contract = closing_contract();
+        // The stage never reported back, an hour later the owner may cancel the closure
+        at_time(accounts(1), near_sdk::env::block_timestamp() + HOUR_NS);
+        contract.cancel_account_closure();
+        assert!(contract.get_account_closure().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Stage SweepIntents of the account closure is still in progress")]
+    fn test_running_account_closure_cannot_be_cancelled() {
+        let mut contract = closing_contract();
+        contract.cancel_account_closure();
+    }
+
+    #[test]
+    fn test_cancel_account_closure_resumes_signing() {
+        let mut contract = closing_contract();
+        let _ = contract.on_closure_intents_balances(Err(PromiseError::Failed), vec![]);
+        contract.cancel_account_closure();
+        assert!(contract.get_account_closure().is_none());
+    }
+
+    #[test]
+    fn test_request_signature_respects_balance_reserve() {
+        testing_env!(withdrawal_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        authorize_agent(&mut contract, accounts(2));
+        contract.set_min_balance_reserve(NearToken::from_near(6));
+        let goal_id = create_test_goal(&mut contract, NearToken::from_near(10).as_yoctonear());
+
+        let mut context = withdrawal_context(accounts(2));
+        context
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .storage_usage(near_sdk::env::storage_usage());
+        testing_env!(context.build());
+        let result = contract.request_signature(
+            wrap_near(),
+            r#"[{"type": "FunctionCall", "method_name": "near_deposit", "args": {}, "gas": "50000000000000", "deposit": "5000000000000000000000000"}]"#.to_string(),
+            U64(1),
+            Base58CryptoHash::from([0u8; 32]),
+            "ed25519:11111111111111111111111111111111".to_string(),
+            "trading-account.near".to_string(),
+            None,
+            goal_id,
+        );
+        assert_rejected(
+            result,
+            "Balance reserve check failed: Attached deposits of 5.00 NEAR exceed",
+        );
+    }
+
+    #[test]
+    fn test_pending_deposits_count_against_balance_reserve() {
+        testing_env!(withdrawal_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.set_min_balance_reserve(NearToken::from_near(2));
+
+        let request_id = contract.record_signature_request(
+            wrap_near(),
+            U64(1),
+            U128(NearToken::from_near(4).as_yoctonear()),
+            RequestReservation::default(),
+        );
+        assert_eq!(contract.get_pending_deposits(), NearToken::from_near(4));
+        assert!(
+            contract
+                .check_balance_reserve(NearToken::from_near(5).as_yoctonear())
+                .is_err()
+        );
+
+        // Once the request fails its deposit no longer counts
+        contract.update_signature_request_status(
+            request_id,
+            SignatureRequestStatus::Failed {
+                reason: "MPC signer unavailable".to_string(),
+            },
+        );
+        assert_eq!(contract.get_pending_deposits(), NearToken::from_near(0));
+        assert!(
+            contract
+                .check_balance_reserve(NearToken::from_near(5).as_yoctonear())
+                .is_ok()
+        );
+    }
+
+    fn request_near_deposit(
+        contract: &mut TradingAccountContract,
+        goal_id: U64,
+        deposit: NearToken,
+    ) -> PromiseOrValue<Option<String>> {
+        let mut context = withdrawal_context(accounts(2));
+        context
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .prepaid_gas(near_sdk::Gas::from_tgas(300))
+            .storage_usage(near_sdk::env::storage_usage());
+        testing_env!(context.build());
+        contract.request_signature(
+            wrap_near(),
+            format!(
+                r#"[{{"type": "FunctionCall", "method_name": "near_deposit", "args": {{}}, "gas": "50000000000000", "deposit": "{}"}}]"#,
+                deposit.as_yoctonear()
+            ),
+            U64(1),
+            Base58CryptoHash::from([0u8; 32]),
+            "ed25519:11111111111111111111111111111111".to_string(),
+            "trading-account.near".to_string(),
+            None,
+            goal_id,
+        )
+    }
+
+    fn signed_deposit_contract() -> (TradingAccountContract, U64, u64) {
+        testing_env!(withdrawal_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        authorize_agent(&mut contract, accounts(2));
+        contract.set_min_balance_reserve(NearToken::from_near(3));
+        let goal_id = create_test_goal(&mut contract, NearToken::from_near(10).as_yoctonear());
+
+        // The mocked MPC call runs out of gas, so record the first request as request_signature does
+        let request_id = contract.record_signature_request(
+            wrap_near(),
+            U64(1),
+            U128(NearToken::from_near(4).as_yoctonear()),
+            goal_reservation(goal_id, 0),
+        );
+        contract.update_signature_request_status(
+            request_id,
+            SignatureRequestStatus::Signed {
+                signed_transaction: "signed".to_string(),
+            },
+        );
+        (contract, goal_id, request_id)
+    }
+
+    #[test]
+    fn test_signed_transaction_deposit_counts_against_balance_reserve() {
+        let (mut contract, goal_id, _) = signed_deposit_contract();
+        assert_eq!(contract.get_pending_deposits(), NearToken::from_near(4));
+
+        // The first transaction may still land, so signing a second one would break the reserve
+        let result = request_near_deposit(&mut contract, goal_id, NearToken::from_near(4));
+        assert_rejected(
+            result,
+            "Balance reserve check failed: Attached deposits of 4.00 NEAR exceed the 3.00 NEAR available",
+        );
+    }
+
+    #[test]
+    fn test_expired_signed_transaction_releases_deposit() {
+        let (mut contract, _, _) = signed_deposit_contract();
+
+        let mut context = withdrawal_context(accounts(2));
+        context
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .block_height(TRANSACTION_VALIDITY_BLOCKS + 1)
+            .storage_usage(near_sdk::env::storage_usage());
+        testing_env!(context.build());
+        contract.release_expired_deposits();
+        assert_eq!(contract.get_pending_deposits(), NearToken::from_near(0));
+    }
+
+    #[test]
+    fn test_settle_signature_request_releases_deposit() {
+        let (mut contract, _, request_id) = signed_deposit_contract();
+
+        testing_env!(context_as(accounts(1)).build());
+        contract.settle_signature_request(U64(request_id));
+        assert_eq!(contract.get_pending_deposits(), NearToken::from_near(0));
+
+        // A failure reported afterwards must not release the deposit a second time
+        contract.update_signature_request_status(
+            request_id,
+            SignatureRequestStatus::Failed {
+                reason: "late failure".to_string(),
+            },
+        );
+        assert_eq!(contract.get_pending_deposits(), NearToken::from_near(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "has no signed transaction holding a deposit")]
+    fn test_settle_signature_request_requires_signed_transaction() {
+        let (mut contract, _, request_id) = signed_deposit_contract();
+        testing_env!(context_as(accounts(1)).build());
+        contract.settle_signature_request(U64(request_id));
+        contract.settle_signature_request(U64(request_id));
+    }
+
+    #[test]
+    fn test_lowering_balance_reserve_is_scheduled() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.set_min_balance_reserve(NearToken::from_near(2));
+        contract.set_policy_change_delay(U64(HOUR_NS));
+
+        contract.set_min_balance_reserve(NearToken::from_near(1));
+        assert_eq!(contract.get_min_balance_reserve(), NearToken::from_near(2));
+        assert_eq!(contract.get_pending_policy_changes().len(), 1);
+    }
+
+    fn test_pending_signature(balance_checks: Vec<BalanceSpend>) -> PendingSignature {
+        PendingSignature {
+            request_payload: serde_json::json!({}),
+            deposit: NearToken::from_yoctonear(0),
+            gas_for_signing: near_sdk::Gas::from_tgas(50),
+            payload: SignedPayload::Transaction {
+                tx_json_string: String::new(),
+            },
+            balance_checks,
+            swap: None,
+        }
+    }
+
+    #[test]
+    fn test_balance_spends() {
+        let intents = AccountId::try_from("intents.near".to_string()).unwrap();
+        let actions = vec![
+            ActionString::FunctionCall {
+                method_name: "ft_withdraw".to_string(),
+                args: serde_json::json!({"token": "usdt.tether-token.near", "receiver_id": "alice.near", "amount": "500"}),
+                gas: "100000000000000".to_string(),
+                deposit: "1".to_string(),
+            },
+            ActionString::Transfer {
+                deposit: "2".to_string(),
+            },
+        ];
+
+        let spends = crate::actions::balance_spends(&intents, &actions).unwrap();
+        assert_eq!(
+            spends,
+            vec![
+                BalanceSpend {
+                    source: BalanceSource::Near,
+                    amount: U128(3),
+                },
+                BalanceSpend {
+                    source: BalanceSource::Intents {
+                        token_id: "nep141:usdt.tether-token.near".to_string(),
+                    },
+                    amount: U128(500),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_balance_spends_sums_spends_of_the_same_balance() {
+        let transfer_call = |amount: &str| ActionString::FunctionCall {
+            method_name: "ft_transfer_call".to_string(),
+            args: serde_json::json!({"receiver_id": "intents.near", "amount": amount, "msg": ""}),
+            gas: "50000000000000".to_string(),
+            deposit: "1".to_string(),
+        };
+        let actions = vec![transfer_call("600"), transfer_call("700")];
+
+        let spends = crate::actions::balance_spends(&wrap_near(), &actions).unwrap();
+        assert_eq!(
+            spends,
+            vec![
+                BalanceSpend {
+                    source: BalanceSource::Near,
+                    amount: U128(2),
+                },
+                BalanceSpend {
+                    source: BalanceSource::Ft { token: wrap_near() },
+                    amount: U128(1300),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_daily_balance_limit_adds_up_spends_of_the_same_balance() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.set_balance_limits(None, Some(1_000));
+        let request_id = contract.record_signature_request(
+            wrap_near(),
+            U64(1),
+            U128(0),
+            RequestReservation::default(),
+        );
+
+        testing_env!(
+            get_context(accounts(1)).build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![
+                PromiseResult::Successful(b"\"1000\"".to_vec()),
+                PromiseResult::Successful(b"\"1000\"".to_vec()),
+            ],
+        );
+        // Each spend is within the daily limit of 100, together they are not
+        let spend = BalanceSpend {
+            source: BalanceSource::Ft { token: wrap_near() },
+            amount: U128(60),
+        };
+        let result = contract.on_balance_snapshot(
+            request_id,
+            test_pending_signature(vec![spend.clone(), spend]),
+        );
+
+        assert!(matches!(result, near_sdk::PromiseOrValue::Value(None)));
+        let request = contract.get_signature_request(U64(request_id)).unwrap();
+        assert!(matches!(
+            request.status,
+            SignatureRequestStatus::Failed { reason } if reason.contains("exceeds the daily limit of 100")
+        ));
+        assert!(contract.get_daily_spend(wrap_near().to_string()).is_none());
+    }
+
+    #[test]
+    fn test_request_signature_respects_per_trade_limit() {
+        testing_env!(withdrawal_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        authorize_agent(&mut contract, accounts(2));
+        contract.set_balance_limits(Some(1_000), None);
+        let goal_id = create_test_goal(&mut contract, NearToken::from_near(10).as_yoctonear());
+
+        let mut context = withdrawal_context(accounts(2));
+        context
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .storage_usage(near_sdk::env::storage_usage());
+        testing_env!(context.build());
+        let result = contract.request_signature(
+            wrap_near(),
+            r#"[{"type": "FunctionCall", "method_name": "near_deposit", "args": {}, "gas": "50000000000000", "deposit": "2000000000000000000000000"}]"#.to_string(),
+            U64(1),
+            Base58CryptoHash::from([0u8; 32]),
+            "ed25519:11111111111111111111111111111111".to_string(),
+            "trading-account.near".to_string(),
+            None,
+            goal_id,
+        );
+        assert_rejected(
+            result,
+            "Balance limit check failed: Amount 2000000000000000000000000 of near exceeds the per trade limit",
+        );
+        // The goal budget spent before the balance limit check is returned
+        assert_eq!(contract.get_goal(goal_id).unwrap().executions, 0);
+    }
+
+    #[test]
+    fn test_daily_balance_limit_resets_each_day() {
+        let mut context = withdrawal_context(accounts(1));
+        context.attached_deposit(NearToken::from_yoctonear(0));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.set_balance_limits(None, Some(2_500));
+
+        let one_near = NearToken::from_near(1).as_yoctonear();
+        assert!(contract.check_near_balance_limits(one_near).is_ok());
+        assert!(contract.check_near_balance_limits(one_near).is_ok());
+        let err = contract.check_near_balance_limits(one_near).unwrap_err();
+        assert!(err.contains("exceeds the daily limit"), "{}", err);
+        assert_eq!(
+            contract.get_daily_spend("near".to_string()).unwrap().spent,
+            U128(2 * one_near)
+        );
+
+        context
+            .block_timestamp(24 * HOUR_NS)
+            .storage_usage(near_sdk::env::storage_usage());
+        testing_env!(context.build());
+        assert!(contract.get_daily_spend("near".to_string()).is_none());
+        assert!(contract.check_near_balance_limits(one_near).is_ok());
+    }
+
+    #[test]
+    fn test_on_balance_snapshot_rejects_spend_above_limit() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.set_balance_limits(Some(1_000), None);
+        let request_id = contract.record_signature_request(
+            wrap_near(),
+            U64(1),
+            U128(0),
+            RequestReservation::default(),
+        );
+
+        testing_env!(
+            get_context(accounts(1)).build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(b"\"1000\"".to_vec())],
+        );
+        let result = contract.on_balance_snapshot(
+            request_id,
+            test_pending_signature(vec![BalanceSpend {
+                source: BalanceSource::Ft { token: wrap_near() },
+                amount: U128(200),
+            }]),
+        );
+
+        assert!(matches!(result, near_sdk::PromiseOrValue::Value(None)));
+        let request = contract.get_signature_request(U64(request_id)).unwrap();
+        assert!(matches!(
+            request.status,
+            SignatureRequestStatus::Failed { reason } if reason.contains("exceeds the per trade limit of 100")
+        ));
+    }
+
+    #[test]
+    fn test_raising_balance_limits_is_scheduled() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.set_balance_limits(Some(1_000), Some(2_500));
+        contract.set_policy_change_delay(U64(HOUR_NS));
+
+        // Tightening applies immediately
+        contract.set_balance_limits(Some(500), Some(2_500));
+        assert_eq!(contract.get_balance_limits().max_trade_bps, Some(500));
+
+        contract.set_balance_limits(Some(500), None);
+        assert_eq!(contract.get_balance_limits().max_daily_bps, Some(2_500));
+        assert_eq!(contract.get_pending_policy_changes().len(), 1);
+    }
+
+    fn snapshot_test_contract(balances: Vec<PromiseResult>) -> (TradingAccountContract, u64) {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.set_balance_preflight(true);
+        let request_id = contract.record_signature_request(
+            wrap_near(),
+            U64(1),
+            U128(0),
+            RequestReservation::default(),
+        );
+
+        testing_env!(
+            get_context(accounts(1)).build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            balances,
+        );
+        (contract, request_id)
+    }
+
+    fn intents_usdt_spend(amount: u128) -> BalanceSpend {
+        BalanceSpend {
+            source: BalanceSource::Intents {
+                token_id: "nep141:usdt.tether-token.near".to_string(),
+            },
+            amount: U128(amount),
+        }
+    }
+
+    #[test]
+    fn test_on_balance_snapshot_rejects_insufficient_balance() {
+        let (mut contract, request_id) =
+            snapshot_test_contract(vec![PromiseResult::Successful(b"\"499\"".to_vec())]);
+
+        let result = contract.on_balance_snapshot(
+            request_id,
+            test_pending_signature(vec![intents_usdt_spend(500)]),
+        );
+
+        assert!(matches!(result, near_sdk::PromiseOrValue::Value(None)));
+        let request = contract.get_signature_request(U64(request_id)).unwrap();
+        assert!(matches!(
+            request.status,
+            SignatureRequestStatus::Failed { reason }
+                if reason == "Insufficient balance of intents.near:nep141:usdt.tether-token.near: the request spends 500 but the trading account holds 499"
+        ));
+    }
+
+    #[test]
+    fn test_on_balance_snapshot_rejects_failed_query() {
+        let (mut contract, request_id) = snapshot_test_contract(vec![PromiseResult::Failed]);
+
+        let _ = contract.on_balance_snapshot(
+            request_id,
+            test_pending_signature(vec![intents_usdt_spend(500)]),
+        );
+
+        let request = contract.get_signature_request(U64(request_id)).unwrap();
+        assert!(matches!(
+            request.status,
+            SignatureRequestStatus::Failed { reason } if reason.starts_with("Failed to fetch the balance of")
+        ));
+    }
+
+    #[test]
+    fn test_on_balance_snapshot_requests_signature_when_funded() {
+        let (mut contract, request_id) =
+            snapshot_test_contract(vec![PromiseResult::Successful(b"\"500\"".to_vec())]);
+
+        let result = contract.on_balance_snapshot(
+            request_id,
+            test_pending_signature(vec![intents_usdt_spend(500)]),
+        );
+
+        assert!(matches!(result, near_sdk::PromiseOrValue::Promise(_)));
+        assert_eq!(
+            contract
+                .get_signature_request(U64(request_id))
+                .unwrap()
+                .status,
+            SignatureRequestStatus::Pending
+        );
+        // Without balance limits nothing is counted towards daily spending
+        assert!(
+            contract
+                .get_daily_spend("intents.near:nep141:usdt.tether-token.near".to_string())
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_failed_request_releases_daily_spend() {
+        let (mut contract, request_id) =
+            snapshot_test_contract(vec![PromiseResult::Successful(b"\"1000\"".to_vec())]);
+        contract.balance_limits.max_daily_bps = Some(1_000);
+        let key = "intents.near:nep141:usdt.tether-token.near".to_string();
+
+        let result = contract.on_balance_snapshot(
+            request_id,
+            test_pending_signature(vec![intents_usdt_spend(60)]),
+        );
+        assert!(matches!(result, near_sdk::PromiseOrValue::Promise(_)));
+        assert_eq!(
+            contract.get_daily_spend(key.clone()).unwrap().spent,
+            U128(60)
+        );
+
+        // The MPC signer fails, so the request never spent what it counted
+        testing_env!(context_as(accounts(1)).build());
+        contract.sign_request_callback(Err(PromiseError::Failed), request_id, String::new());
+        assert_eq!(contract.get_daily_spend(key).unwrap().spent, U128(0));
+    }
+
+    #[test]
+    fn test_failed_request_releases_daily_near_spend() {
+        let mut context = withdrawal_context(accounts(1));
+        context.attached_deposit(NearToken::from_yoctonear(0));
+        testing_env!(context.build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.set_balance_limits(None, Some(2_500));
+        let one_near = NearToken::from_near(1).as_yoctonear();
+
+        let counted = contract.check_near_balance_limits(one_near).unwrap();
+        let request_id = contract.record_signature_request(
+            wrap_near(),
+            U64(1),
+            U128(0),
+            RequestReservation::default(),
+        );
+        contract.add_counted_spends(request_id, counted);
+        contract.check_near_balance_limits(one_near).unwrap();
+
+        contract.update_signature_request_status(
+            request_id,
+            SignatureRequestStatus::Failed {
+                reason: "MPC signer unavailable".to_string(),
+            },
+        );
+        assert_eq!(
+            contract.get_daily_spend("near".to_string()).unwrap().spent,
+            U128(one_near)
+        );
+
+        // Spends counted on an earlier day are not taken back from today's total
+        let counted = contract.check_near_balance_limits(one_near).unwrap();
+        let request_id = contract.record_signature_request(
+            wrap_near(),
+            U64(2),
+            U128(0),
+            RequestReservation::default(),
+        );
+        contract.add_counted_spends(request_id, counted);
+        context
+            .block_timestamp(24 * HOUR_NS)
+            .storage_usage(near_sdk::env::storage_usage());
+        testing_env!(context.build());
+        contract.check_near_balance_limits(one_near).unwrap();
+        contract.update_signature_request_status(
+            request_id,
+            SignatureRequestStatus::Failed {
+                reason: "MPC signer unavailable".to_string(),
+            },
+        );
+        assert_eq!(
+            contract.get_daily_spend("near".to_string()).unwrap().spent,
+            U128(one_near)
+        );
+    }
+
+    fn token_diff_message(spent: &str, received: &str) -> String {
+        serde_json::json!({
+            "signer_id": "alice.near",
+            "deadline": "2026-01-01T00:00:00Z",
+            "intents": [{
+                "intent": "token_diff",
+                "diff": {
+                    "nep141:wrap.near": format!("-{}", spent),
+                    "nep141:usdt.tether-token.near": received,
+                }
+            }]
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_request_intent_signature_rejects_reverse_swap() {
+        let (mut contract, goal_id) = intent_test_contract();
+        testing_env!(context_as(accounts(1)).build());
+        contract.add_allowed_token(usdt());
+        contract.add_allowed_pair(usdt(), wrap_near());
+
+        // Swapping the goal's destination token back into its source token spends no budget
+        let message = serde_json::json!({
+            "signer_id": "alice.near",
+            "deadline": "2026-01-01T00:00:00Z",
+            "intents": [{
+                "intent": "token_diff",
+                "diff": {
+                    "nep141:usdt.tether-token.near": "-5000",
+                    "nep141:wrap.near": "100",
+                }
+            }]
+        })
+        .to_string();
+        let result = request_intent(&mut contract, message, goal_id);
+        assert_rejected(
+            result,
+            "Goal validation failed: Goal 0 only permits spending wrap.near, not usdt.tether-token.near",
+        );
+    }
+
+    fn intent_test_contract() -> (TradingAccountContract, U64) {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        authorize_agent(&mut contract, accounts(2));
+        contract.add_allowed_token(wrap_near());
+        contract.add_allowed_pair(wrap_near(), usdt());
+        contract.set_price_oracle(Some(
+            AccountId::try_from("priceoracle.near".to_string()).unwrap(),
+        ));
+        let goal_id = create_test_goal(&mut contract, 1_000);
+        contract.set_goal_max_slippage(goal_id, Some(100));
+        (contract, goal_id)
+    }
+
+    fn request_intent(
+        contract: &mut TradingAccountContract,
+        message: String,
+        goal_id: U64,
+    ) -> PromiseOrValue<Option<String>> {
+        let mut context = get_context(accounts(2));
+        context.prepaid_gas(near_sdk::Gas::from_tgas(300));
+        testing_env!(context.build());
+        contract.request_intent_signature(
+            message,
+            near_sdk::json_types::Base64VecU8::from(vec![7u8; 32]),
+            "trading-account.near".to_string(),
+            None,
+            goal_id,
+        )
+    }
+
+    #[test]
+    #[should_panic(expected = "GasExceeded")]
+    fn test_request_intent_signature_passes_validation() {
+        let (mut contract, goal_id) = intent_test_contract();
+        // As with transactions, validation succeeds and the mocked MPC call runs out of gas
+        let _ = request_intent(&mut contract, token_diff_message("100", "250"), goal_id);
+    }
+
+    #[test]
+    fn test_request_intent_signature_requires_goal_slippage() {
+        let (mut contract, goal_id) = intent_test_contract();
+        contract.set_goal_max_slippage(goal_id, None);
+        assert_eq!(contract.get_goal(goal_id).unwrap().max_slippage_bps, None);
+
+        // Without a slippage bound there is nothing but the agent to vouch for the received amount
+        let result = request_intent(&mut contract, token_diff_message("100", "1"), goal_id);
+        assert_rejected(
+            result,
+            "Goal validation failed: Goal 0 has no maximum slippage to price intents against",
+        );
+        // The rejection counts towards the circuit breaker and leaves the goal budget untouched
+        assert_eq!(
+            contract
+                .get_agent_rejections(accounts(2))
+                .unwrap()
+                .rejections,
+            1
+        );
+        assert_eq!(contract.get_goal(goal_id).unwrap().executions, 0);
+    }
+
+    #[test]
+    fn test_request_intent_signature_foreign_signer() {
+        let (mut contract, goal_id) = intent_test_contract();
+        let message = token_diff_message("100", "250").replace("alice.near", "bob.near");
+        let result = request_intent(&mut contract, message, goal_id);
+        assert_rejected(
+            result,
+            "Invalid token_diff intent: signer_id bob.near is not the trading account",
+        );
+    }
+
+    #[test]
+    fn test_request_intent_signature_rejects_other_intents() {
+        let (mut contract, goal_id) = intent_test_contract();
+        let message = serde_json::json!({
+            "signer_id": "alice.near",
+            "intents": [{"intent": "transfer", "diff": {}, "receiver_id": "bob.near"}]
+        })
+        .to_string();
+        let result = request_intent(&mut contract, message, goal_id);
+        assert_rejected(
+            result,
+            "Invalid token_diff intent: transfer intents are not allowed",
+        );
+    }
+
+    #[test]
+    fn test_request_intent_signature_slippage_requires_oracle() {
+        let (mut contract, goal_id) = intent_test_contract();
+        contract.set_price_oracle(None);
+        let result = request_intent(&mut contract, token_diff_message("100", "250"), goal_id);
+        assert_rejected(
+            result,
+            "has a maximum slippage but no price oracle is configured",
+        );
+        assert_eq!(contract.get_goal(goal_id).unwrap().executions, 0);
+    }
+
+    #[test]
+    fn test_request_intent_signature_bad_nonce() {
+        let (mut contract, goal_id) = intent_test_contract();
+        let mut context = get_context(accounts(2));
+        context.prepaid_gas(near_sdk::Gas::from_tgas(300));
+        testing_env!(context.build());
+        let result = contract.request_intent_signature(
+            token_diff_message("100", "250"),
+            near_sdk::json_types::Base64VecU8::from(vec![7u8; 16]),
+            "trading-account.near".to_string(),
+            None,
+            goal_id,
+        );
+        assert_rejected(result, "The NEP-413 nonce must be 32 bytes");
+    }
+
+    #[test]
+    fn test_swap_quote_check_slippage() {
+        testing_env!(get_context(accounts(1)).build());
+        // wNEAR at 2.5 USD with 24 decimals, USDT at 1 USD with 6 decimals
+        let price_data = crate::PriceData {
+            timestamp: U64(0),
+            recency_duration_sec: 90,
+            prices: vec![
+                crate::AssetOptionalPrice {
+                    asset_id: wrap_near(),
+                    price: Some(usd_price(25_000, 28)),
+                },
+                crate::AssetOptionalPrice {
+                    asset_id: usdt(),
+                    price: Some(usd_price(10_000, 10)),
+                },
+            ],
+        };
+        let quote = |received: u128| SwapQuote {
+            spent_token: wrap_near(),
+            spent_amount: U128(NearToken::from_near(10).as_yoctonear()),
+            received_token: usdt(),
+            received_amount: U128(received),
+            max_slippage_bps: 100,
+        };
+
+        assert!(quote(25_000_000).check_slippage(&price_data).is_ok());
+        assert!(quote(24_750_000).check_slippage(&price_data).is_ok());
+        let err = quote(24_749_999).check_slippage(&price_data).unwrap_err();
+        assert!(
+            err.contains("exceeds the maximum slippage of 100 bps"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_raising_goal_max_slippage_is_scheduled() {
+        let (mut contract, goal_id) = intent_test_contract();
+        contract.set_goal_max_slippage(goal_id, Some(100));
+        contract.set_policy_change_delay(U64(HOUR_NS));
+
+        contract.set_goal_max_slippage(goal_id, Some(50));
+        assert_eq!(
+            contract.get_goal(goal_id).unwrap().max_slippage_bps,
+            Some(50)
+        );
+
+        contract.set_goal_max_slippage(goal_id, None);
+        assert_eq!(
+            contract.get_goal(goal_id).unwrap().max_slippage_bps,
+            Some(50)
+        );
+        assert_eq!(contract.get_pending_policy_changes().len(), 1);
+    }
+
+    fn swap_transfer_call(receiver_id: &str, msg: String) -> Vec<ActionString> {
+        vec![ActionString::FunctionCall {
+            method_name: "ft_transfer_call".to_string(),
+            args: serde_json::json!({"receiver_id": receiver_id, "amount": "1", "msg": msg}),
+            gas: "100000000000000".to_string(),
+            deposit: "1".to_string(),
+        }]
+    }
+
+    fn memecoin() -> AccountId {
+        AccountId::try_from("meme.near".to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_manage_allowed_pairs() {
+        let (mut contract, _) = intent_test_contract();
+        contract.add_allowed_pair(usdt(), wrap_near());
+        assert_eq!(contract.get_allowed_pairs().len(), 2);
+
+        contract.remove_allowed_pair(wrap_near(), usdt());
+        assert_eq!(
+            contract.get_allowed_pairs(),
+            vec![TokenPair {
+                from: usdt(),
+                to: wrap_near()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_add_allowed_pair_is_scheduled() {
+        let (mut contract, _) = intent_test_contract();
+        contract.set_policy_change_delay(U64(HOUR_NS));
+
+        contract.add_allowed_pair(usdt(), memecoin());
+        assert_eq!(contract.get_allowed_pairs().len(), 1);
+        assert_eq!(contract.get_pending_policy_changes().len(), 1);
+    }
+
+    #[test]
+    fn test_request_intent_signature_disallowed_pair() {
+        let (mut contract, goal_id) = intent_test_contract();
+        let message = serde_json::json!({
+            "signer_id": "alice.near",
+            "deadline": "2030-01-01T00:00:00.000Z",
+            "intents": [{
+                "intent": "token_diff",
+                "diff": {"nep141:wrap.near": "-100", "nep141:meme.near": "5000"}
+            }]
+        })
+        .to_string();
+        let result = request_intent(&mut contract, message, goal_id);
+        assert_rejected(result, "Swap from wrap.near to meme.near is not allowed");
+    }
+
+    #[test]
+    fn test_validate_and_build_actions_checks_intents_swap_pairs() {
+        let (contract, _) = intent_test_contract();
+        let execute_intents = |message: String| {
+            serde_json::json!({
+                "receiver_id": "alice.near",
+                "execute_intents": [{
+                    "standard": "nep413",
+                    "payload": {"message": message, "nonce": "", "recipient": "intents.near"},
+                    "public_key": "ed25519:11111111111111111111111111111111",
+                    "signature": "ed25519:11111111111111111111111111111111"
+                }]
+            })
+            .to_string()
+        };
+
+        let allowed = execute_intents(token_diff_message("100", "250"));
+        assert!(
+            contract
+                .validate_and_build_actions(
+                    swap_transfer_call("intents.near", allowed),
+                    &wrap_near()
+                )
+                .is_ok()
+        );
+
+        let meme = execute_intents(
+            serde_json::json!({
+                "signer_id": "alice.near",
+                "intents": [{
+                    "intent": "token_diff",
+                    "diff": {"nep141:wrap.near": "-100", "nep141:meme.near": "5000"}
+                }]
+            })
+            .to_string(),
+        );
+        let err = contract
+            .validate_and_build_actions(swap_transfer_call("intents.near", meme), &wrap_near())
+            .unwrap_err();
+        assert!(
+            err.contains("Swap from wrap.near to meme.near is not allowed"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_validate_and_build_actions_checks_swap_action_pairs() {
+        let (mut contract, _) = intent_test_contract();
+        contract.set_address_book_delay(U64(0));
+        contract.add_address_book_entry("v2.ref-finance.near".to_string(), None);
+        let msg = serde_json::json!({
+            "actions": [{"pool_id": 1, "token_in": "wrap.near", "token_out": "meme.near"}]
+        })
+        .to_string();
+        let err = contract
+            .validate_and_build_actions(
+                swap_transfer_call("v2.ref-finance.near", msg),
+                &wrap_near(),
+            )
+            .unwrap_err();
+        assert!(err.contains("Swap from wrap.near to meme.near is not allowed"));
+
+        // Messages which do not encode a swap are left alone
+        assert!(
+            contract
+                .validate_and_build_actions(
+                    swap_transfer_call("intents.near", "alice.near".to_string()),
+                    &wrap_near()
+                )
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_ft_transfer_call_receiver_must_be_in_address_book() {
+        let (mut contract, _) = intent_test_contract();
+        let transfer = || swap_transfer_call("danny.near", String::new());
+        let err = contract
+            .validate_and_build_actions(transfer(), &wrap_near())
+            .unwrap_err();
+        assert!(
+            err.contains("Token receiver danny.near is not in the address book"),
+            "{}",
+            err
+        );
+
+        contract.set_address_book_delay(U64(0));
+        contract.add_address_book_entry("danny.near".to_string(), None);
+        assert!(
+            contract
+                .validate_and_build_actions(transfer(), &wrap_near())
+                .is_ok()
+        );
+    }
+
+    fn intents_deposit(
+        contract: &TradingAccountContract,
+        msg: &str,
+    ) -> Result<Vec<omni_transaction::near::types::Action>, String> {
+        contract.validate_and_build_actions(
+            swap_transfer_call("intents.near", msg.to_string()),
+            &wrap_near(),
+        )
+    }
+
+    #[test]
+    fn test_intents_deposit_credited_to_trading_account() {
+        let (contract, _) = intent_test_contract();
+        // The trading account (alice.near in tests) is credited for an empty, plain or JSON msg
+        assert!(intents_deposit(&contract, "").is_ok());
+        assert!(intents_deposit(&contract, "alice.near").is_ok());
+        assert!(intents_deposit(&contract, r#"{"receiver_id": "alice.near"}"#).is_ok());
+    }
+
+    #[test]
+    fn test_intents_deposit_to_other_receiver_rejected() {
+        let (contract, _) = intent_test_contract();
+        let err = intents_deposit(&contract, "bob.near").unwrap_err();
+        assert!(
+            err.contains("Deposit receiver bob.near is not in the address book"),
+            "{}",
+            err
+        );
+        let err = intents_deposit(
+            &contract,
+            r#"{"receiver_id": "bob.near", "refund_if_fails": true}"#,
+        )
+        .unwrap_err();
+        assert!(err.contains("Deposit receiver bob.near is not in the address book"));
+
+        let err = intents_deposit(&contract, "not a receiver!").unwrap_err();
+        assert!(
+            err.contains("Invalid intents.near deposit message"),
+            "{}",
+            err
+        );
+        let err = intents_deposit(&contract, r#"{"execute_intents": []}"#).unwrap_err();
+        assert!(
+            err.contains("Invalid intents.near deposit message"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_intents_deposit_to_address_book_entry() {
+        let (mut contract, _) = intent_test_contract();
+        contract.add_address_book_entry("bob.near".to_string(), None);
+        let err = intents_deposit(&contract, "bob.near").unwrap_err();
+        assert!(err.contains("Deposit receiver bob.near cannot be used until"));
+
+        testing_env!(
+            get_context(accounts(1))
+                .block_timestamp(24 * HOUR_NS)
+                .storage_usage(near_sdk::env::storage_usage())
+                .build()
+        );
+        assert!(intents_deposit(&contract, "bob.near").is_ok());
+    }
+
+    fn mt_call(method_name: &str, args: serde_json::Value) -> ActionString {
+        ActionString::FunctionCall {
+            method_name: method_name.to_string(),
+            args,
+            gas: "30000000000000".to_string(),
+            deposit: "1".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_mt_transfers_count_per_token_id() {
+        let intents = AccountId::try_from("intents.near".to_string()).unwrap();
+        let actions = vec![
+            mt_call(
+                "mt_transfer",
+                serde_json::json!({"receiver_id": "alice.near", "token_id": "nep141:wrap.near", "amount": "10"}),
+            ),
+            mt_call(
+                "mt_batch_transfer_call",
+                serde_json::json!({
+                    "receiver_id": "alice.near",
+                    "token_ids": ["nep141:wrap.near", "nep141:usdt.tether-token.near"],
+                    "amounts": ["20", "30"],
+                    "msg": ""
+                }),
+            ),
+        ];
+
+        let movements = crate::actions::token_movements(&intents, &actions).unwrap();
+        assert_eq!(
+            movements
+                .iter()
+                .map(|movement| (movement.token.clone(), movement.amount))
+                .collect::<Vec<_>>(),
+            vec![(wrap_near(), 10), (wrap_near(), 20), (usdt(), 30)]
+        );
+
+        // Both wrap.near transfers are spent from the same balance
+        let spends = crate::actions::balance_spends(&intents, &actions).unwrap();
+        assert_eq!(spends.len(), 3);
+        assert_eq!(spends[0].source, BalanceSource::Near);
+        assert_eq!(
+            spends[1],
+            BalanceSpend {
+                source: BalanceSource::Intents {
+                    token_id: "nep141:wrap.near".to_string(),
+                },
+                amount: U128(30),
+            }
+        );
+        assert_eq!(
+            spends[2],
+            BalanceSpend {
+                source: BalanceSource::Intents {
+                    token_id: "nep141:usdt.tether-token.near".to_string(),
+                },
+                amount: U128(30),
+            }
+        );
+    }
+
+    #[test]
+    fn test_validate_mt_transfers() {
+        let (contract, _) = intent_test_contract();
+        let intents = AccountId::try_from("intents.near".to_string()).unwrap();
+        let validate = |method_name: &str, args: serde_json::Value| {
+            contract.validate_and_build_actions(vec![mt_call(method_name, args)], &intents)
+        };
+
+        assert!(
+            validate(
+                "mt_transfer_call",
+                serde_json::json!({"receiver_id": "alice.near", "token_id": "nep141:wrap.near", "amount": "10", "msg": ""}),
+            )
+            .is_ok()
+        );
+
+        // Every token id of a batch must be allowed
+        let err = validate(
+            "mt_batch_transfer",
+            serde_json::json!({
+                "receiver_id": "alice.near",
+                "token_ids": ["nep141:wrap.near", "nep141:usdt.tether-token.near"],
+                "amounts": ["10", "10"]
+            }),
+        )
+        .unwrap_err();
+        assert!(
+            err.contains("Token usdt.tether-token.near is not allowed"),
+            "{}",
+            err
+        );
+
+        let err = validate(
+            "mt_transfer",
+            serde_json::json!({"receiver_id": "alice.near", "token_id": "nep245:other.near:1", "amount": "10"}),
+        )
+        .unwrap_err();
+        assert!(
+            err.contains("Only nep141 token ids can be transferred"),
+            "{}",
+            err
+        );
+
+        let err = validate(
+            "mt_transfer",
+            serde_json::json!({"receiver_id": "bob.near", "token_id": "nep141:wrap.near", "amount": "10"}),
+        )
+        .unwrap_err();
+        assert!(
+            err.contains("Transfer receiver bob.near is not in the address book"),
+            "{}",
+            err
+        );
+
+        let err = validate(
+            "mt_batch_transfer",
+            serde_json::json!({"receiver_id": "alice.near", "token_ids": ["nep141:wrap.near"], "amounts": []}),
+        )
+        .unwrap_err();
+        assert!(err.contains("1 token ids but 0 amounts"), "{}", err);
+    }
+
+    fn call_with_deposit(
+        method_name: &str,
+        args: serde_json::Value,
+        deposit: u128,
+    ) -> ActionString {
+        ActionString::FunctionCall {
+            method_name: method_name.to_string(),
+            args,
+            gas: "30000000000000".to_string(),
+            deposit: deposit.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_near_withdraw_counts_as_wrapped_token_spend() {
+        let (contract, _) = intent_test_contract();
+        let unwrap = || {
+            vec![call_with_deposit(
+                "near_withdraw",
+                serde_json::json!({"amount": "500"}),
+                1,
+            )]
+        };
+        assert!(
+            contract
+                .validate_and_build_actions(unwrap(), &wrap_near())
+                .is_ok()
+        );
+
+        let movements = crate::actions::token_movements(&wrap_near(), &unwrap()).unwrap();
+        assert_eq!(movements[0].token, wrap_near());
+        assert_eq!(movements[0].amount, 500);
+        let spends = crate::actions::balance_spends(&wrap_near(), &unwrap()).unwrap();
+        assert_eq!(
+            spends[1],
+            BalanceSpend {
+                source: BalanceSource::Ft { token: wrap_near() },
+                amount: U128(500),
+            }
+        );
+
+        let err = contract
+            .validate_and_build_actions(
+                vec![call_with_deposit(
+                    "near_withdraw",
+                    serde_json::json!({"amount": "0"}),
+                    1,
+                )],
+                &wrap_near(),
+            )
+            .unwrap_err();
+        assert!(err.contains("must be greater than zero"), "{}", err);
+    }
+
+    #[test]
+    fn test_storage_deposit_validation() {
+        let (mut contract, _) = intent_test_contract();
+        let deposit = NearToken::from_millinear(10).as_yoctonear();
+        let storage_deposit = |account_id: serde_json::Value, deposit: u128| {
+            vec![call_with_deposit(
+                "storage_deposit",
+                serde_json::json!({"account_id": account_id, "registration_only": true}),
+                deposit,
+            )]
+        };
+
+        assert!(
+            contract
+                .validate_and_build_actions(
+                    storage_deposit(serde_json::json!("alice.near"), deposit),
+                    &wrap_near()
+                )
+                .is_ok()
+        );
+        assert!(
+            contract
+                .validate_and_build_actions(
+                    storage_deposit(serde_json::Value::Null, deposit),
+                    &wrap_near()
+                )
+                .is_ok()
+        );
+
+        let err = contract
+            .validate_and_build_actions(
+                storage_deposit(serde_json::json!("bob.near"), deposit),
+                &wrap_near(),
+            )
+            .unwrap_err();
+        assert!(
+            err.contains("may only register the trading account, not bob.near"),
+            "{}",
+            err
+        );
+
+        let err = contract
+            .validate_and_build_actions(
+                storage_deposit(serde_json::json!("alice.near"), deposit + 1),
+                &wrap_near(),
+            )
+            .unwrap_err();
+        assert!(err.contains("exceeds the maximum"), "{}", err);
+
+        // Storage can be registered on allowed tokens beyond the allowlisted contracts
+        let err = contract
+            .validate_and_build_actions(storage_deposit(serde_json::Value::Null, deposit), &usdt())
+            .unwrap_err();
+        assert!(err.contains("is not allowed"), "{}", err);
+        contract.add_allowed_token(usdt());
+        assert!(
+            contract
+                .validate_and_build_actions(
+                    storage_deposit(serde_json::Value::Null, deposit),
+                    &usdt()
+                )
+                .is_ok()
+        );
+        let err = contract
+            .validate_and_build_actions(
+                vec![call_with_deposit(
+                    "ft_transfer_call",
+                    serde_json::json!({"receiver_id": "intents.near", "amount": "1"}),
+                    1,
+                )],
+                &usdt(),
+            )
+            .unwrap_err();
+        assert!(err.contains("is not allowed"), "{}", err);
+    }
+
+    fn amount_rule(operator: RuleOperator, value: &str, outcome: RuleOutcome) -> PolicyRule {
+        PolicyRule {
+            contract_id: Some(wrap_near()),
+            method_name: Some("ft_transfer_call".to_string()),
+            conditions: vec![RuleCondition {
+                pointer: "/amount".to_string(),
+                operator,
+                value: value.to_string(),
+            }],
+            outcome,
+        }
+    }
+
+    fn transfer_call_amount(amount: &str) -> Vec<ActionString> {
+        vec![call_with_deposit(
+            "ft_transfer_call",
+            serde_json::json!({"receiver_id": "intents.near", "amount": amount}),
+            1,
+        )]
+    }
+
+    #[test]
+    fn test_policy_rules_evaluated_in_order() {
+        let (mut contract, _) = intent_test_contract();
+        contract.add_policy_rule(
+            amount_rule(RuleOperator::Gt, "1000", RuleOutcome::Deny),
+            None,
+        );
+        contract.add_policy_rule(
+            amount_rule(RuleOperator::Gt, "100", RuleOutcome::RequireApproval),
+            None,
+        );
+        let rules = contract.get_policy_rules();
+        assert_eq!(rules.len(), 2);
+
+        let check = |contract: &TradingAccountContract, amount: &str| {
+            contract.check_policy_rules(&wrap_near(), &transfer_call_amount(amount))
+        };
+        assert_eq!(check(&contract, "100"), Ok(None));
+        assert_eq!(check(&contract, "500"), Ok(Some(rules[1].rule_id)));
+        let err = check(&contract, "5000").unwrap_err();
+        assert!(
+            err.contains("Policy rule 0 denies ft_transfer_call on wrap.near"),
+            "{}",
+            err
+        );
+
+        // An allow rule ahead of the others stops their evaluation
+        contract.add_policy_rule(
+            amount_rule(RuleOperator::Lte, "10000", RuleOutcome::Allow),
+            Some(rules[0].rule_id),
+        );
+        assert_eq!(check(&contract, "5000"), Ok(None));
+
+        // Rules only match their contract
+        assert_eq!(
+            contract.check_policy_rules(&usdt(), &transfer_call_amount("5000")),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_policy_rule_amount_comparison_requires_amount() {
+        let (mut contract, _) = intent_test_contract();
+        contract.add_policy_rule(
+            amount_rule(RuleOperator::Gt, "1000", RuleOutcome::Deny),
+            None,
+        );
+        let err = contract
+            .check_policy_rules(&wrap_near(), &transfer_call_amount("lots"))
+            .unwrap_err();
+        assert!(err.contains("could not be evaluated"), "{}", err);
+    }
+
+    #[test]
+    fn test_policy_rules_apply_to_intent_requests() {
+        let (mut contract, goal_id) = intent_test_contract();
+        contract.add_policy_rule(
+            PolicyRule {
+                contract_id: Some(AccountId::try_from("intents.near".to_string()).unwrap()),
+                method_name: Some("execute_intents".to_string()),
+                conditions: vec![RuleCondition {
+                    pointer: "/received_amount".to_string(),
+                    operator: RuleOperator::Gte,
+                    value: "200".to_string(),
+                }],
+                outcome: RuleOutcome::Deny,
+            },
+            None,
+        );
+
+        let result = request_intent(&mut contract, token_diff_message("100", "250"), goal_id);
+        assert_rejected(
+            result,
+            "Policy rule check failed: Policy rule 0 denies execute_intents on intents.near",
+        );
+        assert_eq!(contract.get_goal(goal_id).unwrap().executions, 0);
+    }
+
+    #[test]
+    fn test_intent_policy_rules_compare_unsigned_amounts() {
+        let (mut contract, _) = intent_test_contract();
+        contract.add_policy_rule(
+            PolicyRule {
+                contract_id: Some(AccountId::try_from("intents.near".to_string()).unwrap()),
+                method_name: Some("execute_intents".to_string()),
+                conditions: vec![
+                    RuleCondition {
+                        pointer: "/spent_token".to_string(),
+                        operator: RuleOperator::Eq,
+                        value: "wrap.near".to_string(),
+                    },
+                    RuleCondition {
+                        pointer: "/spent_amount".to_string(),
+                        operator: RuleOperator::Gt,
+                        value: "1000".to_string(),
+                    },
+                ],
+                outcome: RuleOutcome::Deny,
+            },
+            None,
+        );
+
+        // The diff spends wrap.near with a negative amount, the rule sees it unsigned
+        let check = |spent: &str| {
+            let message = token_diff_message(spent, "250");
+            let swap = parse_token_diff(&message).unwrap();
+            contract.check_intent_policy_rules(&message, &swap)
+        };
+        assert_eq!(check("100"), Ok(()));
+        assert_eq!(
+            check("5000"),
+            Err("Policy rule 0 denies execute_intents on intents.near".to_string())
+        );
+    }
+
+    #[test]
+    fn test_approval_rules_reject_intent_requests() {
+        let (mut contract, goal_id) = intent_test_contract();
+        contract.add_policy_rule(
+            PolicyRule {
+                contract_id: Some(AccountId::try_from("intents.near".to_string()).unwrap()),
+                method_name: None,
+                conditions: vec![],
+                outcome: RuleOutcome::RequireApproval,
+            },
+            None,
+        );
+
+        let result = request_intent(&mut contract, token_diff_message("100", "250"), goal_id);
+        assert_rejected(
+            result,
+            "Policy rule 0 requires owner approval, which intent requests cannot be held for",
+        );
+    }
+
+    #[test]
+    fn test_loosening_policy_rules_is_scheduled() {
+        let (mut contract, _) = intent_test_contract();
+        contract.set_policy_change_delay(U64(HOUR_NS));
+
+        contract.add_policy_rule(
+            amount_rule(RuleOperator::Gt, "1000", RuleOutcome::Deny),
+            None,
+        );
+        contract.add_policy_rule(
+            amount_rule(RuleOperator::Lte, "10", RuleOutcome::Allow),
+            None,
+        );
+        assert_eq!(contract.get_policy_rules().len(), 1);
+        assert_eq!(contract.get_pending_policy_changes().len(), 1);
+
+        let rule_id = contract.get_policy_rules()[0].rule_id;
+        contract.remove_policy_rule(rule_id);
+        assert_eq!(contract.get_policy_rules().len(), 1);
+        assert_eq!(contract.get_pending_policy_changes().len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid amount many in policy rule")]
+    fn test_add_policy_rule_rejects_invalid_amount() {
+        let (mut contract, _) = intent_test_contract();
+        contract.add_policy_rule(
+            amount_rule(RuleOperator::Gt, "many", RuleOutcome::Deny),
+            None,
+        );
+    }
+
+    fn request_wrap(
+        contract: &mut TradingAccountContract,
+        goal_id: U64,
+    ) -> PromiseOrValue<Option<String>> {
+        let mut context = get_context(accounts(2));
+        context
+            .prepaid_gas(near_sdk::Gas::from_tgas(300))
+            .attached_deposit(NearToken::from_millinear(1));
+        testing_env!(context.build());
+        contract.request_signature(
+            wrap_near(),
+            r#"[{"type": "FunctionCall", "method_name": "near_deposit", "args": {}, "gas": "30000000000000", "deposit": "10"}]"#.to_string(),
+            U64(1),
+            Base58CryptoHash::from([0u8; 32]),
+            "ed25519:11111111111111111111111111111111".to_string(),
+            "trading-account.near".to_string(),
+            None,
+            goal_id,
+        )
+    }
+
+    fn approval_test_contract() -> (TradingAccountContract, u64) {
+        let (mut contract, goal_id) = intent_test_contract();
+        contract.add_policy_rule(
+            PolicyRule {
+                contract_id: None,
+                method_name: Some("near_deposit".to_string()),
+                conditions: vec![],
+                outcome: RuleOutcome::RequireApproval,
+            },
+            None,
+        );
+        let result = request_wrap(&mut contract, goal_id);
+        assert!(matches!(result, PromiseOrValue::Value(None)));
+        let request_id = contract.get_signature_requests(None, None)[0].request_id;
+        (contract, request_id.0)
+    }
+
+    #[test]
+    fn test_require_approval_rule_holds_request() {
+        let (contract, request_id) = approval_test_contract();
+        let request = contract.get_signature_request(U64(request_id)).unwrap();
+        assert_eq!(
+            request.status,
+            SignatureRequestStatus::AwaitingApproval { rule_id: U64(0) }
+        );
+        assert_eq!(
+            contract.get_pending_deposits(),
+            NearToken::from_yoctonear(10)
+        );
+        let approval = contract.get_approval_request(U64(request_id)).unwrap();
+        assert_eq!(approval.mpc_deposit, NearToken::from_millinear(1));
+    }
+
+    #[test]
+    fn test_deny_signature_request() {
+        let (mut contract, request_id) = approval_test_contract();
+        testing_env!(
+            get_context(accounts(1))
+                .storage_usage(near_sdk::env::storage_usage())
+                .build()
+        );
+        contract.deny_signature_request(U64(request_id));
+
+        let request = contract.get_signature_request(U64(request_id)).unwrap();
+        assert!(matches!(
+            request.status,
+            SignatureRequestStatus::Failed { .. }
+        ));
+        assert!(contract.get_approval_request(U64(request_id)).is_none());
+        assert_eq!(
+            contract.get_pending_deposits(),
+            NearToken::from_yoctonear(0)
+        );
+        assert_eq!(
+            contract.get_goal(U64(0)).unwrap().remaining_amount,
+            U128(1_000)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can perform this action")]
+    fn test_approve_signature_request_non_owner() {
+        let (mut contract, request_id) = approval_test_contract();
+        let _ = contract.approve_signature_request(U64(request_id));
+    }
+
+    fn manager_test_contract() -> TradingAccountContract {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.grant_role(accounts(3), Role::Manager);
+        contract.set_balance_limits(Some(500), None);
+        testing_env!(
+            get_context(accounts(3))
+                .storage_usage(near_sdk::env::storage_usage())
+                .build()
+        );
+        contract
+    }
+
+    #[test]
+    fn test_role_assignments() {
+        let mut contract = manager_test_contract();
+        authorize_agent(&mut contract, accounts(2));
+        testing_env!(
+            get_context(accounts(1))
+                .storage_usage(near_sdk::env::storage_usage())
+                .build()
+        );
+        contract.grant_role(accounts(4), Role::Viewer);
+
+        assert_eq!(
+            contract.get_role_assignments(),
+            vec![
+                RoleAssignment {
+                    account_id: accounts(1),
+                    role: Role::Owner
+                },
+                RoleAssignment {
+                    account_id: accounts(3),
+                    role: Role::Manager
+                },
+                RoleAssignment {
+                    account_id: accounts(4),
+                    role: Role::Viewer
+                },
+                RoleAssignment {
+                    account_id: accounts(2),
+                    role: Role::Agent
+                },
+            ]
+        );
+        assert_eq!(contract.get_roles(accounts(3)), vec![Role::Manager]);
+
+        contract.revoke_role(accounts(3));
+        assert!(contract.get_roles(accounts(3)).is_empty());
+    }
+
+    #[test]
+    fn test_manager_manages_agents_and_tightens_limits() {
+        let mut contract = manager_test_contract();
+        authorize_agent(&mut contract, accounts(2));
+        assert!(contract.is_authorized(accounts(2)));
+        contract.remove_authorized_user(accounts(2));
+        assert!(!contract.is_authorized(accounts(2)));
+
+        contract.set_balance_limits(Some(100), None);
+        assert_eq!(contract.get_balance_limits().max_trade_bps, Some(100));
+        contract.set_min_balance_reserve(NearToken::from_near(1));
+        assert_eq!(contract.get_min_balance_reserve(), NearToken::from_near(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can perform this action")]
+    fn test_manager_cannot_loosen_limits() {
+        let mut contract = manager_test_contract();
+        contract.set_balance_limits(Some(1_000), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can perform this action")]
+    fn test_manager_cannot_withdraw() {
+        let mut contract = manager_test_contract();
+        let mut context = get_context(accounts(3));
+        context
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .storage_usage(near_sdk::env::storage_usage());
+        testing_env!(context.build());
+        let _ = contract.withdraw_near(None);
+    }
+
+    /// A manager's contract with goal 0 bounded by slippage and a cooldown, an oracle set and
+    /// loosening changes delayed, so only tightening applies immediately
+    fn manager_goal_contract() -> (TradingAccountContract, U64) {
+        let mut contract = manager_test_contract();
+        testing_env!(context_as(accounts(1)).build());
+        let goal_id = create_test_goal(&mut contract, 100);
+        contract.set_goal_max_slippage(goal_id, Some(100));
+        contract.set_goal_cooldown(goal_id, Some(U64(HOUR_NS)));
+        contract.set_price_oracle(Some(
+            AccountId::try_from("priceoracle.near".to_string()).unwrap(),
+        ));
+        contract.set_policy_change_delay(U64(HOUR_NS));
+        testing_env!(context_as(accounts(3)).build());
+        (contract, goal_id)
+    }
+
+    #[test]
+    fn test_manager_cancels_goal() {
+        let (mut contract, goal_id) = manager_goal_contract();
+        contract.cancel_goal(goal_id);
+        assert!(contract.get_goal(goal_id).is_none());
+        assert!(contract.get_pending_policy_changes().is_empty());
+    }
+
+    #[test]
+    fn test_manager_adds_deny_rules() {
+        let (mut contract, _) = manager_goal_contract();
+        contract.add_policy_rule(
+            amount_rule(RuleOperator::Gt, "1000", RuleOutcome::Deny),
+            None,
+        );
+        assert_eq!(contract.get_policy_rules().len(), 1);
+        assert!(contract.get_pending_policy_changes().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can perform this action")]
+    fn test_manager_cannot_add_allow_rules() {
+        let (mut contract, _) = manager_goal_contract();
+        contract.add_policy_rule(
+            amount_rule(RuleOperator::Lte, "1000", RuleOutcome::Allow),
+            None,
+        );
+    }
+
+    #[test]
+    fn test_manager_tightens_goal_slippage_and_cooldown() {
+        let (mut contract, goal_id) = manager_goal_contract();
+        contract.set_goal_max_slippage(goal_id, Some(50));
+        contract.set_goal_cooldown(goal_id, Some(U64(2 * HOUR_NS)));
+
+        let goal = contract.get_goal(goal_id).unwrap();
+        assert_eq!(goal.max_slippage_bps, Some(50));
+        assert_eq!(goal.cooldown_ns, Some(U64(2 * HOUR_NS)));
+        assert!(contract.get_pending_policy_changes().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can perform this action")]
+    fn test_manager_cannot_loosen_goal_slippage() {
+        let (mut contract, goal_id) = manager_goal_contract();
+        contract.set_goal_max_slippage(goal_id, Some(200));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can perform this action")]
+    fn test_manager_cannot_shorten_goal_cooldown() {
+        let (mut contract, goal_id) = manager_goal_contract();
+        contract.set_goal_cooldown(goal_id, None);
+    }
+
+    #[test]
+    fn test_manager_clears_price_oracle() {
+        let (mut contract, _) = manager_goal_contract();
+        contract.set_price_oracle(None);
+        assert!(contract.get_price_oracle().is_none());
+        assert!(contract.get_pending_policy_changes().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can perform this action")]
+    fn test_manager_cannot_set_price_oracle() {
+        let (mut contract, _) = manager_goal_contract();
+        contract.set_price_oracle(Some(
+            AccountId::try_from("otheroracle.near".to_string()).unwrap(),
+        ));
+    }
+
+    #[test]
+    fn test_granting_manager_role_is_scheduled() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.set_policy_change_delay(U64(HOUR_NS));
+
+        contract.grant_role(accounts(3), Role::Manager);
+        contract.grant_role(accounts(4), Role::Viewer);
+        assert!(contract.get_roles(accounts(3)).is_empty());
+        assert_eq!(contract.get_roles(accounts(4)), vec![Role::Viewer]);
+        assert_eq!(contract.get_pending_policy_changes().len(), 1);
+    }
+
+    #[test]
+    fn test_authorization_requires_acceptance() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.add_authorized_user(accounts(2));
+        assert!(!contract.is_authorized(accounts(2)));
+        assert_eq!(contract.get_pending_authorizations(), vec![accounts(2)]);
+
+        testing_env!(context_as(accounts(2)).build());
+        contract.accept_authorization();
+        assert!(contract.is_authorized(accounts(2)));
+        assert!(contract.get_pending_authorizations().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "No authorization has been offered to charlie")]
+    fn test_accept_authorization_without_offer() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.add_authorized_user(accounts(3));
+        contract.remove_authorized_user(accounts(3));
+
+        testing_env!(context_as(accounts(2)).build());
+        contract.accept_authorization();
+    }
+
+    #[test]
+    fn test_renounce_authorization() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        authorize_agent(&mut contract, accounts(2));
+        contract.add_authorized_user(accounts(3));
+
+        testing_env!(context_as(accounts(2)).build());
+        contract.renounce_authorization();
+        assert!(!contract.is_authorized(accounts(2)));
+
+        // A pending offer can be declined the same way
+        testing_env!(context_as(accounts(3)).build());
+        contract.renounce_authorization();
+        assert!(contract.get_pending_authorizations().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "charlie is not authorized")]
+    fn test_renounce_authorization_unauthorized() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        testing_env!(context_as(accounts(2)).build());
+        contract.renounce_authorization();
+    }
+
+    /// Make a request the agent calling it gets rejected for
+    fn request_invalid_actions(contract: &mut TradingAccountContract) {
+        let result = contract.request_signature(
+            wrap_near(),
+            "not json".to_string(),
+            U64(1),
+            Base58CryptoHash::from([0u8; 32]),
+            "ed25519:11111111111111111111111111111111".to_string(),
+            "trading-account.near".to_string(),
+            None,
+            U64(0),
+        );
+        assert_rejected(result, "Failed to parse actions JSON");
+    }
+
+    fn suspended_agent_contract() -> TradingAccountContract {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        authorize_agent(&mut contract, accounts(2));
+        contract.set_circuit_breaker(2, U64(HOUR_NS));
+
+        testing_env!(context_as(accounts(2)).build());
+        request_invalid_actions(&mut contract);
+        assert!(contract.get_suspended_agents().is_empty());
+        request_invalid_actions(&mut contract);
+        contract
+    }
+
+    #[test]
+    fn test_circuit_breaker_suspends_agent() {
+        let mut contract = suspended_agent_contract();
+        assert_eq!(contract.get_suspended_agents(), vec![accounts(2)]);
+        assert_eq!(
+            contract
+                .get_agent_rejections(accounts(2))
+                .unwrap()
+                .rejections,
+            2
+        );
+        assert!(
+            near_sdk::test_utils::get_logs()
+                .iter()
+                .any(|log| log.starts_with("EVENT_JSON:") && log.contains("agent_suspended"))
+        );
+
+        testing_env!(context_as(accounts(1)).build());
+        contract.reinstate_agent(accounts(2));
+        assert!(contract.get_suspended_agents().is_empty());
+        assert!(contract.get_agent_rejections(accounts(2)).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "charlie is suspended after repeated rejected requests")]
+    fn test_suspended_agent_cannot_request_signatures() {
+        let mut contract = suspended_agent_contract();
+        let _ = contract.request_signature(
+            wrap_near(),
+            "[]".to_string(),
+            U64(1),
+            Base58CryptoHash::from([0u8; 32]),
+            "ed25519:11111111111111111111111111111111".to_string(),
+            "trading-account.near".to_string(),
+            None,
+            U64(0),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "charlie is suspended after repeated rejected requests")]
+    fn test_reauthorizing_agent_keeps_suspension() {
+        let mut contract = suspended_agent_contract();
+        testing_env!(context_as(accounts(1)).build());
+        contract.grant_role(accounts(3), Role::Manager);
+
+        // Only reinstate_agent lifts a suspension, removing and re-adding the agent does not
+        testing_env!(context_as(accounts(3)).build());
+        contract.remove_authorized_user(accounts(2));
+        authorize_agent(&mut contract, accounts(2));
+        assert_eq!(contract.get_suspended_agents(), vec![accounts(2)]);
+        assert!(contract.get_agent_rejections(accounts(2)).is_none());
+
+        testing_env!(context_as(accounts(2)).build());
+        let _ = contract.request_signature(
+            wrap_near(),
+            "[]".to_string(),
+            U64(1),
+            Base58CryptoHash::from([0u8; 32]),
+            "ed25519:11111111111111111111111111111111".to_string(),
+            "trading-account.near".to_string(),
+            None,
+            U64(0),
+        );
+    }
+
+    #[test]
+    fn test_circuit_breaker_window_resets_rejections() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        authorize_agent(&mut contract, accounts(2));
+        contract.set_circuit_breaker(2, U64(HOUR_NS));
+
+        testing_env!(context_as(accounts(2)).build());
+        request_invalid_actions(&mut contract);
+
+        let mut context = context_as(accounts(2));
+        context.block_timestamp(near_sdk::env::block_timestamp() + HOUR_NS);
+        testing_env!(context.build());
+        request_invalid_actions(&mut contract);
+        assert!(contract.get_suspended_agents().is_empty());
+        assert_eq!(
+            contract
+                .get_agent_rejections(accounts(2))
+                .unwrap()
+                .rejections,
+            1
+        );
+    }
+
+    fn agent_request_contract() -> (TradingAccountContract, u64) {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        authorize_agent(&mut contract, accounts(2));
+        contract.set_circuit_breaker(1, U64(HOUR_NS));
+
+        testing_env!(context_as(accounts(2)).build());
+        let request_id = contract.record_signature_request(
+            wrap_near(),
+            U64(1),
+            U128(0),
+            RequestReservation::default(),
+        );
+        (contract, request_id)
+    }
+
+    #[test]
+    fn test_balance_snapshot_rejection_counts_towards_circuit_breaker() {
+        let (mut contract, request_id) = agent_request_contract();
+
+        testing_env!(
+            get_context(accounts(1)).build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(b"\"499\"".to_vec())],
+        );
+        let _ = contract.on_balance_snapshot(
+            request_id,
+            test_pending_signature(vec![intents_usdt_spend(500)]),
+        );
+        assert_eq!(contract.get_suspended_agents(), vec![accounts(2)]);
+    }
+
+    #[test]
+    fn test_price_rejection_counts_towards_circuit_breaker() {
+        let (mut contract, request_id) = agent_request_contract();
+
+        testing_env!(context_as(accounts(1)).build());
+        let _ = contract.on_price_data(
+            Err(PromiseError::Failed),
+            request_id,
+            test_pending_signature(vec![]),
+        );
+        assert_eq!(contract.get_suspended_agents(), vec![accounts(2)]);
+    }
+
+    #[test]
+    fn test_mpc_failure_counts_towards_circuit_breaker() {
+        let (mut contract, request_id) = agent_request_contract();
+
+        testing_env!(context_as(accounts(1)).build());
+        contract.sign_request_callback(Err(PromiseError::Failed), request_id, String::new());
+        assert_eq!(contract.get_suspended_agents(), vec![accounts(2)]);
+    }
+
+    #[test]
+    fn test_owner_denial_does_not_count_towards_circuit_breaker() {
+        let (mut contract, request_id) = approval_test_contract();
+
+        testing_env!(context_as(accounts(1)).build());
+        contract.deny_signature_request(U64(request_id));
+        assert!(contract.get_agent_rejections(accounts(2)).is_none());
+    }
+
+    #[test]
+    fn test_loosening_circuit_breaker_is_scheduled() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.set_policy_change_delay(U64(HOUR_NS));
+
+        contract.set_circuit_breaker(10, U64(HOUR_NS));
+        assert_eq!(contract.get_circuit_breaker().max_rejections, 5);
+        assert_eq!(contract.get_pending_policy_changes().len(), 1);
+
+        contract.set_circuit_breaker(3, U64(2 * HOUR_NS));
+        assert_eq!(contract.get_circuit_breaker().max_rejections, 3);
+    }
+
+    /// 1970-01-05 was a Monday
+    const MONDAY_NS: u64 = 4 * 24 * HOUR_NS;
+
+    fn at_time(predecessor: AccountId, timestamp: u64) {
+        let mut context = context_as(predecessor);
+        context.block_timestamp(timestamp);
+        testing_env!(context.build());
+    }
+
+    fn monday_office_hours() -> Vec<TradingWindow> {
+        vec![TradingWindow {
+            weekday: Weekday::Monday,
+            start_minute: 9 * 60,
+            end_minute: 17 * 60,
+        }]
+    }
+
+    #[test]
+    fn test_agent_schedule_limits_trading_windows() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        authorize_agent(&mut contract, accounts(2));
+        contract.set_agent_schedule(accounts(2), Some(monday_office_hours()));
+        assert_eq!(
+            contract.get_agent_schedule(accounts(2)),
+            Some(monday_office_hours())
+        );
+
+        at_time(accounts(2), MONDAY_NS + 10 * HOUR_NS);
+        assert!(contract.check_trading_window(&accounts(2)).is_ok());
+        // Agents without a schedule may trade at any time
+        assert!(contract.check_trading_window(&accounts(3)).is_ok());
+
+        at_time(accounts(2), MONDAY_NS + 24 * HOUR_NS + 10 * HOUR_NS);
+        assert!(contract.check_trading_window(&accounts(2)).is_err());
+
+        at_time(
+            accounts(2),
+            MONDAY_NS + 17 * HOUR_NS + 30 * 60 * 1_000_000_000,
+        );
+        let result = contract.request_signature(
+            wrap_near(),
+            "[]".to_string(),
+            U64(1),
+            Base58CryptoHash::from([0u8; 32]),
+            "ed25519:11111111111111111111111111111111".to_string(),
+            "trading-account.near".to_string(),
+            None,
+            U64(0),
+        );
+        assert_rejected(
+            result,
+            "Trading window check failed: charlie may not trade on Monday at 17:30 UTC",
+        );
+    }
+
+    #[test]
+    fn test_widening_agent_schedule_is_scheduled() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.set_policy_change_delay(U64(HOUR_NS));
+        contract.set_agent_schedule(accounts(2), Some(monday_office_hours()));
+
+        // Narrowing applies immediately
+        let narrower = vec![TradingWindow {
+            weekday: Weekday::Monday,
+            start_minute: 10 * 60,
+            end_minute: 12 * 60,
+        }];
+        contract.set_agent_schedule(accounts(2), Some(narrower.clone()));
+        assert_eq!(
+            contract.get_agent_schedule(accounts(2)),
+            Some(narrower.clone())
+        );
+
+        contract.set_agent_schedule(accounts(2), Some(monday_office_hours()));
+        contract.set_agent_schedule(accounts(2), None);
+        assert_eq!(contract.get_agent_schedule(accounts(2)), Some(narrower));
+        assert_eq!(contract.get_pending_policy_changes().len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid trading window")]
+    fn test_agent_schedule_rejects_invalid_window() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.set_agent_schedule(
+            accounts(2),
+            Some(vec![TradingWindow {
+                weekday: Weekday::Friday,
+                start_minute: 20 * 60,
+                end_minute: 25 * 60,
+            }]),
+        );
+    }
+
+    #[test]
+    fn test_goal_cooldown() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        let goal_id = contract.create_goal(wrap_near(), U128(100), vec![usdt()], U64(u64::MAX), 5);
+        contract.set_goal_cooldown(goal_id, Some(U64(HOUR_NS)));
+
+        at_time(accounts(1), MONDAY_NS);
+        assert!(contract.spend_goal_budget(goal_id.0, &[], None).is_ok());
+        assert_eq!(
+            contract.get_goal(goal_id).unwrap().last_executed_at,
+            Some(U64(MONDAY_NS))
+        );
+
+        at_time(accounts(1), MONDAY_NS + HOUR_NS - 1);
+        let err = contract
+            .spend_goal_budget(goal_id.0, &[], None)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            format!("Goal 0 is cooling down until {}", MONDAY_NS + HOUR_NS)
+        );
+
+        at_time(accounts(1), MONDAY_NS + HOUR_NS);
+        assert!(contract.spend_goal_budget(goal_id.0, &[], None).is_ok());
+
+        // Shortening the cooldown is scheduled
+        contract.set_policy_change_delay(U64(HOUR_NS));
+        contract.set_goal_cooldown(goal_id, None);
+        assert_eq!(
+            contract.get_goal(goal_id).unwrap().cooldown_ns,
+            Some(U64(HOUR_NS))
+        );
+    }
+
+    #[test]
+    fn test_failed_request_restores_goal_cooldown() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        let goal_id = contract.create_goal(wrap_near(), U128(100), vec![usdt()], U64(u64::MAX), 5);
+        contract.set_goal_cooldown(goal_id, Some(U64(HOUR_NS)));
+
+        at_time(accounts(1), MONDAY_NS);
+        assert!(contract.spend_goal_budget(goal_id.0, &[], None).is_ok());
+
+        at_time(accounts(1), MONDAY_NS + 2 * HOUR_NS);
+        let previous = contract.goal_last_execution(goal_id.0);
+        assert!(contract.spend_goal_budget(goal_id.0, &[], None).is_ok());
+        let request_id = contract.record_signature_request(
+            wrap_near(),
+            U64(1),
+            U128(0),
+            RequestReservation {
+                previous_goal_execution: previous,
+                ..goal_reservation(goal_id, 0)
+            },
+        );
+        contract.sign_request_callback(Err(PromiseError::Failed), request_id, String::new());
+
+        // The failed request does not start a new cooldown, the goal can be executed right away
+        let goal = contract.get_goal(goal_id).unwrap();
+        assert_eq!(goal.last_executed_at, Some(U64(MONDAY_NS)));
+        assert_eq!(goal.executions, 1);
+        assert!(contract.spend_goal_budget(goal_id.0, &[], None).is_ok());
+    }
+
+    const WEEK_NS: u64 = 7 * 24 * HOUR_NS;
+
+    fn dca_test_contract() -> (TradingAccountContract, U64) {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        authorize_agent(&mut contract, accounts(2));
+        contract.add_allowed_token(wrap_near());
+        contract.add_allowed_pair(wrap_near(), usdt());
+        let schedule_id = contract.create_dca_schedule(
+            wrap_near(),
+            usdt(),
+            U128(100),
+            U64(WEEK_NS),
+            Some(U64(MONDAY_NS)),
+            None,
+        );
+        (contract, schedule_id)
+    }
+
+    fn dca_swap(spent_amount: u128) -> SwapQuote {
+        SwapQuote {
+            spent_token: wrap_near(),
+            spent_amount: U128(spent_amount),
+            received_token: usdt(),
+            received_amount: U128(250),
+            max_slippage_bps: 0,
+        }
+    }
+
+    fn request_dca(
+        contract: &mut TradingAccountContract,
+        schedule_id: U64,
+        message: String,
+    ) -> PromiseOrValue<Option<String>> {
+        let mut context = context_as(accounts(2));
+        context.prepaid_gas(near_sdk::Gas::from_tgas(300));
+        testing_env!(context.build());
+        contract.request_dca_signature(
+            schedule_id,
+            message,
+            near_sdk::json_types::Base64VecU8::from(vec![7u8; 32]),
+            "trading-account.near".to_string(),
+            None,
+        )
+    }
+
+    #[test]
+    fn test_dca_schedule_executes_once_per_interval() {
+        let (mut contract, schedule_id) = dca_test_contract();
+
+        at_time(accounts(2), MONDAY_NS + HOUR_NS);
+        assert!(
+            contract
+                .take_dca_execution(schedule_id.0, &dca_swap(100))
+                .is_ok()
+        );
+        let schedule = contract.get_dca_schedule(schedule_id).unwrap();
+        assert_eq!(schedule.executions, 1);
+        assert_eq!(schedule.last_executed_at, Some(U64(MONDAY_NS + HOUR_NS)));
+        assert_eq!(schedule.next_execution_at, U64(MONDAY_NS + WEEK_NS));
+
+        let err = contract
+            .take_dca_execution(schedule_id.0, &dca_swap(100))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            format!("DCA schedule 0 is not due until {}", MONDAY_NS + WEEK_NS)
+        );
+
+        // A late execution does not shift the schedule
+        at_time(accounts(2), MONDAY_NS + WEEK_NS + 5 * HOUR_NS);
+        assert!(
+            contract
+                .take_dca_execution(schedule_id.0, &dca_swap(100))
+                .is_ok()
+        );
+        assert_eq!(
+            contract
+                .get_dca_schedule(schedule_id)
+                .unwrap()
+                .next_execution_at,
+            U64(MONDAY_NS + 2 * WEEK_NS)
+        );
+    }
+
+    #[test]
+    fn test_dca_schedule_requires_matching_swap() {
+        let (mut contract, schedule_id) = dca_test_contract();
+        at_time(accounts(2), MONDAY_NS);
+
+        let err = contract
+            .take_dca_execution(schedule_id.0, &dca_swap(101))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "DCA schedule 0 spends exactly 100 of wrap.near, not 101"
+        );
+
+        let mut swap = dca_swap(100);
+        swap.received_token = AccountId::try_from("meme.near".to_string()).unwrap();
+        let err = contract
+            .take_dca_execution(schedule_id.0, &swap)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "DCA schedule 0 swaps wrap.near for usdt.tether-token.near, not wrap.near for meme.near"
+        );
+        assert_eq!(
+            contract.get_dca_schedule(schedule_id).unwrap().executions,
+            0
+        );
+    }
+
+    #[test]
+    fn test_failed_dca_execution_releases_interval() {
+        let (mut contract, schedule_id) = dca_test_contract();
+        at_time(accounts(2), MONDAY_NS + HOUR_NS);
+        assert!(
+            contract
+                .take_dca_execution(schedule_id.0, &dca_swap(100))
+                .is_ok()
+        );
+
+        let request_id = contract.record_signature_request(
+            wrap_near(),
+            U64(0),
+            U128(0),
+            RequestReservation {
+                dca_schedule_id: Some(schedule_id),
+                ..Default::default()
+            },
+        );
+
+        contract.update_signature_request_status(
+            request_id,
+            SignatureRequestStatus::Failed {
+                reason: "MPC signer unavailable".to_string(),
+            },
+        );
+        let schedule = contract.get_dca_schedule(schedule_id).unwrap();
+        assert_eq!(schedule.executions, 0);
+        assert_eq!(schedule.next_execution_at, U64(MONDAY_NS));
+    }
+
+    #[test]
+    fn test_request_dca_signature_before_start() {
+        let (mut contract, schedule_id) = dca_test_contract();
+        let result = request_dca(&mut contract, schedule_id, token_diff_message("100", "250"));
+        assert_rejected(
+            result,
+            "DCA schedule check failed: DCA schedule 0 is not due until",
+        );
+        assert_eq!(
+            contract
+                .get_agent_rejections(accounts(2))
+                .unwrap()
+                .rejections,
+            1
+        );
+    }
+
+    #[test]
+    fn test_request_dca_signature_requires_allowed_pair() {
+        let (mut contract, schedule_id) = dca_test_contract();
+        testing_env!(get_context(accounts(1)).build());
+        contract.remove_allowed_pair(wrap_near(), usdt());
+        at_time(accounts(2), MONDAY_NS);
+
+        let result = request_dca(&mut contract, schedule_id, token_diff_message("100", "250"));
+        assert_rejected(
+            result,
+            "Swap from wrap.near to usdt.tether-token.near is not allowed",
+        );
+        assert_eq!(
+            contract.get_dca_schedule(schedule_id).unwrap().executions,
+            0
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "GasExceeded")]
+    fn test_request_dca_signature_passes_validation() {
+        let (mut contract, schedule_id) = dca_test_contract();
+        at_time(accounts(2), MONDAY_NS);
+        // As with other requests, validation succeeds and the mocked MPC call runs out of gas
+        let _ = request_dca(&mut contract, schedule_id, token_diff_message("100", "250"));
+    }
+
+    #[test]
+    fn test_creating_dca_schedule_is_scheduled() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = TradingAccountContract::new(
+            accounts(1),
+            AccountId::try_from("v1.signer-prod.testnet".to_string()).unwrap(),
+        );
+        contract.set_policy_change_delay(U64(HOUR_NS));
+        let schedule_id =
+            contract.create_dca_schedule(wrap_near(), usdt(), U128(100), U64(WEEK_NS), None, None);
+        assert!(contract.get_dca_schedule(schedule_id).is_none());
+        assert_eq!(contract.get_pending_policy_changes().len(), 1);
+    }
 }
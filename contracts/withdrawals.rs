@@ -0,0 +1,144 @@
+use near_sdk::json_types::U128;
+use near_sdk::{AccountId, Gas, NearToken, Promise, env, ext_contract, near};
+
+use crate::events::TradingAccountEvent;
+use crate::{NEAR_INTENTS_ADDRESS, TradingAccountContract, TradingAccountContractExt};
+
+pub(crate) const FT_TRANSFER_GAS: Gas = Gas::from_tgas(10); // Gas for a NEP-141 ft_transfer
+pub(crate) const INTENTS_WITHDRAW_GAS: Gas = Gas::from_tgas(50); // Gas for intents.near, which transfers the tokens out in turn
+const ONE_YOCTO: NearToken = NearToken::from_yoctonear(1);
+
+// The traits themselves are unused, only the generated `ext_*` bindings are
+#[allow(dead_code)]
+#[ext_contract(ext_ft)]
+pub trait FungibleToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+    fn ft_balance_of(&self, account_id: AccountId) -> U128;
+}
+
+#[allow(dead_code)]
+#[ext_contract(ext_intents)]
+pub trait Intents {
+    fn ft_withdraw(
+        &mut self,
+        token: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    );
+    fn mt_withdraw(
+        &mut self,
+        token: AccountId,
+        receiver_id: AccountId,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+        memo: Option<String>,
+    );
+    fn mt_batch_balance_of(&self, account_id: AccountId, token_ids: Vec<String>) -> Vec<U128>;
+}
+
+#[near]
+impl TradingAccountContract {
+    /// Send NEAR back to the owner. Withdraws everything above the storage cost and the minimum
+    /// balance reserve when no amount is given.
+    #[payable]
+    pub fn withdraw_near(&mut self, amount: Option<NearToken>) -> Promise {
+        self.assert_owner_with_one_yocto();
+
+        let available = self.available_near_balance();
+        let amount = amount.unwrap_or(available);
+        assert!(!amount.is_zero(), "Nothing to withdraw");
+        assert!(
+            amount <= available,
+            "Cannot withdraw {}, only {} is available above the minimum balance reserve of {}",
+            amount,
+            available,
+            self.min_balance_reserve
+        );
+
+        self.emit_withdrawal("near".to_string(), U128(amount.as_yoctonear()));
+        Promise::new(self.owner_id.clone()).transfer(amount)
+    }
+
+    /// Send NEP-141 tokens held by the trading account back to the owner
+    #[payable]
+    pub fn withdraw_ft(&mut self, token_id: AccountId, amount: U128) -> Promise {
+        self.assert_owner_with_one_yocto();
+        assert!(amount.0 > 0, "amount must be greater than zero");
+
+        self.emit_withdrawal(token_id.to_string(), amount);
+        ext_ft::ext(token_id)
+            .with_attached_deposit(ONE_YOCTO)
+            .with_static_gas(FT_TRANSFER_GAS)
+            .ft_transfer(self.owner_id.clone(), amount, None)
+    }
+
+    /// Withdraw a NEP-141 balance the trading account holds on intents.near to the owner
+    #[payable]
+    pub fn withdraw_ft_from_intents(&mut self, token: AccountId, amount: U128) -> Promise {
+        self.assert_owner_with_one_yocto();
+        assert!(amount.0 > 0, "amount must be greater than zero");
+
+        self.emit_withdrawal(
+            format!("{}:{}", NEAR_INTENTS_ADDRESS.as_str(), token),
+            amount,
+        );
+        ext_intents::ext(NEAR_INTENTS_ADDRESS.clone())
+            .with_attached_deposit(ONE_YOCTO)
+            .with_static_gas(INTENTS_WITHDRAW_GAS)
+            .ft_withdraw(token, self.owner_id.clone(), amount, None)
+    }
+
+    /// Withdraw NEP-245 balances the trading account holds on intents.near to the owner
+    #[payable]
+    pub fn withdraw_mt_from_intents(
+        &mut self,
+        token: AccountId,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+    ) -> Promise {
+        self.assert_owner_with_one_yocto();
+        assert!(!token_ids.is_empty(), "At least one token id is required");
+        assert_eq!(
+            token_ids.len(),
+            amounts.len(),
+            "token_ids and amounts must have the same length"
+        );
+        assert!(
+            amounts.iter().all(|amount| amount.0 > 0),
+            "amounts must be greater than zero"
+        );
+
+        for (token_id, amount) in token_ids.iter().zip(&amounts) {
+            self.emit_withdrawal(
+                format!("{}:{}:{}", NEAR_INTENTS_ADDRESS.as_str(), token, token_id),
+                *amount,
+            );
+        }
+        ext_intents::ext(NEAR_INTENTS_ADDRESS.clone())
+            .with_attached_deposit(ONE_YOCTO)
+            .with_static_gas(INTENTS_WITHDRAW_GAS)
+            .mt_withdraw(token, self.owner_id.clone(), token_ids, amounts, None)
+    }
+}
+
+impl TradingAccountContract {
+    /// Withdrawals move funds, so they must be confirmed with a full access key
+    pub(crate) fn assert_owner_with_one_yocto(&self) {
+        self.assert_owner();
+        assert_eq!(
+            env::attached_deposit(),
+            ONE_YOCTO,
+            "This method requires an attached deposit of exactly 1 yoctoNear"
+        );
+    }
+
+    fn emit_withdrawal(&self, asset: String, amount: U128) {
+        TradingAccountEvent::OwnerWithdrawal {
+            receiver_id: self.owner_id.clone(),
+            asset,
+            amount,
+        }
+        .emit();
+    }
+}